@@ -5,13 +5,16 @@ use std::{
     str::FromStr,
 };
 
-use async_fs as fs;
-use smol;
 use smol::process::Command;
-use tempfile::{NamedTempFile, TempDir};
+use tempfile::NamedTempFile;
 
 use crate::{
-    Result, actions::save::save_panes_content, error::Error, management::archive::v1, tmux,
+    Result,
+    actions::save::{SaveOptions, write_backup},
+    error::Error,
+    management::archive,
+    redaction::Redactions,
+    tmux,
 };
 
 /// Client information used to create a headless autosave and report it to Tmux.
@@ -63,72 +66,25 @@ pub fn display_message(context: &AutosaveContext, message: &str) {
 /// Save the tmux sessions, windows and panes into the rolling autosave archive.
 ///
 /// The archive is first fully created in a temporary file in `backup_dirpath`, then atomically
-/// replaced at `autosave.tar.zst`.
+/// replaced at `autosave.tar.zst`, which keeps this name when encrypted. As with `save`, this
+/// returns the number of redacted secrets along with the overview.
 pub async fn autosave<P: AsRef<Path>>(
     backup_dirpath: P,
-    num_lines_to_drop: usize,
+    options: &SaveOptions,
     context: AutosaveContext,
-) -> Result<(PathBuf, v1::Overview)> {
+) -> Result<(PathBuf, archive::Overview, Redactions)> {
     let backup_dirpath = backup_dirpath.as_ref();
-    let temp_dir = TempDir::new()?;
 
-    let metadata_task: smol::Task<Result<(PathBuf, PathBuf, u16, u16)>> = {
-        let temp_dirpath = temp_dir.path().to_path_buf();
-        let client = context.client;
+    let mut metadata = archive::Metadata::new_with_client(context.client).await?;
+    options.retain_selected(&mut metadata)?;
 
-        smol::spawn(async move {
-            let temp_version_filepath = temp_dirpath.join(v1::VERSION_FILENAME);
-            fs::write(&temp_version_filepath, v1::FORMAT_VERSION).await?;
-
-            let metadata = v1::Metadata::new_with_client(client).await?;
-            let json = serde_json::to_string(&metadata)?;
-
-            let temp_metadata_filepath = temp_dirpath.join(v1::METADATA_FILENAME);
-            fs::write(temp_metadata_filepath.as_path(), json).await?;
-
-            Ok((
-                temp_version_filepath,
-                temp_metadata_filepath,
-                metadata.sessions.len() as u16,
-                metadata.windows.len() as u16,
-            ))
-        })
-    };
-
-    let (temp_panes_content_dir, num_panes) = {
-        let temp_panes_content_dir = temp_dir.path().join(v1::PANES_DIR_NAME);
-        fs::create_dir_all(&temp_panes_content_dir).await?;
-
-        let panes = tmux::pane::available_panes().await?;
-        let num_panes = panes.len() as u16;
-        save_panes_content(panes, &temp_panes_content_dir, num_lines_to_drop).await?;
-
-        (temp_panes_content_dir, num_panes)
-    };
-    let (temp_version_filepath, temp_metadata_filepath, num_sessions, num_windows) =
-        metadata_task.await?;
-
-    let autosave_filepath = v1::autosave_filepath(backup_dirpath);
+    let autosave_filepath = archive::autosave_filepath(backup_dirpath);
     let temp_archive = NamedTempFile::new_in(backup_dirpath)?;
     let archive = temp_archive.reopen()?;
-    v1::create_from_file(
-        archive,
-        &temp_version_filepath,
-        &temp_metadata_filepath,
-        &temp_panes_content_dir,
-    )?;
+    let redactions = write_backup(archive, &mut metadata, backup_dirpath, options).await?;
     persist_autosave(temp_archive, &autosave_filepath)?;
 
-    temp_dir.close()?;
-
-    let overview = v1::Overview {
-        version: v1::FORMAT_VERSION.to_string(),
-        num_sessions,
-        num_windows,
-        num_panes,
-    };
-
-    Ok((autosave_filepath, overview))
+    Ok((autosave_filepath, metadata.overview(), redactions))
 }
 
 /// Atomically replace the autosave archive with a completed temporary archive.
@@ -207,6 +163,7 @@ fn tmux_output(output: std::process::Output, failure_message: &str) -> Result<St
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     #[test]
     fn selects_most_recent_client() {
@@ -236,7 +193,7 @@ mod tests {
         use std::io::Write;
 
         let dir = TempDir::new().unwrap();
-        let autosave_filepath = v1::autosave_filepath(dir.path());
+        let autosave_filepath = archive::autosave_filepath(dir.path());
         std::fs::write(&autosave_filepath, "previous archive").unwrap();
 
         let mut temp_archive = NamedTempFile::new_in(dir.path()).unwrap();
@@ -0,0 +1,738 @@
+//! Compare two backups, or a backup and the live Tmux sessions.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+use similar::TextDiff;
+use tempfile::TempDir;
+
+use crate::{
+    Result,
+    actions::save::{SaveOptions, store_panes_content, strip_escapes},
+    management::archive::{Metadata, store::Store, v2},
+    tmux::{pane::Pane, pane_id::PaneId, window::Window},
+};
+
+/// Number of unchanged lines around the changes of a pane content.
+const CONTEXT_LINES: usize = 3;
+
+/// One side of a comparison.
+#[derive(Debug, Clone, Copy)]
+pub enum Side<'a> {
+    /// The backup file at this path.
+    Backup(&'a Path),
+
+    /// The live Tmux sessions, captured as a save with these options would.
+    Live(&'a SaveOptions),
+}
+
+/// Kind of the compared items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// A session.
+    Session,
+    /// A window, named `session:window`.
+    Window,
+    /// A pane, named `session:window.index`.
+    Pane,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Session => write!(f, "session"),
+            Self::Window => write!(f, "window"),
+            Self::Pane => write!(f, "pane"),
+        }
+    }
+}
+
+/// One difference between the old and the new side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum Change {
+    /// Item only on the new side.
+    Added {
+        /// Kind of the item.
+        kind: Kind,
+        /// Name of the item.
+        name: String,
+    },
+
+    /// Item only on the old side.
+    Removed {
+        /// Kind of the item.
+        kind: Kind,
+        /// Name of the item.
+        name: String,
+    },
+
+    /// Item whose name changed.
+    Renamed {
+        /// Kind of the item.
+        kind: Kind,
+        /// Old name of the item.
+        name: String,
+        /// New name of the item.
+        new_name: String,
+    },
+
+    /// Window whose panes were split, resized or moved.
+    Layout {
+        /// Name of the window.
+        name: String,
+        /// Old layout, without the pane ids.
+        old: String,
+        /// New layout, without the pane ids.
+        new: String,
+    },
+
+    /// Pane running another program.
+    Command {
+        /// Name of the pane.
+        name: String,
+        /// Old program.
+        old: String,
+        /// New program.
+        new: String,
+    },
+
+    /// Pane in another directory.
+    Directory {
+        /// Name of the pane.
+        name: String,
+        /// Old directory.
+        old: PathBuf,
+        /// New directory.
+        new: PathBuf,
+    },
+
+    /// Pane whose content changed.
+    Content {
+        /// Name of the pane.
+        name: String,
+        /// Unified diff of the content, without escape sequences.
+        diff: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { kind, name } => write!(f, "+ {kind} `{name}`"),
+            Self::Removed { kind, name } => write!(f, "- {kind} `{name}`"),
+            Self::Renamed {
+                kind,
+                name,
+                new_name,
+            } => write!(f, "~ {kind} `{name}` renamed to `{new_name}`"),
+            Self::Layout { name, old, new } => {
+                write!(f, "~ window `{name}` layout `{old}` → `{new}`")
+            }
+            Self::Command { name, old, new } => {
+                write!(f, "~ pane `{name}` command `{old}` → `{new}`")
+            }
+            Self::Directory { name, old, new } => write!(
+                f,
+                "~ pane `{name}` directory `{}` → `{}`",
+                old.to_string_lossy(),
+                new.to_string_lossy()
+            ),
+            Self::Content { name, diff } => {
+                write!(f, "~ pane `{name}` content:\n{}", diff.trim_end())
+            }
+        }
+    }
+}
+
+/// Differences between the old and the new side, in the order of their sessions and windows.
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    /// All differences.
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    /// Return `true` if both sides are the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the `old` and `new` sides, and the content of their panes if `content` is `true`.
+///
+/// Sessions and windows are paired by name, or else by their Tmux id, which detects renames in
+/// the same Tmux server. Panes are paired by their index in the window. Encrypted backups are
+/// decrypted with the identity file at `identity_filepath`.
+pub async fn diff(
+    old: Side<'_>,
+    new: Side<'_>,
+    content: bool,
+    identity_filepath: Option<&Path>,
+) -> Result<Diff> {
+    let old = Snapshot::read(old, content, identity_filepath).await?;
+    let new = Snapshot::read(new, content, identity_filepath).await?;
+    Ok(compare(&old, &new))
+}
+
+/// Sessions, windows and panes of one side.
+struct Snapshot {
+    metadata: Metadata,
+
+    /// Content of each pane without escape sequences, if requested.
+    contents: Option<HashMap<PaneId, String>>,
+}
+
+impl Snapshot {
+    async fn read(side: Side<'_>, content: bool, identity_filepath: Option<&Path>) -> Result<Self> {
+        match side {
+            Side::Backup(backup_filepath) => {
+                Self::read_backup(backup_filepath, content, identity_filepath).await
+            }
+            Side::Live(options) => Self::capture_live(options, content).await,
+        }
+    }
+
+    /// Read the backup at `backup_filepath`, and its panes content if `content` is `true`.
+    async fn read_backup(
+        backup_filepath: &Path,
+        content: bool,
+        identity_filepath: Option<&Path>,
+    ) -> Result<Self> {
+        let metadata = Metadata::read_file(backup_filepath, identity_filepath).await?;
+        if !content {
+            return Ok(Self {
+                metadata,
+                contents: None,
+            });
+        }
+
+        let temp_dir = TempDir::new()?;
+        let panes_content_dir = v2::extract_panes_content(
+            backup_filepath,
+            &metadata,
+            temp_dir.path(),
+            identity_filepath,
+        )
+        .await?;
+        let mut contents = HashMap::new();
+        for pane in &metadata.panes {
+            let filepath = panes_content_dir.join(format!("pane-{}.txt", pane.id));
+            // A pane created while the backup was written may have no content.
+            let bytes = async_fs::read(&filepath).await.unwrap_or_default();
+            contents.insert(pane.id.clone(), plain_text(&bytes));
+        }
+
+        Ok(Self {
+            metadata,
+            contents: Some(contents),
+        })
+    }
+
+    /// Query Tmux as `save` would with `options`, and capture the panes content if `content` is
+    /// `true`.
+    async fn capture_live(options: &SaveOptions, content: bool) -> Result<Self> {
+        let mut metadata = Metadata::new().await?;
+        options.retain_selected(&mut metadata)?;
+        if !content {
+            return Ok(Self {
+                metadata,
+                contents: None,
+            });
+        }
+
+        let temp_dir = TempDir::new()?;
+        let store = Store::new(temp_dir.path());
+        let (pane_metadata, _) =
+            store_panes_content(metadata.panes.clone(), &store, options).await?;
+        let mut contents = HashMap::new();
+        for pane_metadata in pane_metadata {
+            let bytes = store.read(&pane_metadata.digest).await?;
+            contents.insert(pane_metadata.pane_id, plain_text(&bytes));
+        }
+
+        Ok(Self {
+            metadata,
+            contents: Some(contents),
+        })
+    }
+
+    /// Return the content of `pane`, if the panes content was read.
+    fn content(&self, pane: &Pane) -> Option<&str> {
+        let contents = self.contents.as_ref()?;
+        Some(contents.get(&pane.id).map_or("", String::as_str))
+    }
+}
+
+/// Return the text of a pane content, without its escape sequences.
+fn plain_text(bytes: &[u8]) -> String {
+    strip_escapes(&String::from_utf8_lossy(bytes)).into_owned()
+}
+
+/// Return the differences between the `old` and `new` snapshots.
+fn compare(old: &Snapshot, new: &Snapshot) -> Diff {
+    let mut changes = vec![];
+
+    let sessions = pair(
+        &old.metadata.sessions,
+        &new.metadata.sessions,
+        |session| session.name.clone(),
+        |session| session.name.clone(),
+        |session| session.id.as_str(),
+    );
+    // Windows of a renamed session keep their name.
+    let mut new_session_names = HashMap::new();
+    for (old_session, new_session) in &sessions.pairs {
+        new_session_names.insert(old_session.name.as_str(), new_session.name.as_str());
+        if old_session.name != new_session.name {
+            changes.push(Change::Renamed {
+                kind: Kind::Session,
+                name: old_session.name.clone(),
+                new_name: new_session.name.clone(),
+            });
+        }
+    }
+    changes.extend(sessions.removed.iter().map(|session| Change::Removed {
+        kind: Kind::Session,
+        name: session.name.clone(),
+    }));
+    changes.extend(sessions.added.iter().map(|session| Change::Added {
+        kind: Kind::Session,
+        name: session.name.clone(),
+    }));
+
+    let old_window_name = |window: &Window| {
+        let session_name = window_session_name(window);
+        let session_name = new_session_names
+            .get(session_name)
+            .copied()
+            .unwrap_or(session_name);
+        format!("{session_name}:{}", window.name)
+    };
+    let windows = pair(
+        &old.metadata.windows,
+        &new.metadata.windows,
+        old_window_name,
+        window_name,
+        |window| window.id.as_str(),
+    );
+    for (old_window, new_window) in &windows.pairs {
+        if old_window_name(old_window) != window_name(new_window) {
+            changes.push(Change::Renamed {
+                kind: Kind::Window,
+                name: window_name(old_window),
+                new_name: window_name(new_window),
+            });
+        }
+        compare_windows(old, old_window, new, new_window, &mut changes);
+    }
+    changes.extend(windows.removed.iter().map(|window| Change::Removed {
+        kind: Kind::Window,
+        name: window_name(window),
+    }));
+    changes.extend(windows.added.iter().map(|window| Change::Added {
+        kind: Kind::Window,
+        name: window_name(window),
+    }));
+
+    Diff { changes }
+}
+
+/// Push the differences between the layouts of the `old_window` and the `new_window`, and their
+/// panes.
+fn compare_windows(
+    old: &Snapshot,
+    old_window: &Window,
+    new: &Snapshot,
+    new_window: &Window,
+    changes: &mut Vec<Change>,
+) {
+    let name = window_name(new_window);
+    let (old_layout, new_layout) = (
+        layout_shape(&old_window.layout),
+        layout_shape(&new_window.layout),
+    );
+    if old_layout != new_layout {
+        changes.push(Change::Layout {
+            name: name.clone(),
+            old: old_layout,
+            new: new_layout,
+        });
+    }
+
+    let old_panes: Vec<Pane> = window_panes(&old.metadata, old_window);
+    let new_panes: Vec<Pane> = window_panes(&new.metadata, new_window);
+    let panes = pair(
+        &old_panes,
+        &new_panes,
+        |pane| pane.index.to_string(),
+        |pane| pane.index.to_string(),
+        |pane| pane.id.as_str(),
+    );
+    for (old_pane, new_pane) in &panes.pairs {
+        let pane_name = format!("{name}.{}", new_pane.index);
+        if old_pane.command != new_pane.command {
+            changes.push(Change::Command {
+                name: pane_name.clone(),
+                old: old_pane.command.clone(),
+                new: new_pane.command.clone(),
+            });
+        }
+        if old_pane.dirpath != new_pane.dirpath {
+            changes.push(Change::Directory {
+                name: pane_name.clone(),
+                old: old_pane.dirpath.clone(),
+                new: new_pane.dirpath.clone(),
+            });
+        }
+        if let (Some(old_content), Some(new_content)) =
+            (old.content(old_pane), new.content(new_pane))
+            && old_content != new_content
+        {
+            changes.push(Change::Content {
+                name: pane_name,
+                diff: unified_diff(old_content, new_content),
+            });
+        }
+    }
+    changes.extend(panes.removed.iter().map(|pane| Change::Removed {
+        kind: Kind::Pane,
+        name: format!("{}.{}", window_name(old_window), pane.index),
+    }));
+    changes.extend(panes.added.iter().map(|pane| Change::Added {
+        kind: Kind::Pane,
+        name: format!("{name}.{}", pane.index),
+    }));
+}
+
+/// Return the name of the first session of `window`.
+fn window_session_name(window: &Window) -> &str {
+    window.sessions.first().map_or("", String::as_str)
+}
+
+/// Return the name of `window`, such as `work:editor`.
+fn window_name(window: &Window) -> String {
+    format!("{}:{}", window_session_name(window), window.name)
+}
+
+/// Return the panes of `window`, or none if its layout is invalid.
+fn window_panes(metadata: &Metadata, window: &Window) -> Vec<Pane> {
+    let Ok(layout) = crate::tmux::layout::parse_window_layout(&window.layout) else {
+        return vec![];
+    };
+    let pane_ids: Vec<PaneId> = layout.pane_ids().iter().map(PaneId::from).collect();
+    metadata
+        .panes
+        .iter()
+        .filter(|pane| pane_ids.contains(&pane.id))
+        .cloned()
+        .collect()
+}
+
+/// Return the `layout` without its checksum and pane ids, which differ between Tmux servers.
+fn layout_shape(layout: &str) -> String {
+    static CHECKSUM_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9a-f]+,").unwrap());
+    static PANE_ID_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(\d+x\d+,\d+,\d+),\d+").unwrap());
+
+    let layout = CHECKSUM_RE.replace(layout, "");
+    PANE_ID_RE.replace_all(&layout, "$1").into_owned()
+}
+
+/// Items of both sides, paired when they match.
+struct Pairing<'a, T> {
+    pairs: Vec<(&'a T, &'a T)>,
+    removed: Vec<&'a T>,
+    added: Vec<&'a T>,
+}
+
+/// Pair the `old` and `new` items with the same key, then the remaining ones with the same id.
+///
+/// Keys are computed by `old_key` and `new_key`, so that the old items can be named after the new
+/// names of their parents.
+fn pair<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    old_key: impl Fn(&T) -> String,
+    new_key: impl Fn(&T) -> String,
+    id: impl Fn(&T) -> &str,
+) -> Pairing<'a, T> {
+    let mut unpaired_new: Vec<Option<&T>> = new.iter().map(Some).collect();
+    let mut pairs: Vec<(usize, &T, &T)> = vec![];
+    let mut removed = vec![];
+
+    for (index, old_item) in old.iter().enumerate() {
+        let key = old_key(old_item);
+        let position = unpaired_new
+            .iter()
+            .position(|item| item.is_some_and(|item| new_key(item) == key));
+        match position {
+            Some(position) => pairs.push((index, old_item, unpaired_new[position].take().unwrap())),
+            None => removed.push((index, old_item)),
+        }
+    }
+
+    let mut still_removed = vec![];
+    for (index, old_item) in removed {
+        let position = unpaired_new
+            .iter()
+            .position(|item| item.is_some_and(|item| id(item) == id(old_item)));
+        match position {
+            Some(position) => pairs.push((index, old_item, unpaired_new[position].take().unwrap())),
+            None => still_removed.push(old_item),
+        }
+    }
+    pairs.sort_by_key(|(index, ..)| *index);
+
+    Pairing {
+        pairs: pairs.into_iter().map(|(_, old, new)| (old, new)).collect(),
+        removed: still_removed,
+        added: unpaired_new.into_iter().flatten().collect(),
+    }
+}
+
+/// Return the unified diff turning `old` into `new`, with a few lines of context around changes.
+fn unified_diff(old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::tmux::{client::Client, session::Session};
+
+    fn snapshot(sessions: &[&str], windows: &[&str], panes: &[&str]) -> Snapshot {
+        Snapshot {
+            metadata: Metadata {
+                version: crate::management::archive::FORMAT_VERSION.to_string(),
+                client: Client::from_str("'work':''").unwrap(),
+                sessions: sessions
+                    .iter()
+                    .map(|s| Session::from_str(s).unwrap())
+                    .collect(),
+                windows: windows
+                    .iter()
+                    .map(|w| Window::from_str(w).unwrap())
+                    .collect(),
+                panes: panes.iter().map(|p| Pane::from_str(p).unwrap()).collect(),
+                pane_metadata: vec![],
+                escapes: true,
+                label: None,
+                note: None,
+            },
+            contents: None,
+        }
+    }
+
+    fn old() -> Snapshot {
+        snapshot(
+            &["$1:'work':/home/user", "$2:'misc':/tmp"],
+            &[
+                "@1:0:true:035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}:'editor':'work'",
+                "@2:0:true:64f1,334x85,0,0,3:'scratch':'misc'",
+            ],
+            &[
+                "%1:0:true:'host':'nvim':/home/user/src",
+                "%2:1:false:'host':'zsh':/home/user",
+                "%3:0:true:'host':'zsh':/tmp",
+            ],
+        )
+    }
+
+    fn changes(old: &Snapshot, new: &Snapshot) -> Vec<String> {
+        compare(old, new)
+            .changes
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    mod structure {
+        use super::*;
+
+        #[test]
+        fn identical_snapshots_have_no_changes() {
+            assert!(compare(&old(), &old()).is_empty());
+        }
+
+        #[test]
+        fn restored_sessions_with_other_ids_are_the_same() {
+            let new = snapshot(
+                &["$7:'work':/home/user", "$8:'misc':/tmp"],
+                &[
+                    "@7:0:true:a1b2,334x85,0,0{167x85,0,0,11,166x85,168,0,12}:'editor':'work'",
+                    "@8:0:true:c3d4,334x85,0,0,13:'scratch':'misc'",
+                ],
+                &[
+                    "%11:0:true:'host':'nvim':/home/user/src",
+                    "%12:1:false:'host':'zsh':/home/user",
+                    "%13:0:true:'host':'zsh':/tmp",
+                ],
+            );
+
+            assert!(compare(&old(), &new).is_empty());
+        }
+
+        #[test]
+        fn reports_renamed_added_and_removed_sessions() {
+            let new = snapshot(
+                &["$1:'main':/home/user", "$3:'logs':/var/log"],
+                &[
+                    "@1:0:true:035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}:'editor':'main'",
+                    "@3:0:true:64f2,334x85,0,0,4:'tail':'logs'",
+                ],
+                &[
+                    "%1:0:true:'host':'nvim':/home/user/src",
+                    "%2:1:false:'host':'zsh':/home/user",
+                    "%4:0:true:'host':'tail':/var/log",
+                ],
+            );
+
+            assert_eq!(
+                changes(&old(), &new),
+                [
+                    "~ session `work` renamed to `main`",
+                    "- session `misc`",
+                    "+ session `logs`",
+                    "- window `misc:scratch`",
+                    "+ window `logs:tail`",
+                ]
+            );
+        }
+
+        #[test]
+        fn reports_renamed_windows() {
+            let mut new = old();
+            new.metadata.windows[1].name = "notes".to_string();
+
+            assert_eq!(
+                changes(&old(), &new),
+                ["~ window `misc:scratch` renamed to `misc:notes`"]
+            );
+        }
+
+        #[test]
+        fn reports_layouts_and_panes() {
+            let new = snapshot(
+                &["$1:'work':/home/user", "$2:'misc':/tmp"],
+                &[
+                    "@1:0:true:64f0,334x85,0,0,1:'editor':'work'",
+                    "@2:0:true:035e,334x85,0,0[334x42,0,0,3,334x42,0,43,5]:'scratch':'misc'",
+                ],
+                &[
+                    "%1:0:true:'host':'hx':/home/user/src",
+                    "%3:0:true:'host':'zsh':/var/tmp",
+                    "%5:1:false:'host':'zsh':/tmp",
+                ],
+            );
+
+            assert_eq!(
+                changes(&old(), &new),
+                [
+                    "~ window `work:editor` layout `334x85,0,0{167x85,0,0,166x85,168,0}` → `334x85,0,0`",
+                    "~ pane `work:editor.0` command `nvim` → `hx`",
+                    "- pane `work:editor.1`",
+                    "~ window `misc:scratch` layout `334x85,0,0` → `334x85,0,0[334x42,0,0,334x42,0,43]`",
+                    "~ pane `misc:scratch.0` directory `/tmp` → `/var/tmp`",
+                    "+ pane `misc:scratch.1`",
+                ]
+            );
+        }
+
+        #[test]
+        fn reports_pane_content_when_read() {
+            let (mut before, mut after) = (old(), old());
+            for snapshot in [&mut before, &mut after] {
+                let contents = snapshot
+                    .metadata
+                    .panes
+                    .iter()
+                    .map(|pane| (pane.id.clone(), "$ ls\nfile\n".to_string()))
+                    .collect();
+                snapshot.contents = Some(contents);
+            }
+            after.contents.as_mut().unwrap().insert(
+                PaneId::from_str("%3").unwrap(),
+                "$ ls\nfile\n$ pwd\n/tmp\n".to_string(),
+            );
+
+            assert_eq!(
+                changes(&before, &after),
+                ["~ pane `misc:scratch.0` content:\n@@ -1,2 +1,4 @@\n $ ls\n file\n+$ pwd\n+/tmp"]
+            );
+        }
+    }
+
+    mod content {
+        use super::*;
+
+        #[test]
+        fn shows_changes_with_their_context() {
+            let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+            let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n";
+
+            assert_eq!(
+                unified_diff(old, new),
+                "@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n"
+            );
+        }
+
+        #[test]
+        fn distant_changes_are_separate_hunks() {
+            let old = (1..=20).map(|i| format!("{i}\n")).collect::<String>();
+            let new = old.replace("2\n", "two\n").replace("19\n", "nineteen\n");
+
+            let diff = unified_diff(&old, &new);
+
+            assert_eq!(diff.matches("@@ -").count(), 2);
+            assert!(diff.starts_with("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n"));
+            assert!(diff.ends_with("-19\n+nineteen\n 20\n"));
+        }
+
+        #[test]
+        fn scrolled_history_is_a_deletion_and_an_insertion() {
+            let old = "a\nb\nc\nd\n";
+            let new = "b\nc\nd\ne\n";
+
+            assert_eq!(
+                unified_diff(old, new),
+                "@@ -1,4 +1,4 @@\n-a\n b\n c\n d\n+e\n"
+            );
+        }
+
+        #[test]
+        fn empty_sides_have_no_context() {
+            assert_eq!(unified_diff("", "a\nb\n"), "@@ -0,0 +1,2 @@\n+a\n+b\n");
+            assert_eq!(unified_diff("a\n", ""), "@@ -1 +0,0 @@\n-a\n");
+        }
+    }
+
+    #[test]
+    fn layout_shape_leaves_out_checksum_and_pane_ids() {
+        assert_eq!(
+            layout_shape(
+                "41e9,279x71,0,0[279x40,0,0,71,279x30,0,41{147x30,0,41,72,131x30,148,41,73}]"
+            ),
+            "279x71,0,0[279x40,0,0,279x30,0,41{147x30,0,41,131x30,148,41}]"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Rewrite backups in older archive formats into the current format.
+
+use std::path::Path;
+
+use async_fs as fs;
+use tempfile::{NamedTempFile, TempDir};
+
+use crate::{
+    Result,
+    error::Error,
+    management::archive::{self, HistoryLines, Metadata, PaneMetadata, store::Store, v2},
+};
+
+/// Outcome of the migration of a backup.
+#[derive(Debug)]
+pub enum Migration {
+    /// The backup was rewritten from the `previous_version` format into the current format.
+    Migrated {
+        /// Format version of the backup before the migration.
+        previous_version: String,
+        /// Content of the backup.
+        overview: archive::Overview,
+    },
+
+    /// The backup is already in the current format, and was left untouched.
+    UpToDate {
+        /// Content of the backup.
+        overview: archive::Overview,
+    },
+}
+
+/// Rewrite the backup at `backup_filepath` into the current archive format.
+///
+/// The panes content is moved into the content store next to the backup. The new archive replaces
+/// the old one atomically, and keeps its permissions and modification time, which dates the
+/// autosave archive. An encrypted backup is read with the identity file at `identity_filepath`.
+pub async fn migrate<P: AsRef<Path>>(
+    backup_filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<Migration> {
+    let backup_filepath = backup_filepath.as_ref();
+    let mut metadata = Metadata::read_file(backup_filepath, identity_filepath).await?;
+    if metadata.version == archive::FORMAT_VERSION {
+        return Ok(Migration::UpToDate {
+            overview: metadata.overview(),
+        });
+    }
+
+    // Move the content of each pane into the store.
+    let temp_dir = TempDir::new()?;
+    let panes_content_dir = v2::extract_panes_content(
+        backup_filepath,
+        &metadata,
+        temp_dir.path(),
+        identity_filepath,
+    )
+    .await?;
+    let store = Store::for_backup(backup_filepath);
+
+    let mut pane_metadata = vec![];
+    for pane in &metadata.panes {
+        let filepath = panes_content_dir.join(format!("pane-{}.txt", pane.id));
+        // A pane created while the backup was written may have no content.
+        let Ok(content) = fs::read(&filepath).await else {
+            continue;
+        };
+        pane_metadata.push(PaneMetadata {
+            pane_id: pane.id.clone(),
+            digest: store.write(&content).await?,
+            size: content.len() as u64,
+            // The processes of older backups are unknown.
+            argv: vec![],
+            history_lines: HistoryLines::All,
+        });
+    }
+    temp_dir.close()?;
+
+    let previous_version =
+        std::mem::replace(&mut metadata.version, archive::FORMAT_VERSION.to_string());
+    metadata.pane_metadata = pane_metadata;
+
+    // Replace the backup, keeping the attributes of the original file.
+    let attributes = std::fs::metadata(backup_filepath)?;
+    let dirpath = backup_filepath.parent().unwrap_or(Path::new(""));
+    let temp_archive = NamedTempFile::new_in(dirpath)?;
+    v2::create_from_file(temp_archive.reopen()?, &metadata)?;
+    let archive = temp_archive
+        .persist(backup_filepath)
+        .map_err(|error| Error::from(error.error))?;
+    archive.set_permissions(attributes.permissions())?;
+    archive.set_modified(attributes.modified()?)?;
+
+    Ok(Migration::Migrated {
+        previous_version,
+        overview: metadata.overview(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::{management::archive::v1, tmux};
+
+    /// Write a v1 archive with one pane `%1`, whose content is `content`.
+    fn write_v1_archive(filepath: &Path, content: &[u8]) {
+        let metadata = v1::Metadata {
+            version: v1::FORMAT_VERSION.to_string(),
+            client: tmux::client::Client::from_str("'work':''").unwrap(),
+            sessions: vec![],
+            windows: vec![],
+            panes: vec![tmux::pane::Pane::from_str("%1:0:true:'host':'zsh':/tmp").unwrap()],
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let version_filepath = temp_dir.path().join(archive::VERSION_FILENAME);
+        std::fs::write(&version_filepath, v1::FORMAT_VERSION).unwrap();
+        let metadata_filepath = temp_dir.path().join(archive::METADATA_FILENAME);
+        std::fs::write(&metadata_filepath, serde_json::to_vec(&metadata).unwrap()).unwrap();
+        let panes_content_dir = temp_dir.path().join(archive::PANES_DIR_NAME);
+        std::fs::create_dir(&panes_content_dir).unwrap();
+        std::fs::write(panes_content_dir.join("pane-%1.txt"), content).unwrap();
+
+        let archive = std::fs::File::create(filepath).unwrap();
+        let enc = zstd::stream::write::Encoder::new(archive, 0)
+            .unwrap()
+            .auto_finish();
+        let mut tar = tar::Builder::new(enc);
+        tar.append_path_with_name(&version_filepath, archive::VERSION_FILENAME)
+            .unwrap();
+        tar.append_path_with_name(&metadata_filepath, archive::METADATA_FILENAME)
+            .unwrap();
+        tar.append_dir_all(archive::PANES_DIR_NAME, &panes_content_dir)
+            .unwrap();
+        tar.finish().unwrap();
+    }
+
+    #[test]
+    fn rewrites_v1_archives_into_the_current_format() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup-20220910T172024.141993.tar.zst");
+        write_v1_archive(&filepath, b"$ make test\n");
+
+        let migration = smol::block_on(migrate(&filepath, None)).unwrap();
+
+        let Migration::Migrated {
+            previous_version,
+            overview,
+        } = migration
+        else {
+            panic!("expected a migration, got {migration:?}");
+        };
+        assert_eq!(previous_version, v1::FORMAT_VERSION);
+        assert_eq!(overview.version, archive::FORMAT_VERSION);
+
+        let metadata = smol::block_on(Metadata::read_file(&filepath, None)).unwrap();
+        assert_eq!(metadata.version, archive::FORMAT_VERSION);
+        assert_eq!(metadata.pane_metadata.len(), 1);
+        let content =
+            smol::block_on(Store::for_backup(&filepath).read(&metadata.pane_metadata[0].digest))
+                .unwrap();
+        assert_eq!(content, b"$ make test\n");
+    }
+
+    #[test]
+    fn keeps_the_modification_time() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join(archive::AUTOSAVE_FILENAME);
+        write_v1_archive(&filepath, b"content");
+        let a_day_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(86400);
+        std::fs::File::options()
+            .append(true)
+            .open(&filepath)
+            .unwrap()
+            .set_modified(a_day_ago)
+            .unwrap();
+
+        smol::block_on(migrate(&filepath, None)).unwrap();
+
+        let modified = std::fs::metadata(&filepath).unwrap().modified().unwrap();
+        assert_eq!(modified, a_day_ago);
+    }
+
+    #[test]
+    fn leaves_current_archives_untouched() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup-20220910T172024.141993.tar.zst");
+        write_v1_archive(&filepath, b"content");
+        smol::block_on(migrate(&filepath, None)).unwrap();
+        let before = std::fs::read(&filepath).unwrap();
+
+        let migration = smol::block_on(migrate(&filepath, None)).unwrap();
+
+        assert!(matches!(migration, Migration::UpToDate { .. }));
+        assert_eq!(std::fs::read(&filepath).unwrap(), before);
+    }
+}
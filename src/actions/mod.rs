@@ -5,7 +5,16 @@ pub use autosave::{
     AutosaveContext, autosave, context as autosave_context,
     display_message as display_autosave_message,
 };
+mod diff;
+pub use diff::{Change, Diff, Kind as DiffKind, Side as DiffSide, diff};
+mod migrate;
+pub use migrate::{Migration, migrate};
 mod restore;
-pub use restore::restore;
+pub use restore::{
+    ExistingSessions, Restoration, RestoreOptions, RestorePlan, SkippedSession,
+    plan as restore_plan, restore,
+};
 mod save;
-pub use save::save;
+pub use save::{HistoryOverride, SaveOptions, ShellPattern, default_shells, save};
+mod verify;
+pub use verify::{Verification, verify};
@@ -1,20 +1,26 @@
 //! Restore sessions, windows and panes from the content of a backup.
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    fmt,
     iter::zip,
     path::{Path, PathBuf},
 };
 
+use clap::ValueEnum;
 use futures::future::join_all;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use smol;
+use smol::process::Command;
 use tempfile::TempDir;
 
 use crate::{
     Result,
     error::Error,
-    management::archive::v1,
-    tmux::{self, pane::Pane, session::Session, window::Window},
+    management::archive::{self, v2},
+    pattern::NamePattern,
+    tmux::{self, pane::Pane, pane_id::PaneId, session::Session, window::Window},
 };
 
 /// Name of the placeholder session.
@@ -28,12 +34,564 @@ fn is_inside_tmux() -> bool {
     std::env::var("TMUX").is_ok()
 }
 
-/// Restore all sessions, windows & panes from the backup file.
-pub async fn restore<P: AsRef<Path>>(backup_filepath: P) -> Result<v1::Overview> {
-    // Prepare the temp directory with the content of the backup.
+/// Handling of archived sessions which already exist in Tmux.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExistingSessions {
+    /// Leave the existing session untouched and do not restore it.
+    #[default]
+    Skip,
+
+    /// Kill the existing session and recreate it from the archive.
+    Override,
+
+    /// Rename the existing session aside with the suffix, then recreate it from the archive.
+    RenameLive,
+
+    /// Keep the existing session, and restore the archived one under a suffixed name.
+    RenameRestored,
+}
+
+/// Options of the restore.
+#[derive(Debug, Default, Clone)]
+pub struct RestoreOptions {
+    /// Restore only the sessions matching one of these patterns, or all sessions if empty.
+    pub sessions: Vec<NamePattern>,
+
+    /// Handling of archived sessions which already exist.
+    pub existing: ExistingSessions,
+
+    /// Suffix of the renamed sessions, by default `previous` for the live sessions and
+    /// `restored` for the restored ones.
+    pub suffix: Option<String>,
+
+    /// Programs relaunched in their pane after the content is replayed, such as `nvim` or
+    /// `htop`. The other panes only start the default shell.
+    pub relaunch: Vec<String>,
+
+    /// Identity file decrypting an encrypted backup.
+    pub identity_filepath: Option<PathBuf>,
+}
+
+impl RestoreOptions {
+    /// Suffix used to rename live or restored sessions.
+    fn suffix(&self) -> &str {
+        match (&self.suffix, self.existing) {
+            (Some(suffix), _) => suffix,
+            (None, ExistingSessions::RenameLive) => "previous",
+            (None, _) => "restored",
+        }
+    }
+}
+
+/// Return `true` if the program `command` saved in a pane, with its saved command line `argv`,
+/// is on the `relaunch` allow-list.
+///
+/// Each entry of the list is matched by its program name, its first word. Its other words, such
+/// as `-f` in `tail -f`, must all be arguments of the saved command line: such entries never match
+/// a pane whose command line is unknown.
+fn is_relaunched(command: &str, argv: &[String], relaunch: &[String]) -> bool {
+    relaunch.iter().any(|entry| {
+        let mut words = entry.split_whitespace();
+        words.next() == Some(command) && words.all(|word| argv.iter().skip(1).any(|a| a == word))
+    })
+}
+
+/// Return the command starting a restored pane.
+///
+/// The command prints the saved content, relaunches the saved program if it is on the `relaunch`
+/// allow-list, then replaces itself with the default command. The program is relaunched with its
+/// saved command line `argv` if it is known. The pane is started in its saved directory by Tmux.
+fn pane_command(
+    pane: &Pane,
+    argv: &[String],
+    content_filepath: &Path,
+    default_command: &str,
+    relaunch: &[String],
+) -> String {
+    let content_filepath = content_filepath.to_string_lossy();
+    if is_relaunched(&pane.command, argv, relaunch) {
+        format!(
+            "cat {content_filepath} ; {} ; exec {default_command}",
+            command_line(&pane.command, argv)
+        )
+    } else {
+        format!("cat {content_filepath} ; exec {default_command}")
+    }
+}
+
+/// Return the shell command line running `argv`, or only the program `command` if `argv` is
+/// unknown.
+fn command_line(command: &str, argv: &[String]) -> String {
+    if argv.is_empty() {
+        shell_quote(command)
+    } else {
+        argv.iter().map(|arg| shell_quote(arg)).join(" ")
+    }
+}
+
+/// Quote `word` for a POSIX shell, if needed.
+fn shell_quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=+@%,".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// What the restore does with an archived session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum SessionAction {
+    /// Create the session under this name.
+    Create {
+        /// Name of the new session.
+        name: String,
+    },
+
+    /// Do not restore the session.
+    Skip {
+        /// Why the session is not restored.
+        reason: &'static str,
+    },
+
+    /// Kill the existing session, then create it.
+    Override,
+
+    /// Rename the existing session, then create it.
+    RenameLive {
+        /// New name of the existing session.
+        live_name: String,
+    },
+}
+
+impl SessionAction {
+    /// Name of the session created from the archived session named `archived_name`, if any.
+    fn restored_name<'a>(&'a self, archived_name: &'a str) -> Option<&'a str> {
+        match self {
+            SessionAction::Create { name } => Some(name),
+            SessionAction::Skip { .. } => None,
+            SessionAction::Override | SessionAction::RenameLive { .. } => Some(archived_name),
+        }
+    }
+}
+
+/// Decide what to do with each archived session, given the names of the existing sessions.
+///
+/// The `current_session_name` is the session running this process, if any: it is never killed.
+/// Suffixed names are chosen so they do not collide with existing or other suffixed names.
+fn session_actions(
+    sessions: &[Session],
+    existing_names: &HashSet<String>,
+    current_session_name: Option<&str>,
+    options: &RestoreOptions,
+) -> Vec<SessionAction> {
+    let mut taken_names: HashSet<String> = existing_names.clone();
+    taken_names.extend(sessions.iter().map(|s| s.name.clone()));
+
+    let mut unique_name = |name: &str| {
+        let mut candidate = format!("{name}-{}", options.suffix());
+        let mut counter = 2;
+        while taken_names.contains(&candidate) {
+            candidate = format!("{name}-{}-{counter}", options.suffix());
+            counter += 1;
+        }
+        taken_names.insert(candidate.clone());
+        candidate
+    };
+
+    sessions
+        .iter()
+        .map(|session| {
+            if !existing_names.contains(&session.name) {
+                return SessionAction::Create {
+                    name: session.name.clone(),
+                };
+            }
+            match options.existing {
+                ExistingSessions::Skip => SessionAction::Skip {
+                    reason: "already exists",
+                },
+                ExistingSessions::Override
+                    if current_session_name == Some(session.name.as_str()) =>
+                {
+                    SessionAction::Skip {
+                        reason: "runs tmux-backup, cannot be killed",
+                    }
+                }
+                ExistingSessions::Override => SessionAction::Override,
+                ExistingSessions::RenameLive => SessionAction::RenameLive {
+                    live_name: unique_name(&session.name),
+                },
+                ExistingSessions::RenameRestored => SessionAction::Create {
+                    name: unique_name(&session.name),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Read the metadata of the backup, keeping only the sessions selected by `options`.
+async fn selected_metadata(
+    backup_filepath: &Path,
+    options: &RestoreOptions,
+) -> Result<archive::Metadata> {
+    let mut metadata =
+        archive::Metadata::read_file(backup_filepath, options.identity_filepath.as_deref()).await?;
+    if !options.sessions.is_empty() {
+        metadata.retain_sessions(&options.sessions);
+        if metadata.sessions.is_empty() {
+            let patterns = options.sessions.iter().map(|p| format!("`{p}`")).join(", ");
+            return Err(Error::UnknownSession(patterns));
+        }
+    }
+    Ok(metadata)
+}
+
+/// Outcome of a restore.
+#[derive(Debug)]
+pub struct Restoration {
+    /// Content of the restored Tmux environment.
+    pub overview: archive::Overview,
+
+    /// Archived sessions which were not restored.
+    pub skipped_sessions: Vec<SkippedSession>,
+
+    /// Session to attach to, if the restore started the Tmux server.
+    pub attach_session: Option<String>,
+}
+
+/// Archived session which was not restored.
+#[derive(Debug, Serialize)]
+pub struct SkippedSession {
+    /// Name of the archived session.
+    pub name: String,
+
+    /// Reason why the session was skipped, such as `already exists`.
+    pub reason: &'static str,
+}
+
+/// What a restore would do, as reported by `restore --dry-run`.
+#[derive(Debug, Serialize)]
+pub struct RestorePlan {
+    /// Backup file to restore.
+    pub backup_filepath: PathBuf,
+
+    /// Whether a Tmux server is started, because the restore runs outside Tmux.
+    pub start_server: bool,
+
+    /// Archived sessions and what is done with each of them.
+    pub sessions: Vec<SessionPlan>,
+
+    /// Session the client switches to.
+    pub client_session: String,
+
+    /// Session the client switches to before the current one, if any.
+    pub client_last_session: Option<String>,
+
+    /// Session killed at the end of the restore.
+    pub killed_session: String,
+
+    /// Whether the killed session exists. Otherwise, the restore reports unusual start
+    /// conditions.
+    pub killed_session_exists: bool,
+}
+
+/// Planned restore of an archived session.
+#[derive(Debug, Serialize)]
+pub struct SessionPlan {
+    /// Name of the session in the backup.
+    pub archived_name: String,
+
+    /// What is done with the session.
+    #[serde(flatten)]
+    pub action: SessionAction,
+
+    /// Working directory of the session.
+    pub dirpath: PathBuf,
+
+    /// Windows of the session, by index.
+    pub windows: Vec<WindowPlan>,
+}
+
+/// Window created in a restored session.
+#[derive(Debug, Serialize)]
+pub struct WindowPlan {
+    /// Index of the window.
+    pub index: u16,
+
+    /// Name of the window.
+    pub name: String,
+
+    /// Layout of the window.
+    pub layout: String,
+
+    /// Whether the window is active.
+    pub is_active: bool,
+
+    /// Panes of the window, by index.
+    pub panes: Vec<PanePlan>,
+}
+
+/// Pane created in a restored window.
+#[derive(Debug, Serialize)]
+pub struct PanePlan {
+    /// Index of the pane.
+    pub index: u16,
+
+    /// Command which was running in the pane.
+    pub command: String,
+
+    /// Command line of the program which was running in the pane, if it is known.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub argv: Vec<String>,
+
+    /// Working directory of the pane.
+    pub dirpath: PathBuf,
+
+    /// Whether the pane is active.
+    pub is_active: bool,
+
+    /// Whether the command is relaunched after the content is replayed.
+    pub is_relaunched: bool,
+}
+
+impl RestorePlan {
+    /// Build the plan from the selected `metadata`, the `actions` decided for its sessions, the
+    /// names of the existing sessions and the `relaunch` allow-list.
+    fn new(
+        backup_filepath: &Path,
+        metadata: &archive::Metadata,
+        actions: Vec<SessionAction>,
+        existing_names: &HashSet<String>,
+        inside_tmux: bool,
+        relaunch: &[String],
+    ) -> Self {
+        let sessions: Vec<SessionPlan> = zip(&metadata.sessions, actions)
+            .map(|(session, action)| {
+                let mut windows = metadata.windows_related_to(session);
+                windows.sort_unstable_by_key(|w| w.index);
+                let windows = windows
+                    .into_iter()
+                    .map(|window| {
+                        let mut panes = metadata.panes_related_to(&window);
+                        panes.sort_unstable_by_key(|p| p.index);
+                        WindowPlan {
+                            index: window.index,
+                            name: window.name,
+                            layout: window.layout.clone(),
+                            is_active: window.is_active,
+                            panes: panes
+                                .into_iter()
+                                .map(|pane| PanePlan {
+                                    index: pane.index,
+                                    command: pane.command.clone(),
+                                    argv: metadata.pane_argv(&pane.id).to_vec(),
+                                    dirpath: pane.dirpath.clone(),
+                                    is_active: pane.is_active,
+                                    is_relaunched: is_relaunched(
+                                        &pane.command,
+                                        metadata.pane_argv(&pane.id),
+                                        relaunch,
+                                    ),
+                                })
+                                .collect(),
+                        }
+                    })
+                    .collect();
+
+                SessionPlan {
+                    archived_name: session.name.clone(),
+                    action,
+                    dirpath: session.dirpath.clone(),
+                    windows,
+                }
+            })
+            .collect();
+
+        let restored_names = sessions
+            .iter()
+            .filter_map(|s| s.action.restored_name(&s.archived_name));
+        let available_names: HashSet<&str> = existing_names
+            .iter()
+            .map(String::as_str)
+            .chain(restored_names)
+            .collect();
+        let (client_last_session, client_session) = client_sessions(metadata, &available_names);
+
+        let (killed_session, killed_session_exists) = if inside_tmux {
+            ("0", existing_names.contains("0"))
+        } else {
+            (PLACEHOLDER_SESSION_NAME, true)
+        };
+
+        Self {
+            backup_filepath: backup_filepath.to_path_buf(),
+            start_server: !inside_tmux,
+            client_session: client_session.to_string(),
+            client_last_session: client_last_session.map(str::to_string),
+            killed_session: killed_session.to_string(),
+            killed_session_exists,
+            sessions,
+        }
+    }
+}
+
+impl fmt::Display for RestorePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Restore plan for {}",
+            self.backup_filepath.to_string_lossy()
+        )?;
+        if self.start_server {
+            writeln!(
+                f,
+                "Start the Tmux server with session `{PLACEHOLDER_SESSION_NAME}`"
+            )?;
+        }
+
+        for session in &self.sessions {
+            let action = match &session.action {
+                SessionAction::Create { name } if *name == session.archived_name => {
+                    "create".to_string()
+                }
+                SessionAction::Create { name } => format!("create as `{name}`"),
+                SessionAction::Skip { reason } => format!("skip, {reason}"),
+                SessionAction::Override => "kill the existing session, then create".to_string(),
+                SessionAction::RenameLive { live_name } => {
+                    format!("rename the existing session to `{live_name}`, then create")
+                }
+            };
+            writeln!(
+                f,
+                "\n{} ({}): {action}",
+                session.archived_name,
+                session.dirpath.to_string_lossy()
+            )?;
+
+            if matches!(session.action, SessionAction::Skip { .. }) {
+                continue;
+            }
+
+            for (window_pos, window) in session.windows.iter().enumerate() {
+                let (branch, indent) = if window_pos + 1 == session.windows.len() {
+                    ("└──", "    ")
+                } else {
+                    ("├──", "│   ")
+                };
+                let marker = if window.is_active { "*" } else { " " };
+                writeln!(
+                    f,
+                    "{branch} {}:{marker} {}  layout: {}",
+                    window.index, window.name, window.layout
+                )?;
+
+                for (pane_pos, pane) in window.panes.iter().enumerate() {
+                    let branch = if pane_pos + 1 == window.panes.len() {
+                        "└──"
+                    } else {
+                        "├──"
+                    };
+                    let marker = if pane.is_active { "*" } else { " " };
+                    let command = if pane.argv.is_empty() {
+                        pane.command.clone()
+                    } else {
+                        pane.argv.join(" ")
+                    };
+                    let relaunched = if pane.is_relaunched {
+                        "  (relaunched)"
+                    } else {
+                        ""
+                    };
+                    writeln!(
+                        f,
+                        "{indent}{branch} {}:{marker} {command}  {}{relaunched}",
+                        pane.index,
+                        pane.dirpath.to_string_lossy()
+                    )?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        if let Some(last_session) = &self.client_last_session {
+            writeln!(f, "Switch the client to last session `{last_session}`")?;
+        }
+        writeln!(f, "Switch the client to session `{}`", self.client_session)?;
+        if self.killed_session_exists {
+            write!(f, "Kill session `{}`", self.killed_session)
+        } else {
+            write!(
+                f,
+                "Kill session `{}`: not found, the restore will report unusual start conditions",
+                self.killed_session
+            )
+        }
+    }
+}
+
+/// Describe what restoring the backup file would do, without changing anything.
+///
+/// The sessions are selected and matched against the existing sessions as in [`restore`]. If no
+/// Tmux server is running, no session exists.
+pub async fn plan<P: AsRef<Path>>(
+    backup_filepath: P,
+    options: &RestoreOptions,
+) -> Result<RestorePlan> {
+    let metadata = selected_metadata(backup_filepath.as_ref(), options).await?;
+
+    let inside_tmux = is_inside_tmux();
+    let existing_sessions_names: HashSet<String> = tmux::session::available_sessions()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    let current_session_name = if inside_tmux {
+        tmux::client::current().await.ok().map(|c| c.session_name)
+    } else {
+        None
+    };
+    let actions = session_actions(
+        &metadata.sessions,
+        &existing_sessions_names,
+        current_session_name.as_deref(),
+        options,
+    );
+
+    Ok(RestorePlan::new(
+        backup_filepath.as_ref(),
+        &metadata,
+        actions,
+        &existing_sessions_names,
+        inside_tmux,
+        &options.relaunch,
+    ))
+}
+
+/// Restore sessions, windows & panes from the backup file.
+///
+/// If `options.sessions` is not empty, only the sessions matching one of the patterns are
+/// restored, along with their windows and panes. Archived sessions which already exist are
+/// handled according to `options.existing`.
+pub async fn restore<P: AsRef<Path>>(
+    backup_filepath: P,
+    options: &RestoreOptions,
+) -> Result<Restoration> {
+    // Read and select the sessions to restore, before touching Tmux.
+    let metadata = selected_metadata(backup_filepath.as_ref(), options).await?;
+
+    // Prepare the temp directory with the content of the panes.
     let temp_dir = TempDir::new()?;
-    v1::unpack(backup_filepath.as_ref(), temp_dir.path()).await?;
-    let panes_content_dir = temp_dir.path().join("panes-content");
+    let panes_content_dir = v2::extract_panes_content(
+        backup_filepath.as_ref(),
+        &metadata,
+        temp_dir.path(),
+        options.identity_filepath.as_deref(),
+    )
+    .await?;
 
     // Start tmux if needed.
     let not_in_tmux = !is_inside_tmux();
@@ -45,38 +603,69 @@ pub async fn restore<P: AsRef<Path>>(backup_filepath: P) -> Result<v1::Overview>
     let default_command = tmux::server::default_command().await?;
 
     // Restore sessions, windows and panes.
-    let metadata = v1::Metadata::read_file(backup_filepath).await?;
-
     let existing_sessions_names: HashSet<_> = tmux::session::available_sessions()
         .await?
         .into_iter()
         .map(|s| s.name)
         .collect();
+    let current_session_name = if not_in_tmux {
+        None
+    } else {
+        tmux::client::current().await.ok().map(|c| c.session_name)
+    };
+    let actions = session_actions(
+        &metadata.sessions,
+        &existing_sessions_names,
+        current_session_name.as_deref(),
+        options,
+    );
 
     let mut handles = vec![];
+    let mut restored_sessions_names = vec![];
+    let mut skipped_sessions = vec![];
 
-    for session in &metadata.sessions {
-        if existing_sessions_names.contains(&session.name) {
-            eprintln!("skip creating existing session {}", session.name);
-            continue;
-        }
-
-        let session = session.clone();
+    for (session, action) in zip(&metadata.sessions, actions) {
+        let mut session = session.clone();
         let related_windows = metadata.windows_related_to(&session);
         let related_panes: Vec<Vec<Pane>> = related_windows
             .iter()
             .map(|w| metadata.panes_related_to(w).into_iter().cloned().collect())
             .collect();
+        let panes_argv: HashMap<PaneId, Vec<String>> = related_panes
+            .iter()
+            .flatten()
+            .map(|pane| (pane.id.clone(), metadata.pane_argv(&pane.id).to_vec()))
+            .collect();
+
+        match action {
+            SessionAction::Skip { reason } => {
+                skipped_sessions.push(SkippedSession {
+                    name: session.name,
+                    reason,
+                });
+                continue;
+            }
+            SessionAction::Create { name } => session.name = name,
+            SessionAction::Override => tmux::server::kill_session(&session.name).await?,
+            SessionAction::RenameLive { live_name } => {
+                rename_session(&session.name, &live_name).await?
+            }
+        }
+        restored_sessions_names.push(session.name.clone());
+
         let panes_content_dirpath = panes_content_dir.clone();
         let default_command = default_command.clone();
+        let relaunch = options.relaunch.clone();
 
         let handle = smol::spawn(async move {
             restore_session(
                 session,
                 related_windows,
                 related_panes,
+                panes_argv,
                 panes_content_dirpath,
                 &default_command,
+                &relaunch,
             )
             .await
         });
@@ -91,21 +680,30 @@ pub async fn restore<P: AsRef<Path>>(backup_filepath: P) -> Result<v1::Overview>
     // Delete the temp restore directory.
     temp_dir.close()?;
 
-    // Set the client last and current session.
-    tmux::client::switch_client(&metadata.client.last_session_name).await?;
-    tmux::client::switch_client(&metadata.client.session_name).await?;
+    // Set the client last and current session, falling back on a restored session if the
+    // archived ones were not selected.
+    let available_sessions_names: HashSet<&str> = existing_sessions_names
+        .iter()
+        .chain(restored_sessions_names.iter())
+        .map(String::as_str)
+        .collect();
+    let (last_session_name, session_name) = client_sessions(&metadata, &available_sessions_names);
+    if let Some(last_session_name) = last_session_name {
+        tmux::client::switch_client(last_session_name).await?;
+    }
+    tmux::client::switch_client(session_name).await?;
 
     // Kill the session used to start the server.
     if not_in_tmux {
         tmux::server::kill_session(PLACEHOLDER_SESSION_NAME).await?;
-        println!(
-            "Attach to your last session with `tmux attach -t {}`",
-            metadata.client.session_name
-        );
 
         // Return an overview of the archived tmux environment, which is identical, in principle,
         // with the new one. We cannot do more because the client metadata cannot be fetched.
-        Ok(metadata.overview())
+        Ok(Restoration {
+            overview: metadata.overview(),
+            skipped_sessions,
+            attach_session: Some(session_name.to_string()),
+        })
     } else {
         if tmux::server::kill_session("0").await.is_err() {
             let message = "
@@ -117,11 +715,58 @@ pub async fn restore<P: AsRef<Path>>(backup_filepath: P) -> Result<v1::Overview>
         }
 
         // Return an overview of the restored tmux environment.
-        let metadata = v1::Metadata::new().await?;
-        Ok(metadata.overview())
+        let metadata = archive::Metadata::new().await?;
+        Ok(Restoration {
+            overview: metadata.overview(),
+            skipped_sessions,
+            attach_session: None,
+        })
     }
 }
 
+/// Rename the session exactly named `name` to `new_name`.
+async fn rename_session(name: &str, new_name: &str) -> Result<()> {
+    let exact_name = format!("={name}");
+    let output = Command::new("tmux")
+        .args(["rename-session", "-t", &exact_name, new_name])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::TmuxCommand(format!(
+            "could not rename session `{name}` to `{new_name}`: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Return the last and current sessions of the client after the restore.
+///
+/// The archived client sessions are used if they are `available`. Otherwise, the current session
+/// falls back on the first restored session, and there is no last session.
+fn client_sessions<'a>(
+    metadata: &'a archive::Metadata,
+    available: &HashSet<&str>,
+) -> (Option<&'a str>, &'a str) {
+    let client = &metadata.client;
+
+    let session_name = if available.contains(client.session_name.as_str()) {
+        client.session_name.as_str()
+    } else {
+        metadata
+            .sessions
+            .first()
+            .map_or(client.session_name.as_str(), |s| s.name.as_str())
+    };
+
+    let last_session_name = Some(client.last_session_name.as_str())
+        .filter(|name| available.contains(name) && *name != session_name);
+
+    (last_session_name, session_name)
+}
+
 /// Association between a pane from the backup with a new target pane id.
 #[derive(Debug, Clone)]
 struct Pair {
@@ -144,8 +789,10 @@ async fn restore_session(
     mut session: Session,
     session_windows: Vec<Window>,
     panes_per_window: Vec<Vec<Pane>>,
+    panes_argv: HashMap<PaneId, Vec<String>>,
     panes_content_dir: PathBuf,
     default_command: &str,
+    relaunch: &[String],
 ) -> Result<()> {
     let mut pairs: Vec<Pair> = vec![];
 
@@ -154,28 +801,25 @@ async fn restore_session(
     for (index, (src_window, src_panes)) in zip(&session_windows, &panes_per_window).enumerate() {
         let first_pane = src_panes.first().unwrap(); // guaranteed
         let content_filepath = panes_content_dir.join(format!("pane-{}.txt", first_pane.id));
-        let pane_command = format!(
-            "cat {} ; exec {}",
-            content_filepath.to_string_lossy(),
-            default_command
+        let command = pane_command(
+            first_pane,
+            &panes_argv[&first_pane.id],
+            &content_filepath,
+            default_command,
+            relaunch,
         );
 
         let (new_window_id, new_pane_id) = {
             if index == 0 {
-                let (new_session_id, new_window_id, new_pane_id) = tmux::session::new_session(
-                    &session,
-                    src_window,
-                    first_pane,
-                    Some(&pane_command),
-                )
-                .await?;
+                let (new_session_id, new_window_id, new_pane_id) =
+                    tmux::session::new_session(&session, src_window, first_pane, Some(&command))
+                        .await?;
                 // Update session with the newly created session ID so that
                 // subsequent new_window() calls target the correct session.
                 session.id = new_session_id;
                 (new_window_id, new_pane_id)
             } else {
-                tmux::window::new_window(&session, src_window, first_pane, Some(&pane_command))
-                    .await?
+                tmux::window::new_window(&session, src_window, first_pane, Some(&command)).await?
             }
         };
 
@@ -189,22 +833,29 @@ async fn restore_session(
         //     panes for this first window. Each new pane is configured as the original pane.
         for pane in src_panes.iter().skip(1) {
             let content_filepath = panes_content_dir.join(format!("pane-{}.txt", pane.id));
-            let pane_command = format!(
-                "cat {} ; exec {}",
-                content_filepath.to_string_lossy(),
-                default_command
+            let command = pane_command(
+                pane,
+                &panes_argv[&pane.id],
+                &content_filepath,
+                default_command,
+                relaunch,
             );
 
-            let new_pane_id =
-                tmux::pane::new_pane(pane, Some(&pane_command), &new_window_id).await?;
+            let new_pane_id = tmux::pane::new_pane(pane, Some(&command), &new_window_id).await?;
             pairs.push(Pair {
                 source: pane.clone(),
                 target: new_pane_id,
             });
         }
 
-        // 1d. Set the layout
-        tmux::window::set_layout(&src_window.layout, &new_window_id).await?;
+        // 1d. Set the layout. Saves may leave out some panes of a window, which no longer fit
+        //     its layout: these windows are tiled instead.
+        let layout = if src_panes.len() == src_window.pane_ids().len() {
+            src_window.layout.as_str()
+        } else {
+            "tiled"
+        };
+        tmux::window::set_layout(layout, &new_window_id).await?;
 
         if src_window.is_active {
             tmux::window::select_window(&new_window_id).await?;
@@ -254,6 +905,415 @@ mod tests {
         }
     }
 
+    mod session_actions {
+        use super::*;
+        use std::str::FromStr;
+
+        fn sessions(names: &[&str]) -> Vec<Session> {
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| Session::from_str(&format!("${i}:'{name}':/tmp")).unwrap())
+                .collect()
+        }
+
+        fn existing(names: &[&str]) -> HashSet<String> {
+            names.iter().map(|name| name.to_string()).collect()
+        }
+
+        fn options(existing: ExistingSessions) -> RestoreOptions {
+            RestoreOptions {
+                existing,
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn missing_sessions_are_created() {
+            let actions = session_actions(
+                &sessions(&["work"]),
+                &existing(&["misc"]),
+                None,
+                &options(ExistingSessions::Override),
+            );
+
+            assert_eq!(
+                actions,
+                [SessionAction::Create {
+                    name: "work".into()
+                }]
+            );
+        }
+
+        #[test]
+        fn existing_sessions_are_skipped_by_default() {
+            let actions = session_actions(
+                &sessions(&["work", "misc"]),
+                &existing(&["work"]),
+                None,
+                &RestoreOptions::default(),
+            );
+
+            assert_eq!(
+                actions,
+                [
+                    SessionAction::Skip {
+                        reason: "already exists"
+                    },
+                    SessionAction::Create {
+                        name: "misc".into()
+                    }
+                ]
+            );
+        }
+
+        #[test]
+        fn override_kills_existing_sessions_except_the_current_one() {
+            let actions = session_actions(
+                &sessions(&["work", "misc"]),
+                &existing(&["work", "misc"]),
+                Some("misc"),
+                &options(ExistingSessions::Override),
+            );
+
+            assert_eq!(
+                actions,
+                [
+                    SessionAction::Override,
+                    SessionAction::Skip {
+                        reason: "runs tmux-backup, cannot be killed"
+                    }
+                ]
+            );
+        }
+
+        #[test]
+        fn rename_live_uses_the_previous_suffix() {
+            let actions = session_actions(
+                &sessions(&["work"]),
+                &existing(&["work"]),
+                Some("work"),
+                &options(ExistingSessions::RenameLive),
+            );
+
+            assert_eq!(
+                actions,
+                [SessionAction::RenameLive {
+                    live_name: "work-previous".into()
+                }]
+            );
+        }
+
+        #[test]
+        fn rename_restored_uses_the_restored_suffix() {
+            let actions = session_actions(
+                &sessions(&["work"]),
+                &existing(&["work"]),
+                None,
+                &options(ExistingSessions::RenameRestored),
+            );
+
+            assert_eq!(
+                actions,
+                [SessionAction::Create {
+                    name: "work-restored".into()
+                }]
+            );
+        }
+
+        #[test]
+        fn suffixed_names_do_not_collide() {
+            let options = RestoreOptions {
+                existing: ExistingSessions::RenameRestored,
+                suffix: Some("copy".into()),
+                ..Default::default()
+            };
+            let actions = session_actions(
+                &sessions(&["work", "work-copy"]),
+                &existing(&["work", "work-copy"]),
+                None,
+                &options,
+            );
+
+            assert_eq!(
+                actions,
+                [
+                    SessionAction::Create {
+                        name: "work-copy-2".into()
+                    },
+                    SessionAction::Create {
+                        name: "work-copy-copy".into()
+                    }
+                ]
+            );
+        }
+    }
+
+    mod client_fallback {
+        use super::*;
+        use std::str::FromStr;
+
+        fn metadata(session_names: &[&str], client: &str) -> archive::Metadata {
+            archive::Metadata {
+                version: archive::FORMAT_VERSION.to_string(),
+                client: tmux::client::Client::from_str(client).unwrap(),
+                sessions: session_names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| Session::from_str(&format!("${i}:'{name}':/tmp")).unwrap())
+                    .collect(),
+                windows: vec![],
+                panes: vec![],
+                pane_metadata: vec![],
+                escapes: true,
+                label: None,
+                note: None,
+            }
+        }
+
+        #[test]
+        fn keeps_archived_sessions_when_available() {
+            let metadata = metadata(&["work", "misc"], "'work':'misc'");
+            let available = HashSet::from(["work", "misc"]);
+
+            assert_eq!(
+                client_sessions(&metadata, &available),
+                (Some("misc"), "work")
+            );
+        }
+
+        #[test]
+        fn falls_back_on_first_restored_session() {
+            let metadata = metadata(&["api", "web"], "'work':'misc'");
+            let available = HashSet::from(["api", "web"]);
+
+            assert_eq!(client_sessions(&metadata, &available), (None, "api"));
+        }
+
+        #[test]
+        fn keeps_last_session_if_it_already_existed() {
+            let metadata = metadata(&["api"], "'work':'misc'");
+            let available = HashSet::from(["api", "misc"]);
+
+            assert_eq!(
+                client_sessions(&metadata, &available),
+                (Some("misc"), "api")
+            );
+        }
+
+        #[test]
+        fn skips_empty_last_session() {
+            let metadata = metadata(&["work"], "'work':''");
+            let available = HashSet::from(["work"]);
+
+            assert_eq!(client_sessions(&metadata, &available), (None, "work"));
+        }
+    }
+
+    mod relaunch {
+        use super::*;
+        use std::str::FromStr;
+
+        fn pane(command: &str) -> Pane {
+            Pane::from_str(&format!("%1:0:true:'host':'{command}':/home/user")).unwrap()
+        }
+
+        fn allow_list(entries: &[&str]) -> Vec<String> {
+            entries.iter().map(|e| e.to_string()).collect()
+        }
+
+        #[test]
+        fn other_panes_only_start_the_default_command() {
+            let command = pane_command(
+                &pane("zsh"),
+                &[],
+                Path::new("/tmp/pane-%1.txt"),
+                "/bin/zsh",
+                &allow_list(&["nvim"]),
+            );
+
+            assert_eq!(command, "cat /tmp/pane-%1.txt ; exec /bin/zsh");
+        }
+
+        #[test]
+        fn allowed_programs_are_relaunched_after_the_content() {
+            let command = pane_command(
+                &pane("nvim"),
+                &[],
+                Path::new("/tmp/pane-%1.txt"),
+                "/bin/zsh",
+                &allow_list(&["vim", "nvim"]),
+            );
+
+            assert_eq!(command, "cat /tmp/pane-%1.txt ; nvim ; exec /bin/zsh");
+        }
+
+        #[test]
+        fn allowed_programs_are_relaunched_with_their_command_line() {
+            let argv = allow_list(&["nvim", "my notes.md", "+12"]);
+            let command = pane_command(
+                &pane("nvim"),
+                &argv,
+                Path::new("/tmp/pane-%1.txt"),
+                "/bin/zsh",
+                &allow_list(&["nvim"]),
+            );
+
+            assert_eq!(
+                command,
+                "cat /tmp/pane-%1.txt ; nvim 'my notes.md' +12 ; exec /bin/zsh"
+            );
+        }
+
+        #[test]
+        fn entries_are_matched_by_program_name() {
+            assert!(is_relaunched("tail", &[], &allow_list(&["less", "tail"])));
+            assert!(!is_relaunched("tail", &[], &allow_list(&["tailscale"])));
+            assert!(!is_relaunched("tail", &[], &[]));
+        }
+
+        #[test]
+        fn entry_arguments_filter_the_saved_command_line() {
+            let relaunch = allow_list(&["tail -f"]);
+
+            assert!(is_relaunched(
+                "tail",
+                &allow_list(&["tail", "-f", "/var/log/syslog"]),
+                &relaunch
+            ));
+            assert!(!is_relaunched(
+                "tail",
+                &allow_list(&["tail", "-n", "20", "/var/log/syslog"]),
+                &relaunch
+            ));
+            assert!(!is_relaunched("tail", &[], &relaunch));
+        }
+
+        #[test]
+        fn unsafe_words_are_quoted() {
+            assert_eq!(shell_quote("htop"), "htop");
+            assert_eq!(shell_quote("my prog"), "'my prog'");
+            assert_eq!(shell_quote("it's"), r"'it'\''s'");
+            assert_eq!(shell_quote(""), "''");
+        }
+    }
+
+    mod restore_plan {
+        use super::*;
+        use std::str::FromStr;
+
+        fn metadata() -> archive::Metadata {
+            let windows = [
+                "@1:1:false:64f0,334x85,0,0,3:'shell':'work'",
+                "@2:0:true:035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}:'editor':'work'",
+                "@3:0:true:64f1,334x85,0,0,4:'scratch':'misc'",
+            ];
+            let panes = [
+                "%2:1:false:'host':'zsh':/home/user/work",
+                "%1:0:true:'host':'nvim':/home/user/work/src",
+                "%3:0:true:'host':'zsh':/home/user",
+                "%4:0:true:'':'htop':/tmp",
+            ];
+
+            archive::Metadata {
+                version: archive::FORMAT_VERSION.to_string(),
+                client: tmux::client::Client::from_str("'work':'misc'").unwrap(),
+                sessions: vec![
+                    Session::from_str("$1:'work':/home/user/work").unwrap(),
+                    Session::from_str("$2:'misc':/tmp").unwrap(),
+                ],
+                windows: windows.map(|w| Window::from_str(w).unwrap()).to_vec(),
+                panes: panes.map(|p| Pane::from_str(p).unwrap()).to_vec(),
+                pane_metadata: vec![archive::PaneMetadata {
+                    pane_id: PaneId::from_str("%1").unwrap(),
+                    digest: String::new(),
+                    size: 0,
+                    argv: vec!["nvim".to_string(), "src/lib.rs".to_string()],
+                    history_lines: archive::HistoryLines::All,
+                }],
+                escapes: true,
+                label: None,
+                note: None,
+            }
+        }
+
+        fn plan(existing_names: &[&str], inside_tmux: bool) -> RestorePlan {
+            let metadata = metadata();
+            let existing_names: HashSet<String> =
+                existing_names.iter().map(|s| s.to_string()).collect();
+            let options = RestoreOptions {
+                relaunch: vec!["nvim".to_string()],
+                ..Default::default()
+            };
+            let actions = session_actions(&metadata.sessions, &existing_names, None, &options);
+
+            RestorePlan::new(
+                Path::new("/tmp/backup.tar.zst"),
+                &metadata,
+                actions,
+                &existing_names,
+                inside_tmux,
+                &options.relaunch,
+            )
+        }
+
+        #[test]
+        fn renders_restored_sessions_as_a_tree() {
+            let plan = plan(&["misc", "0"], true);
+
+            let expected = r#"Restore plan for /tmp/backup.tar.zst
+
+work (/home/user/work): create
+├── 0:* editor  layout: 035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}
+│   ├── 0:* nvim src/lib.rs  /home/user/work/src  (relaunched)
+│   └── 1:  zsh  /home/user/work
+└── 1:  shell  layout: 64f0,334x85,0,0,3
+    └── 0:* zsh  /home/user
+
+misc (/tmp): skip, already exists
+
+Switch the client to last session `misc`
+Switch the client to session `work`
+Kill session `0`"#;
+            assert_eq!(plan.to_string(), expected);
+        }
+
+        #[test]
+        fn outside_tmux_starts_the_server_with_the_placeholder() {
+            let plan = plan(&[], false);
+
+            assert!(plan.start_server);
+            assert_eq!(plan.killed_session, PLACEHOLDER_SESSION_NAME);
+            assert!(plan.killed_session_exists);
+            assert!(plan.to_string().contains("Start the Tmux server"));
+        }
+
+        #[test]
+        fn warns_when_session_zero_is_missing() {
+            let plan = plan(&[], true);
+
+            assert!(!plan.killed_session_exists);
+            assert!(plan.to_string().ends_with("unusual start conditions"));
+        }
+
+        #[test]
+        fn serializes_the_action_of_each_session() {
+            let plan = plan(&["misc"], true);
+
+            let value = serde_json::to_value(&plan).unwrap();
+
+            assert_eq!(value["sessions"][0]["action"], "create");
+            assert_eq!(value["sessions"][0]["name"], "work");
+            assert_eq!(
+                value["sessions"][0]["windows"][0]["panes"][0]["command"],
+                "nvim"
+            );
+            assert_eq!(value["sessions"][1]["action"], "skip");
+            assert_eq!(value["sessions"][1]["reason"], "already exists");
+            assert_eq!(value["client_last_session"], "misc");
+        }
+    }
+
     mod pair_struct {
         use super::*;
         use std::path::PathBuf;
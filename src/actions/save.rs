@@ -1,147 +1,503 @@
 //! Retrieve session information and panes content save to a backup.
 
+use std::borrow::Cow;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::LazyLock;
 
-use async_fs as fs;
 use futures::future::join_all;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use smol;
-use tempfile::TempDir;
-
-use crate::{Result, management::archive::v1, tmux};
+use tempfile::{NamedTempFile, TempDir};
+
+use crate::{
+    Result,
+    error::Error,
+    management::archive::{self, HistoryLines, Label, encryption::Encryption, store::Store, v2},
+    pattern::{NameFilter, NamePattern},
+    process,
+    redaction::{Redactions, Redactor},
+    tmux,
+};
 use tmux_lib::utils;
 
-/// Shell commands that are recognized for prompt line dropping.
+/// Shell commands that are recognized for prompt line dropping, by default.
 ///
 /// When capturing pane content, if the active command is one of these shells,
 /// we can optionally drop the last N lines to avoid capturing the shell prompt.
 const DETECTED_SHELLS: &[&str] = &["zsh", "bash", "fish"];
 
-/// Save the tmux sessions, windows and panes into a backup at `backup_dirpath`.
-///
-/// After saving, this function returns the path to the backup and the number of
-/// sessions, windows and panes.
-///
-/// # Notes
-///
-/// - The `backup_dirpath` folder is assumed to exist (done during catalog initialization).
-/// - Backups have a name similar to `backup-20220731T222948.tar.zst`.
+/// Return the shells recognized by default for prompt line dropping.
+pub fn default_shells() -> Vec<ShellPattern> {
+    DETECTED_SHELLS
+        .iter()
+        .map(|name| ShellPattern::from_str(name).expect("valid shell name"))
+        .collect()
+}
+
+/// A shell recognized for prompt line dropping, optionally with its own number of prompt lines.
 ///
-pub async fn save<P: AsRef<Path>>(
-    backup_dirpath: P,
-    num_lines_to_drop: usize,
-) -> Result<(PathBuf, v1::Overview)> {
-    // Prepare the temp directory.
-    let temp_dir = TempDir::new()?;
+/// It is written `PATTERN` or `PATTERN=LINES`, such as `bash`, `zsh=2` or `re:^x?onsh$=1`. The
+/// [`NamePattern`] is matched against the program name of the pane, without its directory and
+/// without the leading `-` of login shells: `zsh` matches `zsh`, `-zsh` and `/bin/zsh`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ShellPattern {
+    /// Pattern of the program name.
+    pattern: NamePattern,
+
+    /// Number of lines of the prompt of this shell, which replaces the default number.
+    prompt_lines: Option<u8>,
+}
 
-    // Save sessions & windows into `metadata.json` in the temp folder.
-    let metadata_task: smol::Task<Result<(PathBuf, PathBuf, u16, u16)>> = {
-        let temp_dirpath = temp_dir.path().to_path_buf();
+impl ShellPattern {
+    /// Return `true` if the program `command` of a pane is this shell.
+    pub fn is_match(&self, command: &str) -> bool {
+        is_program_match(&self.pattern, command)
+    }
+}
 
-        smol::spawn(async move {
-            let temp_version_filepath = temp_dirpath.join(v1::VERSION_FILENAME);
-            fs::write(&temp_version_filepath, v1::FORMAT_VERSION).await?;
+impl FromStr for ShellPattern {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let (pattern, prompt_lines) = match source.rsplit_once('=') {
+            Some((pattern, lines)) => match lines.parse() {
+                Ok(lines) => (pattern, Some(lines)),
+                Err(_) => (source, None),
+            },
+            _ => (source, None),
+        };
+        if pattern.is_empty() {
+            return Err("empty shell pattern".to_string());
+        }
 
-            let metadata = v1::Metadata::new().await?;
+        let pattern = pattern
+            .parse()
+            .map_err(|e| format!("invalid shell pattern `{pattern}`: {e}"))?;
+        Ok(Self {
+            pattern,
+            prompt_lines,
+        })
+    }
+}
+
+impl TryFrom<String> for ShellPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
 
-            let json = serde_json::to_string(&metadata)?;
+impl From<ShellPattern> for String {
+    fn from(shell_pattern: ShellPattern) -> Self {
+        shell_pattern.to_string()
+    }
+}
 
-            let temp_metadata_filepath = temp_dirpath.join(v1::METADATA_FILENAME);
-            fs::write(temp_metadata_filepath.as_path(), json).await?;
+impl fmt::Display for ShellPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prompt_lines {
+            Some(lines) => write!(f, "{}={lines}", self.pattern),
+            None => write!(f, "{}", self.pattern),
+        }
+    }
+}
 
-            Ok((
-                temp_version_filepath,
-                temp_metadata_filepath,
-                metadata.sessions.len() as u16,
-                metadata.windows.len() as u16,
-            ))
+/// The depth of history captured in the panes running a program.
+///
+/// It is written `PATTERN=DEPTH`, such as `htop=visible` or `re:^(zsh|bash)$=all`, where the
+/// depth is `all`, `visible` or a number of lines. The [`NamePattern`] is matched against the
+/// program name of the pane as for [`ShellPattern`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HistoryOverride {
+    /// Pattern of the program name.
+    pattern: NamePattern,
+
+    /// Depth of the history captured in the matching panes.
+    history_lines: HistoryLines,
+}
+
+impl HistoryOverride {
+    /// Return `true` if the program `command` of a pane matches this override.
+    pub fn is_match(&self, command: &str) -> bool {
+        is_program_match(&self.pattern, command)
+    }
+}
+
+impl FromStr for HistoryOverride {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let Some((pattern, history_lines)) = source.rsplit_once('=') else {
+            return Err(format!("expected `PATTERN=DEPTH`, not `{source}`"));
+        };
+        if pattern.is_empty() {
+            return Err("empty program pattern".to_string());
+        }
+
+        let pattern = pattern
+            .parse()
+            .map_err(|e| format!("invalid program pattern `{pattern}`: {e}"))?;
+        Ok(Self {
+            pattern,
+            history_lines: history_lines.parse()?,
         })
-    };
-
-    // Save pane contents in the temp folder.
-    let (temp_panes_content_dir, num_panes) = {
-        let temp_panes_content_dir = temp_dir.path().join(v1::PANES_DIR_NAME);
-        fs::create_dir_all(&temp_panes_content_dir).await?;
-
-        let panes = tmux::pane::available_panes().await?;
-        let num_panes = panes.len() as u16;
-        save_panes_content(panes, &temp_panes_content_dir, num_lines_to_drop).await?;
-
-        (temp_panes_content_dir, num_panes)
-    };
-    let (temp_version_filepath, temp_metadata_filepath, num_sessions, num_windows) =
-        metadata_task.await?;
-
-    // Tar-compress content of temp folder into a new backup file in `backup_dirpath`.
-    let new_backup_filepath = v1::new_backup_filepath(backup_dirpath.as_ref());
-
-    v1::create_from_paths(
-        &new_backup_filepath,
-        &temp_version_filepath,
-        &temp_metadata_filepath,
-        &temp_panes_content_dir,
-    )?;
-
-    // Cleanup the entire temp folder.
-    temp_dir.close()?;
-
-    let overview = v1::Overview {
-        version: v1::FORMAT_VERSION.to_string(),
-        num_sessions,
-        num_windows,
-        num_panes,
-    };
-
-    Ok((new_backup_filepath, overview))
+    }
+}
+
+impl TryFrom<String> for HistoryOverride {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HistoryOverride> for String {
+    fn from(history_override: HistoryOverride) -> Self {
+        history_override.to_string()
+    }
+}
+
+impl fmt::Display for HistoryOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.pattern, self.history_lines)
+    }
+}
+
+/// Return `true` if `pattern` matches the program name of the pane `command`.
+fn is_program_match(pattern: &NamePattern, command: &str) -> bool {
+    let name = program_name(command);
+    !name.is_empty() && pattern.is_match(name)
+}
+
+/// Return the program name of the pane `command`, without its directory and without the leading
+/// `-` of login shells.
+fn program_name(command: &str) -> &str {
+    let name = command.rsplit('/').next().unwrap_or(command);
+    name.strip_prefix('-').unwrap_or(name)
+}
+
+/// Options of the capture of panes, shared by save and autosave.
+#[derive(Debug, Clone)]
+pub struct SaveOptions {
+    /// Number of lines to drop at the end of the panes running a shell, usually the prompt.
+    pub num_lines_to_drop: usize,
+
+    /// Shells whose prompt lines are dropped.
+    pub shells: Vec<ShellPattern>,
+
+    /// Pattern of the first line of the prompt, used instead of the number of prompt lines.
+    ///
+    /// In the panes running a shell, the last line matching this pattern and the lines below it
+    /// are dropped: the prompt and any partially typed command. If no line matches, the number
+    /// of prompt lines applies.
+    pub prompt_pattern: Option<Regex>,
+
+    /// Capture the escape sequences of colours and attributes along with the text, so that
+    /// restored panes look the same. Otherwise, the panes content is plain text.
+    pub escapes: bool,
+
+    /// Depth of the history captured in the panes.
+    pub history_lines: HistoryLines,
+
+    /// Depth of the history captured in the panes running some programs, which replaces
+    /// `history_lines`. The first matching override applies.
+    pub history_overrides: Vec<HistoryOverride>,
+
+    /// Names of the sessions to save.
+    pub sessions: NameFilter,
+
+    /// Names of the windows to save.
+    pub windows: NameFilter,
+
+    /// Program names of the panes to save, matched as for [`ShellPattern`].
+    pub commands: NameFilter,
+
+    /// Detectors of the secrets redacted from the panes content and command lines.
+    pub redactor: Redactor,
+
+    /// Encryption of the backup, which then holds its panes content.
+    pub encryption: Option<Encryption>,
 }
 
-/// Determine if the given command is a recognized shell.
+impl SaveOptions {
+    /// Leave out of `metadata` the sessions, windows and panes which are not selected.
+    ///
+    /// Return an error if no session is left to save.
+    pub(crate) fn retain_selected(&self, metadata: &mut archive::Metadata) -> Result<()> {
+        if self.sessions.is_empty() && self.windows.is_empty() && self.commands.is_empty() {
+            return Ok(());
+        }
+
+        metadata.retain(
+            |session| self.sessions.is_match(&session.name),
+            |window| self.windows.is_match(&window.name),
+            |pane| self.commands.is_match(program_name(&pane.command)),
+        );
+        if metadata.sessions.is_empty() {
+            return Err(Error::ConfigError(
+                "the filters leave out every session".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            num_lines_to_drop: 0,
+            shells: default_shells(),
+            prompt_pattern: None,
+            escapes: true,
+            history_lines: HistoryLines::All,
+            history_overrides: vec![],
+            sessions: NameFilter::default(),
+            windows: NameFilter::default(),
+            commands: NameFilter::default(),
+            redactor: Redactor::default(),
+            encryption: None,
+        }
+    }
+}
+
+/// Save the tmux sessions, windows and panes into a backup at `backup_dirpath`, with an optional
+/// `label` and `note`.
+///
+/// After saving, this function returns the path to the backup, the number of sessions, windows
+/// and panes, and the number of redacted secrets.
+///
+/// # Notes
+///
+/// - The `backup_dirpath` folder is assumed to exist (done during catalog initialization).
+/// - Backups have a name similar to `backup-20220731T222948.tar.zst`, or
+///   `backup-20220731T222948.tar.zst.age` if encrypted. The label follows the timestamp, as in
+///   `backup-20220731T222948-my-label.tar.zst`, and is also stored in the metadata along with the
+///   note.
+/// - The panes content is written to the content store of `backup_dirpath`, and only referenced
+///   by the backup, unless the backup is encrypted.
 ///
-/// Used to decide whether to drop trailing lines (shell prompt) when capturing pane content.
-fn is_shell_command(command: &str) -> bool {
-    DETECTED_SHELLS.contains(&command)
+pub async fn save<P: AsRef<Path>>(
+    backup_dirpath: P,
+    options: &SaveOptions,
+    label: Option<&Label>,
+    note: Option<&str>,
+) -> Result<(PathBuf, archive::Overview, Redactions)> {
+    let backup_dirpath = backup_dirpath.as_ref();
+
+    let mut metadata = archive::Metadata::new().await?;
+    options.retain_selected(&mut metadata)?;
+    metadata.label = label.map(|label| label.to_string());
+    metadata.note = note.map(str::to_string);
+
+    // Write the backup to a temporary file, named as a backup only once complete.
+    let temp_archive = NamedTempFile::new_in(backup_dirpath)?;
+    let redactions = write_backup(
+        temp_archive.reopen()?,
+        &mut metadata,
+        backup_dirpath,
+        options,
+    )
+    .await?;
+    let new_backup_filepath =
+        archive::new_backup_filepath(backup_dirpath, options.encryption.is_some(), label);
+    temp_archive
+        .persist(&new_backup_filepath)
+        .map_err(|error| Error::from(error.error))?;
+
+    Ok((new_backup_filepath, metadata.overview(), redactions))
+}
+
+/// Capture the panes content of `metadata`, and write the backup archive into `archive`.
+///
+/// The panes content is written to the content store of `backup_dirpath`. If the backup is
+/// encrypted, it is written to a temporary store instead, whose blobs go into the archive.
+pub(crate) async fn write_backup(
+    archive: std::fs::File,
+    metadata: &mut archive::Metadata,
+    backup_dirpath: &Path,
+    options: &SaveOptions,
+) -> Result<Redactions> {
+    let temp_dir = options
+        .encryption
+        .as_ref()
+        .map(|_| TempDir::new())
+        .transpose()?;
+    let store = Store::new(temp_dir.as_ref().map_or(backup_dirpath, TempDir::path));
+
+    let (pane_metadata, redactions) =
+        store_panes_content(metadata.panes.clone(), &store, options).await?;
+    metadata.pane_metadata = pane_metadata;
+    metadata.escapes = options.escapes;
+
+    match &options.encryption {
+        Some(encryption) => v2::create_encrypted_from_file(archive, metadata, &store, encryption)?,
+        None => v2::create_from_file(archive, metadata)?,
+    }
+
+    Ok(redactions)
 }
 
 /// Calculate how many lines to drop from pane capture based on the active command.
 ///
-/// If the pane is running a recognized shell, we drop `num_lines_to_drop` lines
-/// to avoid capturing the shell prompt. For other commands, we keep everything.
-fn lines_to_drop_for_pane(pane_command: &str, num_lines_to_drop: usize) -> usize {
-    if is_shell_command(pane_command) {
-        num_lines_to_drop
-    } else {
-        0
+/// If the pane is running one of the `shells`, we drop its number of prompt lines, by default
+/// `num_lines_to_drop`, to avoid capturing the shell prompt. For other commands, we keep
+/// everything. The first matching shell applies.
+fn lines_to_drop_for_pane(
+    pane_command: &str,
+    num_lines_to_drop: usize,
+    shells: &[ShellPattern],
+) -> usize {
+    match shells.iter().find(|shell| shell.is_match(pane_command)) {
+        Some(shell) => shell
+            .prompt_lines
+            .map_or(num_lines_to_drop, |lines| lines as usize),
+        None => 0,
     }
 }
 
-/// For each provided pane, retrieve the content and save it into `destination_dir`.
-pub(crate) async fn save_panes_content<P: AsRef<Path>>(
+/// Return the depth of the history to capture in a pane running `pane_command`.
+fn history_lines_for_pane(
+    pane_command: &str,
+    history_lines: HistoryLines,
+    overrides: &[HistoryOverride],
+) -> HistoryLines {
+    overrides
+        .iter()
+        .find(|history_override| history_override.is_match(pane_command))
+        .map_or(history_lines, |history_override| {
+            history_override.history_lines
+        })
+}
+
+/// For each provided pane, retrieve the content and write it into the content `store`.
+///
+/// Return the metadata of each pane, referencing its content in the store, along with the command
+/// line of its foreground process, and the number of secrets redacted from both.
+pub(crate) async fn store_panes_content(
     panes: Vec<tmux::pane::Pane>,
-    destination_dir: P,
-    num_lines_to_drop: usize,
-) -> Result<()> {
+    store: &Store,
+    options: &SaveOptions,
+) -> Result<(Vec<archive::PaneMetadata>, Redactions)> {
+    let pane_pids = process::pane_pids().await?;
     let mut handles = Vec::new();
 
     for pane in panes {
-        let dest_dir = destination_dir.as_ref().to_path_buf();
-        let drop_n_last_lines = lines_to_drop_for_pane(&pane.command, num_lines_to_drop);
+        let store = store.clone();
+        let drop_n_last_lines =
+            lines_to_drop_for_pane(&pane.command, options.num_lines_to_drop, &options.shells);
+        let is_shell = options
+            .shells
+            .iter()
+            .any(|shell| shell.is_match(&pane.command));
+        let prompt_pattern = options.prompt_pattern.clone().filter(|_| is_shell);
+        let pane_pid = pane_pids.get(&pane.id).copied();
+        let escapes = options.escapes;
+        let redactor = options.redactor.clone();
+        let history_lines = history_lines_for_pane(
+            &pane.command,
+            options.history_lines,
+            &options.history_overrides,
+        );
 
         let handle = smol::spawn(async move {
-            let stdout = pane.capture().await?;
-            let cleaned_buffer = cleanup_captured_buffer(&stdout, drop_n_last_lines);
-
-            let filename = format!("pane-{}.txt", pane.id);
-            let filepath = dest_dir.join(filename);
-            fs::write(filepath, cleaned_buffer).await?;
-            Ok(())
+            let mut redactions = Redactions::default();
+            let argv = pane_pid
+                .and_then(process::foreground_argv)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|arg| {
+                    let (arg, arg_redactions) = redactor.redact(arg.as_bytes());
+                    redactions.extend(arg_redactions);
+                    String::from_utf8_lossy(&arg).into_owned()
+                })
+                .collect();
+            let stdout = capture(&pane, escapes, history_lines).await?;
+            let drop_n_last_lines = prompt_pattern
+                .and_then(|pattern| lines_from_last_prompt(&stdout, &pattern))
+                .unwrap_or(drop_n_last_lines);
+            let mut cleaned_buffer = cleanup_captured_buffer(&stdout, drop_n_last_lines);
+            if !escapes {
+                strip_reset_code(&mut cleaned_buffer);
+            }
+            if !redactor.is_empty() {
+                let (redacted_buffer, content_redactions) = redactor.redact(&cleaned_buffer);
+                cleaned_buffer = redacted_buffer;
+                redactions.extend(content_redactions);
+            }
+
+            let digest = store.write(&cleaned_buffer).await?;
+            let pane_metadata = archive::PaneMetadata {
+                pane_id: pane.id,
+                digest,
+                size: cleaned_buffer.len() as u64,
+                argv,
+                history_lines,
+            };
+            Ok::<_, Error>((pane_metadata, redactions))
         });
         handles.push(handle);
     }
 
-    join_all(handles)
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>>>()?;
-    Ok(())
+    let mut pane_metadata = vec![];
+    let mut redactions = Redactions::default();
+    for result in join_all(handles).await {
+        let (metadata, pane_redactions) = result?;
+        pane_metadata.push(metadata);
+        redactions.extend(pane_redactions);
+    }
+
+    Ok((pane_metadata, redactions))
+}
+
+/// Return the content of `pane`, with the escape sequences of colours and attributes if
+/// `escapes` is `true`, and the history above its visible lines up to `history_lines`.
+///
+/// This is [`tmux::pane::Pane::capture`] with a configurable `capture-pane` command.
+async fn capture(
+    pane: &tmux::pane::Pane,
+    escapes: bool,
+    history_lines: HistoryLines,
+) -> Result<Vec<u8>> {
+    let start_line = history_lines.start_line();
+    let mut args = vec![
+        "capture-pane",
+        "-t",
+        pane.id.as_str(),
+        "-J", // preserves trailing spaces & joins any wrapped lines
+        "-p", // output goes to stdout
+        "-S", // starting line number
+        &start_line,
+        "-E", // ending line number
+        "-",  // end of history
+    ];
+    if escapes {
+        args.push("-e"); // include escape sequences for text & background
+    }
+
+    let output = smol::process::Command::new("tmux")
+        .args(&args)
+        .output()
+        .await?;
+
+    Ok(output.stdout)
+}
+
+/// Remove the reset code which tmux-lib appends to the last line of a cleaned buffer.
+fn strip_reset_code(buffer: &mut Vec<u8>) {
+    const RESET_LINE_END: &[u8] = b"\x1b[0m\n";
+
+    if buffer.ends_with(RESET_LINE_END) {
+        buffer.truncate(buffer.len() - RESET_LINE_END.len());
+        buffer.push(b'\n');
+    }
 }
 
 /// Clean a captured pane buffer while safely limiting the number of trailing lines to drop.
@@ -150,6 +506,35 @@ fn cleanup_captured_buffer(buffer: &[u8], requested_drop_count: usize) -> Vec<u8
     utils::cleanup_captured_buffer(buffer, requested_drop_count.min(available_line_count))
 }
 
+/// Return the number of trailing lines to drop in order to cut the `buffer` at its last line
+/// matching the prompt `pattern`, or `None` if no line matches.
+///
+/// The lines are counted as in [`captured_line_count`], so the result can be passed to
+/// [`cleanup_captured_buffer`].
+fn lines_from_last_prompt(buffer: &[u8], pattern: &Regex) -> Option<usize> {
+    let line_count = captured_line_count(buffer);
+    let lines: Vec<&[u8]> = buffer
+        .split(|byte| *byte == b'\n')
+        .take(line_count)
+        .collect();
+    lines
+        .iter()
+        .rposition(|line| {
+            let line = String::from_utf8_lossy(line);
+            pattern.is_match(strip_escapes(&line).trim_end())
+        })
+        .map(|prompt_index| line_count - prompt_index)
+}
+
+/// Remove the escape sequences of colours, attributes and hyperlinks from a captured `line`, so
+/// that prompt patterns match the visible text.
+pub(crate) fn strip_escapes(line: &str) -> Cow<'_, str> {
+    static ESCAPE_RE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\x1b\[[0-9;:]*[A-Za-z]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)").unwrap()
+    });
+    ESCAPE_RE.replace_all(line, "")
+}
+
 /// Return the number of lines retained by tmux-lib before it drops requested trailing lines.
 fn captured_line_count(buffer: &[u8]) -> usize {
     let lines: Vec<_> = buffer
@@ -170,54 +555,82 @@ mod tests {
     mod shell_detection {
         use super::*;
 
+        fn is_shell_command(command: &str, shells: &[ShellPattern]) -> bool {
+            shells.iter().any(|shell| shell.is_match(command))
+        }
+
+        fn shells(sources: &[&str]) -> Vec<ShellPattern> {
+            sources.iter().map(|s| s.parse().unwrap()).collect()
+        }
+
         #[test]
         fn recognizes_zsh() {
-            assert!(is_shell_command("zsh"));
+            assert!(is_shell_command("zsh", &default_shells()));
         }
 
         #[test]
         fn recognizes_bash() {
-            assert!(is_shell_command("bash"));
+            assert!(is_shell_command("bash", &default_shells()));
         }
 
         #[test]
         fn recognizes_fish() {
-            assert!(is_shell_command("fish"));
+            assert!(is_shell_command("fish", &default_shells()));
         }
 
         #[test]
         fn rejects_vim() {
-            assert!(!is_shell_command("vim"));
+            assert!(!is_shell_command("vim", &default_shells()));
         }
 
         #[test]
         fn rejects_nvim() {
-            assert!(!is_shell_command("nvim"));
+            assert!(!is_shell_command("nvim", &default_shells()));
         }
 
         #[test]
         fn rejects_python() {
-            assert!(!is_shell_command("python"));
+            assert!(!is_shell_command("python", &default_shells()));
         }
 
         #[test]
         fn rejects_empty_command() {
-            assert!(!is_shell_command(""));
+            assert!(!is_shell_command("", &default_shells()));
         }
 
         #[test]
         fn rejects_similar_but_different() {
             // Shell name as substring shouldn't match
-            assert!(!is_shell_command("zsh-5.9"));
-            assert!(!is_shell_command("/bin/zsh"));
-            assert!(!is_shell_command("bash-5.2"));
+            assert!(!is_shell_command("zsh-5.9", &default_shells()));
+            assert!(!is_shell_command("bash-5.2", &default_shells()));
+            assert!(!is_shell_command("/bin/zsh-5.9", &default_shells()));
+        }
+
+        #[test]
+        fn matches_the_program_name_of_paths_and_login_shells() {
+            assert!(is_shell_command("/bin/bash", &default_shells()));
+            assert!(is_shell_command("-zsh", &default_shells()));
+            assert!(is_shell_command("/usr/local/bin/-fish", &default_shells()));
+            assert!(!is_shell_command("-", &default_shells()));
+        }
+
+        #[test]
+        fn matches_configured_globs_and_regexes() {
+            let shells = shells(&["nu", "re:^x?onsh$", "elv*"]);
+
+            assert!(is_shell_command("nu", &shells));
+            assert!(is_shell_command("xonsh", &shells));
+            assert!(is_shell_command("onsh", &shells));
+            assert!(is_shell_command("/opt/bin/elvish", &shells));
+            assert!(!is_shell_command("zsh", &shells));
+            assert!(!is_shell_command("nushell", &shells));
         }
 
         #[test]
         fn case_sensitive() {
-            assert!(!is_shell_command("ZSH"));
-            assert!(!is_shell_command("BASH"));
-            assert!(!is_shell_command("Fish"));
+            assert!(!is_shell_command("ZSH", &default_shells()));
+            assert!(!is_shell_command("BASH", &default_shells()));
+            assert!(!is_shell_command("Fish", &default_shells()));
         }
     }
 
@@ -237,6 +650,82 @@ mod tests {
 
             assert!(cleaned.is_empty());
         }
+
+        #[test]
+        fn plain_text_has_no_reset_code() {
+            let mut cleaned = cleanup_captured_buffer(b"one\ntwo\n\n", 0);
+            strip_reset_code(&mut cleaned);
+
+            assert_eq!(cleaned, b"one\ntwo\n");
+        }
+    }
+
+    mod prompt_pattern {
+        use super::*;
+
+        fn cleaned(buffer: &[u8], pattern: &str) -> String {
+            let pattern = Regex::new(pattern).unwrap();
+            let drop_n_last_lines = lines_from_last_prompt(buffer, &pattern).unwrap_or(0);
+            String::from_utf8(cleanup_captured_buffer(buffer, drop_n_last_lines)).unwrap()
+        }
+
+        #[test]
+        fn cuts_at_the_last_prompt() {
+            let buffer = b"$ make\nok\n$ ls\nsrc\n$ \n\n\n";
+
+            assert_eq!(cleaned(buffer, r"^\$ ?"), "$ make\nok\n$ ls\nsrc\x1b[0m\n");
+        }
+
+        #[test]
+        fn drops_multiline_prompts_and_typed_input() {
+            let buffer = "\
+~/src/app on main [!]
+❯ cargo test
+test result: ok
+~/src/app on main [!] took 3s
+❯ git comm
+"
+            .as_bytes();
+
+            assert_eq!(
+                cleaned(buffer, r"^~/\S+ on "),
+                "~/src/app on main [!]\n❯ cargo test\ntest result: ok\x1b[0m\n"
+            );
+        }
+
+        #[test]
+        fn no_match_keeps_every_line() {
+            let pattern = Regex::new("^> ").unwrap();
+
+            assert_eq!(lines_from_last_prompt(b"one\ntwo\n", &pattern), None);
+        }
+
+        #[test]
+        fn a_prompt_on_the_first_line_drops_everything() {
+            let pattern = Regex::new(r"^\$").unwrap();
+
+            assert_eq!(lines_from_last_prompt(b"$ \n\n", &pattern), Some(1));
+            assert_eq!(cleaned(b"$ \n\n", r"^\$"), "");
+        }
+
+        #[test]
+        fn matches_prompts_without_their_escape_sequences() {
+            let buffer =
+                b"\x1b[1m\x1b[35m\xe2\x9d\xaf\x1b[0m make\nok\n\x1b[35m\xe2\x9d\xaf\x1b[39m git comm\n";
+
+            assert_eq!(
+                cleaned(buffer, "^❯ "),
+                "\x1b[1m\x1b[35m❯\x1b[0m make\nok\x1b[0m\n"
+            );
+        }
+
+        #[test]
+        fn strips_colours_and_hyperlinks() {
+            let line = "\x1b[38;5;208mwarn\x1b[0m: \x1b]8;;file:///tmp/a\x1b\\a\x1b]8;;\x1b\\";
+
+            assert_eq!(strip_escapes(line), "warn: a");
+            assert!(matches!(strip_escapes("plain"), Cow::Borrowed("plain")));
+        }
     }
 
     mod lines_to_drop {
@@ -244,22 +733,232 @@ mod tests {
 
         #[test]
         fn drops_lines_for_shells() {
-            assert_eq!(lines_to_drop_for_pane("zsh", 2), 2);
-            assert_eq!(lines_to_drop_for_pane("bash", 3), 3);
-            assert_eq!(lines_to_drop_for_pane("fish", 1), 1);
+            assert_eq!(lines_to_drop_for_pane("zsh", 2, &default_shells()), 2);
+            assert_eq!(lines_to_drop_for_pane("bash", 3, &default_shells()), 3);
+            assert_eq!(lines_to_drop_for_pane("fish", 1, &default_shells()), 1);
         }
 
         #[test]
         fn zero_drop_for_non_shells() {
-            assert_eq!(lines_to_drop_for_pane("vim", 5), 0);
-            assert_eq!(lines_to_drop_for_pane("python", 10), 0);
-            assert_eq!(lines_to_drop_for_pane("htop", 3), 0);
+            assert_eq!(lines_to_drop_for_pane("vim", 5, &default_shells()), 0);
+            assert_eq!(lines_to_drop_for_pane("python", 10, &default_shells()), 0);
+            assert_eq!(lines_to_drop_for_pane("htop", 3, &default_shells()), 0);
+        }
+
+        #[test]
+        fn drops_lines_for_configured_shells() {
+            let shells: Vec<ShellPattern> = ["nu", "xonsh"].map(|s| s.parse().unwrap()).to_vec();
+
+            assert_eq!(lines_to_drop_for_pane("nu", 2, &shells), 2);
+            assert_eq!(lines_to_drop_for_pane("zsh", 2, &shells), 0);
+        }
+
+        #[test]
+        fn per_shell_prompt_lines_replace_the_default() {
+            let shells: Vec<ShellPattern> = ["zsh=2", "bash", "re:^x?onsh$=0"]
+                .map(|s| s.parse().unwrap())
+                .to_vec();
+
+            assert_eq!(lines_to_drop_for_pane("-zsh", 1, &shells), 2);
+            assert_eq!(lines_to_drop_for_pane("/bin/bash", 1, &shells), 1);
+            assert_eq!(lines_to_drop_for_pane("xonsh", 1, &shells), 0);
+        }
+
+        #[test]
+        fn first_matching_shell_applies() {
+            let shells: Vec<ShellPattern> = ["zsh=3", "*sh=1"].map(|s| s.parse().unwrap()).to_vec();
+
+            assert_eq!(lines_to_drop_for_pane("zsh", 0, &shells), 3);
+            assert_eq!(lines_to_drop_for_pane("bash", 0, &shells), 1);
         }
 
         #[test]
         fn zero_requested_means_zero_dropped() {
-            assert_eq!(lines_to_drop_for_pane("zsh", 0), 0);
-            assert_eq!(lines_to_drop_for_pane("bash", 0), 0);
+            assert_eq!(lines_to_drop_for_pane("zsh", 0, &default_shells()), 0);
+            assert_eq!(lines_to_drop_for_pane("bash", 0, &default_shells()), 0);
+        }
+    }
+
+    mod shell_pattern {
+        use super::*;
+
+        #[test]
+        fn parses_the_number_of_prompt_lines() {
+            let shell = ShellPattern::from_str("zsh=2").unwrap();
+
+            assert_eq!(shell.prompt_lines, Some(2));
+            assert!(shell.is_match("zsh"));
+        }
+
+        #[test]
+        fn equal_signs_of_regexes_are_kept() {
+            let shell = ShellPattern::from_str("re:^a=b$").unwrap();
+
+            assert_eq!(shell.prompt_lines, None);
+            assert!(shell.is_match("a=b"));
+        }
+
+        #[test]
+        fn rejects_empty_and_invalid_patterns() {
+            assert!(ShellPattern::from_str("").is_err());
+            assert!(ShellPattern::from_str("=2").is_err());
+            assert!(ShellPattern::from_str("re:(unclosed").is_err());
+        }
+
+        #[test]
+        fn displays_as_written() {
+            for source in ["zsh", "zsh=2", "re:^x?onsh$=1"] {
+                assert_eq!(ShellPattern::from_str(source).unwrap().to_string(), source);
+            }
+        }
+    }
+
+    mod history_lines {
+        use super::*;
+
+        fn overrides(sources: &[&str]) -> Vec<HistoryOverride> {
+            sources.iter().map(|s| s.parse().unwrap()).collect()
+        }
+
+        #[test]
+        fn parses_the_depth_of_overrides() {
+            let history_override = HistoryOverride::from_str("htop=visible").unwrap();
+
+            assert_eq!(history_override.history_lines, HistoryLines::Visible);
+            assert!(history_override.is_match("/usr/bin/htop"));
+        }
+
+        #[test]
+        fn rejects_invalid_overrides() {
+            assert!(HistoryOverride::from_str("htop").is_err());
+            assert!(HistoryOverride::from_str("=all").is_err());
+            assert!(HistoryOverride::from_str("htop=some").is_err());
+            assert!(HistoryOverride::from_str("re:(=all").is_err());
+        }
+
+        #[test]
+        fn displays_as_written() {
+            for source in ["htop=visible", "re:^(zsh|bash)$=all", "cargo=5000"] {
+                assert_eq!(
+                    HistoryOverride::from_str(source).unwrap().to_string(),
+                    source
+                );
+            }
+        }
+
+        #[test]
+        fn first_matching_override_applies() {
+            let overrides = overrides(&["htop=visible", "re:sh$=all", "bash=10"]);
+
+            assert_eq!(
+                history_lines_for_pane("htop", HistoryLines::Lines(500), &overrides),
+                HistoryLines::Visible
+            );
+            assert_eq!(
+                history_lines_for_pane("-bash", HistoryLines::Lines(500), &overrides),
+                HistoryLines::All
+            );
+        }
+
+        #[test]
+        fn other_panes_use_the_default_depth() {
+            let overrides = overrides(&["htop=visible"]);
+
+            assert_eq!(
+                history_lines_for_pane("cargo", HistoryLines::Lines(500), &overrides),
+                HistoryLines::Lines(500)
+            );
+        }
+    }
+
+    mod filters {
+        use std::str::FromStr;
+
+        use super::*;
+        use crate::tmux::{client::Client, pane::Pane, session::Session, window::Window};
+
+        fn metadata() -> archive::Metadata {
+            archive::Metadata {
+                version: archive::FORMAT_VERSION.to_string(),
+                client: Client::from_str("'work':'tmux-backup'").unwrap(),
+                sessions: ["$1:'work':/tmp", "$2:'tmux-backup':/tmp"]
+                    .map(|s| Session::from_str(s).unwrap())
+                    .to_vec(),
+                windows: [
+                    "@1:0:true:035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}:'editor':'work'",
+                    "@2:0:true:64f1,334x85,0,0,3:'popup':'tmux-backup'",
+                ]
+                .map(|w| Window::from_str(w).unwrap())
+                .to_vec(),
+                panes: [
+                    "%1:0:true:'host':'nvim':/tmp",
+                    "%2:1:false:'host':'-bash':/tmp",
+                    "%3:0:true:'host':'tmux-backup':/tmp",
+                ]
+                .map(|p| Pane::from_str(p).unwrap())
+                .to_vec(),
+                pane_metadata: vec![],
+                escapes: true,
+                label: None,
+                note: None,
+            }
+        }
+
+        fn filter(include: &[&str], exclude: &[&str]) -> NameFilter {
+            let patterns = |sources: &[&str]| sources.iter().map(|p| p.parse().unwrap()).collect();
+            NameFilter {
+                include: patterns(include),
+                exclude: patterns(exclude),
+            }
+        }
+
+        #[test]
+        fn without_filters_everything_is_saved() {
+            let mut metadata = metadata();
+            SaveOptions::default()
+                .retain_selected(&mut metadata)
+                .unwrap();
+
+            assert_eq!(metadata.sessions.len(), 2);
+            assert_eq!(metadata.panes.len(), 3);
+        }
+
+        #[test]
+        fn excluded_sessions_are_left_out() {
+            let mut metadata = metadata();
+            let options = SaveOptions {
+                sessions: filter(&[], &["tmux-backup"]),
+                ..Default::default()
+            };
+            options.retain_selected(&mut metadata).unwrap();
+
+            assert_eq!(metadata.sessions.len(), 1);
+            assert_eq!(metadata.windows.len(), 1);
+            assert_eq!(metadata.panes.len(), 2);
+        }
+
+        #[test]
+        fn commands_are_matched_by_program_name() {
+            let mut metadata = metadata();
+            let options = SaveOptions {
+                commands: filter(&[], &["bash"]),
+                ..Default::default()
+            };
+            options.retain_selected(&mut metadata).unwrap();
+
+            let pane_ids: Vec<_> = metadata.panes.iter().map(|p| p.id.as_str()).collect();
+            assert_eq!(pane_ids, ["%1", "%3"]);
+        }
+
+        #[test]
+        fn leaving_out_every_session_is_an_error() {
+            let mut metadata = metadata();
+            let options = SaveOptions {
+                windows: filter(&["nope"], &[]),
+                ..Default::default()
+            };
+
+            assert!(options.retain_selected(&mut metadata).is_err());
         }
     }
 
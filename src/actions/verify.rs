@@ -0,0 +1,466 @@
+//! Check the integrity of backups.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+use async_fs as fs;
+
+use crate::{
+    Result,
+    management::archive::{
+        self, METADATA_FILENAME, Metadata, PANES_DIR_NAME, PaneMetadata, VERSION_FILENAME, digest,
+        encryption, reader, store::Store, v1,
+    },
+    tmux,
+};
+
+/// Outcome of the verification of a backup.
+#[derive(Debug)]
+pub struct Verification {
+    /// Content of the backup, if its metadata could be read.
+    pub overview: Option<archive::Overview>,
+
+    /// Problems found in the backup, empty if it is intact.
+    pub problems: Vec<String>,
+}
+
+impl Verification {
+    /// Return `true` if no problem was found.
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check the backup at `backup_filepath`.
+///
+/// The whole archive is decoded, which checks the zstd checksums of recent archives, and
+/// its metadata is decoded and checked against the sessions, windows and panes model. The content
+/// of each pane must be present, with the digest and size recorded at save time, and no file of
+/// the archive may be left unreferenced.
+///
+/// Corruption is reported in the returned problems. This only returns an error if the backup
+/// cannot be checked at all: the file cannot be opened, or cannot be decrypted with the identity
+/// file at `identity_filepath`.
+pub async fn verify<P: AsRef<Path>>(
+    backup_filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<Verification> {
+    let backup_filepath = backup_filepath.as_ref();
+    let mut problems = vec![];
+
+    let mut entries = match read_entries(backup_filepath, identity_filepath)? {
+        Ok(entries) => entries,
+        Err(problem) => {
+            return Ok(Verification {
+                overview: None,
+                problems: vec![problem],
+            });
+        }
+    };
+
+    let metadata = decode_metadata(&mut entries, &mut problems);
+    if let Some(metadata) = &metadata {
+        check_model(metadata, &mut problems);
+        if metadata.version == v1::FORMAT_VERSION {
+            check_v1_panes_content(metadata, &mut entries, &mut problems);
+        } else {
+            check_panes_content(backup_filepath, metadata, &mut entries, &mut problems).await;
+        }
+    }
+
+    for name in entries.keys() {
+        problems.push(format!("unexpected file `{name}`"));
+    }
+
+    Ok(Verification {
+        overview: metadata.map(|metadata| metadata.overview()),
+        problems,
+    })
+}
+
+/// Read every file of the archive at `backup_filepath`, keyed by path.
+///
+/// Directory entries, such as the `panes-content/` directory of v1 archives, are left out. The
+/// outer error is returned if the archive cannot be opened, the inner one if it cannot be
+/// decoded to its end.
+fn read_entries(
+    backup_filepath: &Path,
+    identity_filepath: Option<&Path>,
+) -> Result<std::result::Result<BTreeMap<String, Vec<u8>>, String>> {
+    let mut tar = reader::open_archive(backup_filepath, identity_filepath)?;
+
+    let decoded = (|| {
+        let mut entries = BTreeMap::new();
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let mut bytes = vec![];
+            entry.read_to_end(&mut bytes)?;
+            entries.insert(path, bytes);
+        }
+        Ok::<_, std::io::Error>(entries)
+    })();
+
+    Ok(decoded.map_err(|e| format!("the archive cannot be decoded: {e}")))
+}
+
+/// Remove the version and metadata files from `entries`, and return the decoded metadata.
+fn decode_metadata(
+    entries: &mut BTreeMap<String, Vec<u8>>,
+    problems: &mut Vec<String>,
+) -> Option<Metadata> {
+    let version = entries.remove(VERSION_FILENAME);
+    let bytes = entries.remove(METADATA_FILENAME);
+
+    let Some(version) = version else {
+        problems.push(format!("missing `{VERSION_FILENAME}` file"));
+        return None;
+    };
+    let Some(bytes) = bytes else {
+        problems.push(format!("missing `{METADATA_FILENAME}` file"));
+        return None;
+    };
+
+    let decoded = reader::decoder(&String::from_utf8_lossy(&version))
+        .and_then(|decoder| decoder.decode(&bytes));
+    match decoded {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            problems.push(format!("invalid `{METADATA_FILENAME}`: {e}"));
+            None
+        }
+    }
+}
+
+/// Check that each window belongs to an archived session, and each pane to an archived window.
+fn check_model(metadata: &Metadata, problems: &mut Vec<String>) {
+    let session_names: HashSet<&str> = metadata.sessions.iter().map(|s| s.name.as_str()).collect();
+
+    let mut window_pane_ids = HashSet::new();
+    for window in &metadata.windows {
+        if !window
+            .sessions
+            .iter()
+            .any(|name| session_names.contains(name.as_str()))
+        {
+            problems.push(format!(
+                "window {} belongs to no session",
+                window.id.as_str()
+            ));
+        }
+        match tmux::layout::parse_window_layout(&window.layout) {
+            Ok(layout) => {
+                window_pane_ids.extend(layout.pane_ids().iter().map(tmux::pane_id::PaneId::from))
+            }
+            Err(_) => problems.push(format!(
+                "window {} has an invalid layout",
+                window.id.as_str()
+            )),
+        }
+    }
+
+    for pane in &metadata.panes {
+        if !window_pane_ids.contains(&pane.id) {
+            problems.push(format!("pane {} belongs to no window", pane.id));
+        }
+    }
+}
+
+/// Check that each pane of a v1 archive has its content file, and remove these from `entries`.
+fn check_v1_panes_content(
+    metadata: &Metadata,
+    entries: &mut BTreeMap<String, Vec<u8>>,
+    problems: &mut Vec<String>,
+) {
+    for pane in &metadata.panes {
+        let name = format!("{PANES_DIR_NAME}/pane-{}.txt", pane.id);
+        if entries.remove(&name).is_none() {
+            problems.push(format!("missing content of pane {}", pane.id));
+        }
+    }
+}
+
+/// Check the content of each pane, read from the store next to the backup, or from `entries` if
+/// the backup is encrypted, and remove the blobs of the latter.
+async fn check_panes_content(
+    backup_filepath: &Path,
+    metadata: &Metadata,
+    entries: &mut BTreeMap<String, Vec<u8>>,
+    problems: &mut Vec<String>,
+) {
+    let pane_ids: HashSet<_> = metadata.panes.iter().map(|pane| &pane.id).collect();
+    let content_ids: HashSet<_> = metadata
+        .pane_metadata
+        .iter()
+        .map(|pane_metadata| &pane_metadata.pane_id)
+        .collect();
+    for pane in &metadata.panes {
+        if !content_ids.contains(&pane.id) {
+            problems.push(format!("missing content of pane {}", pane.id));
+        }
+    }
+
+    let encrypted = matches!(encryption::is_encrypted(backup_filepath), Ok(true));
+    let store = Store::for_backup(backup_filepath);
+    let mut checked = BTreeSet::new();
+    for pane_metadata in &metadata.pane_metadata {
+        if !pane_ids.contains(&pane_metadata.pane_id) {
+            problems.push(format!("content of unknown pane {}", pane_metadata.pane_id));
+        }
+
+        let blob_filepath = Store::relative_blob_filepath(&pane_metadata.digest);
+        let name = blob_filepath.to_string_lossy().into_owned();
+        let compressed = if encrypted {
+            // A blob shared by several panes is only stored once.
+            match entries.remove(&name) {
+                Some(compressed) => Some(compressed),
+                None if checked.contains(&name) => continue,
+                None => None,
+            }
+        } else {
+            fs::read(store.blob_filepath(&pane_metadata.digest))
+                .await
+                .ok()
+        };
+        checked.insert(name);
+
+        if let Some(problem) = check_blob(compressed, pane_metadata) {
+            problems.push(problem);
+        }
+    }
+}
+
+/// Check the blob of a pane, `None` if missing, against its recorded digest and size.
+fn check_blob(compressed: Option<Vec<u8>>, pane_metadata: &PaneMetadata) -> Option<String> {
+    let pane_id = &pane_metadata.pane_id;
+    let Some(compressed) = compressed else {
+        return Some(format!(
+            "missing content of pane {pane_id} (blob {})",
+            pane_metadata.digest
+        ));
+    };
+    let Ok(content) = zstd::decode_all(compressed.as_slice()) else {
+        return Some(format!("corrupt content of pane {pane_id}"));
+    };
+
+    if digest::sha256_hex(&content) != pane_metadata.digest {
+        Some(format!(
+            "content of pane {pane_id} does not match its digest"
+        ))
+    } else if content.len() as u64 != pane_metadata.size {
+        Some(format!("content of pane {pane_id} does not match its size"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use tempfile::TempDir;
+
+    use crate::management::archive::{
+        FORMAT_VERSION,
+        encryption::{self, Encryption},
+        v2,
+    };
+
+    const CONTENT: &[u8] = b"$ make test\n";
+
+    fn metadata() -> Metadata {
+        let pane = tmux::pane::Pane::from_str("%3:0:true:'host':'zsh':/home/user").unwrap();
+        Metadata {
+            version: FORMAT_VERSION.to_string(),
+            client: tmux::client::Client::from_str("'work':''").unwrap(),
+            sessions: vec![tmux::session::Session::from_str("$1:'work':/home/user").unwrap()],
+            windows: vec![
+                tmux::window::Window::from_str("@1:0:true:64f0,334x85,0,0,3:'shell':'work'")
+                    .unwrap(),
+            ],
+            pane_metadata: vec![PaneMetadata {
+                pane_id: pane.id.clone(),
+                digest: digest::sha256_hex(CONTENT),
+                size: CONTENT.len() as u64,
+                argv: vec![],
+                history_lines: archive::HistoryLines::All,
+            }],
+            panes: vec![pane],
+            escapes: true,
+            label: None,
+            note: None,
+        }
+    }
+
+    /// Write a backup of `metadata` in `dir`, with the content of its pane in the store.
+    fn write_backup(dir: &Path, metadata: &Metadata) -> std::path::PathBuf {
+        let filepath = dir.join("backup-20220910T172024.141993.tar.zst");
+        smol::block_on(Store::for_backup(&filepath).write(CONTENT)).unwrap();
+        v2::create_from_file(std::fs::File::create(&filepath).unwrap(), metadata).unwrap();
+        filepath
+    }
+
+    fn problems(filepath: &Path) -> Vec<String> {
+        smol::block_on(verify(filepath, None)).unwrap().problems
+    }
+
+    #[test]
+    fn intact_backups_have_no_problems() {
+        let dir = TempDir::new().unwrap();
+        let filepath = write_backup(dir.path(), &metadata());
+
+        let verification = smol::block_on(verify(&filepath, None)).unwrap();
+
+        assert!(verification.is_intact(), "{:?}", verification.problems);
+        assert_eq!(verification.overview.unwrap().num_panes, 1);
+    }
+
+    #[test]
+    fn truncated_archives_are_corrupt() {
+        let dir = TempDir::new().unwrap();
+        let filepath = write_backup(dir.path(), &metadata());
+        let bytes = std::fs::read(&filepath).unwrap();
+        std::fs::write(&filepath, &bytes[..bytes.len() / 2]).unwrap();
+
+        let verification = smol::block_on(verify(&filepath, None)).unwrap();
+
+        assert!(verification.overview.is_none());
+        assert_eq!(verification.problems.len(), 1);
+        assert!(verification.problems[0].starts_with("the archive cannot be decoded"));
+    }
+
+    #[test]
+    fn altered_archives_fail_their_checksum() {
+        let dir = TempDir::new().unwrap();
+        let filepath = write_backup(dir.path(), &metadata());
+        let mut bytes = std::fs::read(&filepath).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        std::fs::write(&filepath, &bytes).unwrap();
+
+        assert!(!problems(&filepath).is_empty());
+    }
+
+    #[test]
+    fn missing_and_altered_blobs_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let filepath = write_backup(dir.path(), &metadata());
+        let blob_filepath =
+            Store::for_backup(&filepath).blob_filepath(&digest::sha256_hex(CONTENT));
+
+        std::fs::write(&blob_filepath, zstd::encode_all(&b"other"[..], 0).unwrap()).unwrap();
+        assert_eq!(
+            problems(&filepath),
+            ["content of pane %3 does not match its digest"]
+        );
+
+        std::fs::remove_file(&blob_filepath).unwrap();
+        assert!(problems(&filepath)[0].starts_with("missing content of pane %3"));
+    }
+
+    #[test]
+    fn panes_and_their_content_must_match() {
+        let dir = TempDir::new().unwrap();
+        let mut metadata = metadata();
+        metadata.pane_metadata[0].pane_id = tmux::pane_id::PaneId::from_str("%4").unwrap();
+        let filepath = write_backup(dir.path(), &metadata);
+
+        assert_eq!(
+            problems(&filepath),
+            ["missing content of pane %3", "content of unknown pane %4"]
+        );
+    }
+
+    #[test]
+    fn panes_must_belong_to_an_archived_window() {
+        let dir = TempDir::new().unwrap();
+        let mut metadata = metadata();
+        metadata.windows.clear();
+        let filepath = write_backup(dir.path(), &metadata);
+
+        assert_eq!(problems(&filepath), ["pane %3 belongs to no window"]);
+    }
+
+    #[test]
+    fn encrypted_backups_hold_their_blobs() {
+        let capture_dir = TempDir::new().unwrap();
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(capture_dir.path());
+        smol::block_on(store.write(CONTENT)).unwrap();
+
+        let filepath = dir.path().join("backup-20220910T172024.141993.tar.zst.age");
+        let encryption = Encryption::Recipients(vec![encryption::tests::recipient()]);
+        let archive = std::fs::File::create(&filepath).unwrap();
+        v2::create_encrypted_from_file(archive, &metadata(), &store, &encryption).unwrap();
+
+        let identity_filepath = encryption::tests::identity_filepath(dir.path());
+        let verification = smol::block_on(verify(&filepath, Some(&identity_filepath))).unwrap();
+        assert!(verification.problems.is_empty());
+    }
+
+    /// Write a v1 archive of `metadata` with the content of its pane, as v1 archives were
+    /// written: the `panes-content` directory is appended with its directory entry.
+    fn write_v1_backup(dir: &Path, metadata: &Metadata) -> std::path::PathBuf {
+        let metadata = v1::Metadata {
+            version: v1::FORMAT_VERSION.to_string(),
+            client: metadata.client.clone(),
+            sessions: metadata.sessions.clone(),
+            windows: metadata.windows.clone(),
+            panes: metadata.panes.clone(),
+        };
+        let temp_dir = TempDir::new().unwrap();
+        let version_filepath = temp_dir.path().join(VERSION_FILENAME);
+        std::fs::write(&version_filepath, v1::FORMAT_VERSION).unwrap();
+        let metadata_filepath = temp_dir.path().join(METADATA_FILENAME);
+        std::fs::write(&metadata_filepath, serde_json::to_vec(&metadata).unwrap()).unwrap();
+        let panes_content_dir = temp_dir.path().join(PANES_DIR_NAME);
+        std::fs::create_dir(&panes_content_dir).unwrap();
+        for pane in &metadata.panes {
+            std::fs::write(
+                panes_content_dir.join(format!("pane-{}.txt", pane.id)),
+                CONTENT,
+            )
+            .unwrap();
+        }
+
+        let filepath = dir.join("backup-20220910T172024.141993.tar.zst");
+        let archive = std::fs::File::create(&filepath).unwrap();
+        let enc = zstd::stream::write::Encoder::new(archive, 0)
+            .unwrap()
+            .auto_finish();
+        let mut tar = tar::Builder::new(enc);
+        tar.append_path_with_name(&version_filepath, VERSION_FILENAME)
+            .unwrap();
+        tar.append_path_with_name(&metadata_filepath, METADATA_FILENAME)
+            .unwrap();
+        tar.append_dir_all(PANES_DIR_NAME, &panes_content_dir)
+            .unwrap();
+        tar.finish().unwrap();
+        filepath
+    }
+
+    #[test]
+    fn intact_v1_backups_have_no_problems() {
+        let dir = TempDir::new().unwrap();
+        let filepath = write_v1_backup(dir.path(), &metadata());
+
+        let verification = smol::block_on(verify(&filepath, None)).unwrap();
+
+        assert!(verification.is_intact(), "{:?}", verification.problems);
+        assert_eq!(verification.overview.unwrap().version, v1::FORMAT_VERSION);
+    }
+
+    #[test]
+    fn missing_files_cannot_be_verified() {
+        let dir = TempDir::new().unwrap();
+
+        let result = smol::block_on(verify(dir.path().join("backup.tar.zst"), None));
+
+        assert!(result.is_err());
+    }
+}
@@ -1,25 +1,48 @@
 //! Main runner
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use async_fs as fs;
-use clap::{CommandFactory, Parser};
+use clap::CommandFactory;
 use clap_complete::generate;
 
 use tmux_backup::{
     actions::{
-        AutosaveContext, autosave, autosave_context, display_autosave_message, restore, save,
+        AutosaveContext, DiffSide, Migration, RestoreOptions, SkippedSession, autosave,
+        autosave_context, diff, display_autosave_message, migrate, restore, restore_plan, save,
+        verify,
     },
-    config::{AutosaveTmuxOutput, CatalogSubcommand, Command, Config, StrategyConfig},
-    management::{archive::v1, catalog::Catalog},
+    config::{
+        AutosaveTmuxOutput, CatalogSubcommand, Command, Config, ConfigSubcommand, StrategyConfig,
+    },
+    management::{
+        archive::{self, v2},
+        catalog::{Catalog, Compaction},
+    },
+    redaction::Redactions,
+    report::{self, Action, Format, Report},
     tmux,
 };
 
 async fn init_catalog<P: AsRef<Path>>(
     backup_dirpath: P,
     strategy_config: StrategyConfig,
+    identity_filepath: Option<&Path>,
+    format: Format,
 ) -> Catalog {
-    match Catalog::new(&backup_dirpath.as_ref(), strategy_config.strategy()).await {
+    let strategy = strategy_config.strategy();
+    let limits = strategy_config.limits();
+    let keep_labelled = strategy_config.keep_labelled();
+    let identity_filepath = identity_filepath.map(Path::to_path_buf);
+    match Catalog::new(
+        &backup_dirpath.as_ref(),
+        strategy,
+        limits,
+        keep_labelled,
+        identity_filepath,
+    )
+    .await
+    {
         Ok(catalog) => catalog,
         Err(e) => {
             failure_message(
@@ -27,17 +50,33 @@ async fn init_catalog<P: AsRef<Path>>(
                     "🛑 Catalog cannot be created from `{}`: {e}",
                     backup_dirpath.as_ref().to_string_lossy()
                 ),
+                Report::failure(Action::Catalog, &e),
                 Output::Both,
+                format,
             );
             std::process::exit(1);
         }
     }
 }
 
+/// Return the filepaths of all backups of the `catalog`, and of its autosave archive.
+fn all_backup_filepaths(catalog: &Catalog) -> Vec<PathBuf> {
+    catalog
+        .backups
+        .iter()
+        .map(|backup| backup.filepath.clone())
+        .chain(catalog.autosave.iter().map(|a| a.filepath.clone()))
+        .collect()
+}
+
 async fn run(config: Config) {
+    let format = config.format;
+    let identity_filepath = config.identity_filepath.as_deref();
+
     match config.command {
         Command::Catalog { strategy, command } => {
-            let catalog = init_catalog(&config.backup_dirpath, strategy).await;
+            let catalog =
+                init_catalog(&config.backup_dirpath, strategy, identity_filepath, format).await;
 
             match command {
                 CatalogSubcommand::List {
@@ -46,69 +85,132 @@ async fn run(config: Config) {
                     filepaths_flag,
                 } => {
                     catalog
-                        .list(details_flag, only_backup_status, filepaths_flag)
+                        .list(details_flag, only_backup_status, filepaths_flag, format)
                         .await
                 }
                 CatalogSubcommand::Compact => match catalog.compact().await {
-                    Ok(n) => {
-                        let message = format!("✅ deleted {n} outdated backups");
-                        success_message(message, Output::Stdout)
+                    Ok(compaction) => {
+                        let message = format!(
+                            "✅ deleted {} outdated backups{}",
+                            compaction.num_deleted,
+                            compaction_message(&compaction)
+                        );
+                        let report = Report::compacted(&compaction);
+                        success_message(message, report, Output::Stdout, format)
                     }
                     Err(e) => failure_message(
                         format!("🛑 Could not compact backups: {e}"),
+                        Report::failure(Action::Compact, &e),
                         Output::Stdout,
+                        format,
                     ),
                 },
+                CatalogSubcommand::Pin { backup_filepath } => {
+                    let result = catalog.pin(&backup_filepath).await;
+                    pin_message(Action::Pin, &backup_filepath, result, format)
+                }
+                CatalogSubcommand::Unpin { backup_filepath } => {
+                    let result = catalog.unpin(&backup_filepath).await;
+                    pin_message(Action::Unpin, &backup_filepath, result, format)
+                }
             }
         }
 
         Command::Describe { backup_filepath } => {
-            v1::print_description(backup_filepath).await.unwrap()
+            let result = match format {
+                Format::Text => v2::print_description(&backup_filepath, identity_filepath).await,
+                Format::Json | Format::Ndjson => {
+                    archive::Metadata::read_file(&backup_filepath, identity_filepath)
+                        .await
+                        .map(|metadata| {
+                            if let Some(document) = report::to_string(&metadata, format) {
+                                println!("{document}");
+                            }
+                        })
+                }
+            };
+            if let Err(e) = result {
+                failure_message(
+                    format!(
+                        "🛑 Could not describe `{}`: {e}",
+                        backup_filepath.to_string_lossy()
+                    ),
+                    Report::failure(Action::Describe, &e),
+                    Output::Stdout,
+                    format,
+                );
+            }
         }
 
         Command::Save {
             strategy,
             to_tmux,
             compact,
-            num_lines_to_drop,
+            label,
+            note,
+            save: save_config,
         } => {
-            let catalog = init_catalog(&config.backup_dirpath, strategy).await;
+            let catalog =
+                init_catalog(&config.backup_dirpath, strategy, identity_filepath, format).await;
 
-            match save(&catalog.dirpath, num_lines_to_drop as usize).await {
-                Ok((backup_filepath, archive_overview)) => {
-                    if compact {
+            let options = save_config.options();
+            match save(&catalog.dirpath, &options, label.as_ref(), note.as_deref()).await {
+                Ok((backup_filepath, archive_overview, redactions)) => {
+                    let compaction = if compact {
                         // In practice this should never fail: write to the catalog already ensures
                         // the catalog's dirpath is writable.
-                        catalog
+                        let compaction = catalog
                             .refresh()
                             .await
                             .expect("Success saving but could not refresh")
                             .compact()
                             .await
                             .expect("Success saving but could not compact");
-                    }
+                        Some(compaction)
+                    } else {
+                        None
+                    };
                     let message = format!(
-                        "✅ {archive_overview}, persisted to `{}`",
-                        backup_filepath.to_string_lossy()
+                        "✅ {archive_overview}, persisted to `{}`{}{}",
+                        backup_filepath.to_string_lossy(),
+                        redactions_message(&redactions),
+                        compaction
+                            .as_ref()
+                            .map(compaction_message)
+                            .unwrap_or_default()
                     );
-                    success_message(message, to_tmux);
+                    let report = Report::saved(
+                        Action::Save,
+                        &backup_filepath,
+                        &archive_overview,
+                        &redactions,
+                    )
+                    .with_compaction(compaction.as_ref());
+                    success_message(message, report, to_tmux, format);
                 }
                 Err(e) => {
-                    failure_message(format!("🛑 Could not save sessions: {e}"), to_tmux);
+                    failure_message(
+                        format!("🛑 Could not save sessions: {e}"),
+                        Report::failure(Action::Save, &e),
+                        to_tmux,
+                        format,
+                    );
                 }
             };
         }
 
         Command::Autosave {
             to_tmux,
-            num_lines_to_drop,
+            save: save_config,
         } => {
             let context = match autosave_context(to_tmux.is_some()).await {
                 Ok(context) => context,
                 Err(e) => {
                     failure_message(
                         format!("🛑 Could not prepare autosave: {e}"),
+                        Report::failure(Action::Autosave, &e),
                         Output::Stdout,
+                        format,
                     );
                     return;
                 }
@@ -117,31 +219,43 @@ async fn run(config: Config) {
             if let Err(e) = fs::create_dir_all(&config.backup_dirpath).await {
                 autosave_failure(
                     format!("🛑 Could not create autosave directory: {e}"),
+                    Report::failure(Action::Autosave, &e),
                     to_tmux,
                     &context,
+                    format,
                 );
                 return;
             }
 
             match autosave(
                 &config.backup_dirpath,
-                num_lines_to_drop as usize,
+                &save_config.options(),
                 context.clone(),
             )
             .await
             {
-                Ok((backup_filepath, archive_overview)) => autosave_success(
+                Ok((backup_filepath, archive_overview, redactions)) => autosave_success(
                     format!(
-                        "✅ {archive_overview}, auto-saved to `{}`",
-                        backup_filepath.to_string_lossy()
+                        "✅ {archive_overview}, auto-saved to `{}`{}",
+                        backup_filepath.to_string_lossy(),
+                        redactions_message(&redactions)
+                    ),
+                    Report::saved(
+                        Action::Autosave,
+                        &backup_filepath,
+                        &archive_overview,
+                        &redactions,
                     ),
                     to_tmux,
                     &context,
+                    format,
                 ),
                 Err(e) => autosave_failure(
                     format!("🛑 Could not autosave sessions: {e}"),
+                    Report::failure(Action::Autosave, &e),
                     to_tmux,
                     &context,
+                    format,
                 ),
             }
         }
@@ -149,31 +263,267 @@ async fn run(config: Config) {
         Command::Restore {
             strategy,
             to_tmux,
+            restore: restore_config,
+            dry_run,
+            label,
             backup_filepath,
         } => {
-            let catalog = init_catalog(&config.backup_dirpath, strategy).await;
+            let catalog =
+                init_catalog(&config.backup_dirpath, strategy, identity_filepath, format).await;
 
-            // Either the provided filepath, or newest ordinary backup/autosave, or failure.
+            // Either the provided filepath, or latest backup with the label, or newest ordinary
+            // backup/autosave, or failure.
             let backup_to_restore = {
                 if let Some(ref backup_filepath) = backup_filepath {
                     backup_filepath.as_path()
+                } else if let Some(label) = &label {
+                    match catalog.latest_labelled(label) {
+                        Some(backup) => backup.filepath.as_path(),
+                        None => {
+                            failure_message(
+                                format!("🛑 No backup labelled `{label}`"),
+                                Report::failure(
+                                    Action::Restore,
+                                    format!("no backup labelled `{label}`"),
+                                ),
+                                to_tmux,
+                                format,
+                            );
+                            return;
+                        }
+                    }
                 } else if let Some(backup_filepath) = catalog.latest_for_restore() {
                     backup_filepath
                 } else {
-                    failure_message("🛑 No available backup to restore".to_string(), to_tmux);
+                    failure_message(
+                        "🛑 No available backup to restore".to_string(),
+                        Report::failure(Action::Restore, "no available backup to restore"),
+                        to_tmux,
+                        format,
+                    );
                     return;
                 }
             };
-            match restore(backup_to_restore).await {
-                Ok(overview) => {
+            let options = RestoreOptions {
+                identity_filepath: config.identity_filepath.clone(),
+                ..restore_config.options()
+            };
+            if dry_run {
+                match restore_plan(backup_to_restore, &options).await {
+                    Ok(plan) => match report::to_string(&plan, format) {
+                        Some(document) => println!("{document}"),
+                        None => println!("{plan}"),
+                    },
+                    Err(e) => failure_message(
+                        format!("🛑 Could not plan the restore: {e}"),
+                        Report::failure(Action::Restore, &e),
+                        Output::Stdout,
+                        format,
+                    ),
+                }
+                return;
+            }
+
+            match restore(backup_to_restore, &options).await {
+                Ok(restoration) => {
                     let message = format!(
-                        "✅ restored {overview} from `{}`",
-                        backup_to_restore.to_string_lossy()
+                        "✅ restored {} from `{}`{}",
+                        restoration.overview,
+                        backup_to_restore.to_string_lossy(),
+                        skipped_sessions_message(&restoration.skipped_sessions)
                     );
-                    success_message(message, to_tmux)
+                    let report = Report::restored(backup_to_restore, &restoration);
+                    success_message(message, report, to_tmux, format);
+                    if format == Format::Text
+                        && let Some(session_name) = &restoration.attach_session
+                    {
+                        println!(
+                            "Attach to your last session with `tmux attach -t {session_name}`"
+                        );
+                    }
                 }
                 Err(e) => {
-                    failure_message(format!("🛑 Could not restore sessions: {e}"), to_tmux);
+                    failure_message(
+                        format!("🛑 Could not restore sessions: {e}"),
+                        Report::failure(Action::Restore, &e),
+                        to_tmux,
+                        format,
+                    );
+                }
+            }
+        }
+
+        Command::Diff {
+            content,
+            live,
+            save,
+            old_backup_filepath,
+            new_backup_filepath,
+        } => {
+            let options = save.options();
+            let new_side = match &new_backup_filepath {
+                Some(new_backup_filepath) if !live => DiffSide::Backup(new_backup_filepath),
+                _ => DiffSide::Live(&options),
+            };
+            let old_side = DiffSide::Backup(&old_backup_filepath);
+            match diff(old_side, new_side, content, identity_filepath).await {
+                Ok(diff) => match report::to_string(&diff, format) {
+                    Some(document) => println!("{document}"),
+                    None if diff.is_empty() => println!("✅ no differences"),
+                    None => print!("{diff}"),
+                },
+                Err(e) => failure_message(
+                    format!("🛑 Could not compare backups: {e}"),
+                    Report::failure(Action::Diff, &e),
+                    Output::Stdout,
+                    format,
+                ),
+            }
+        }
+
+        Command::Migrate {
+            strategy,
+            backup_filepaths,
+        } => {
+            let backup_filepaths = if backup_filepaths.is_empty() {
+                let catalog =
+                    init_catalog(&config.backup_dirpath, strategy, identity_filepath, format).await;
+                all_backup_filepaths(&catalog)
+            } else {
+                backup_filepaths
+            };
+
+            // Migrate every backup, even after a failure, then report the failure.
+            let mut num_failures = 0;
+            for backup_filepath in &backup_filepaths {
+                let path = backup_filepath.to_string_lossy();
+                let (message, report) = match migrate(backup_filepath, identity_filepath).await {
+                    Ok(Migration::Migrated {
+                        previous_version,
+                        overview,
+                    }) => {
+                        let message = format!(
+                            "✅ migrated `{path}` from format {previous_version} to {}",
+                            overview.version
+                        );
+                        let report = report::to_string(
+                            &Report::migrated(backup_filepath, &overview, &previous_version),
+                            format,
+                        );
+                        (message, report)
+                    }
+                    Ok(Migration::UpToDate { overview }) => {
+                        let message =
+                            format!("✅ `{path}` is already in format {}", overview.version);
+                        let report = report::to_string(
+                            &Report::success(Action::Migrate, backup_filepath, &overview),
+                            format,
+                        );
+                        (message, report)
+                    }
+                    Err(e) => {
+                        num_failures += 1;
+                        let report = Report {
+                            backup_filepath: Some(backup_filepath),
+                            ..Report::failure(Action::Migrate, &e)
+                        };
+                        match report::to_string(&report, format) {
+                            Some(record) => println!("{record}"),
+                            None => eprintln!("🛑 Could not migrate `{path}`: {e}"),
+                        }
+                        continue;
+                    }
+                };
+                println!("{}", report.unwrap_or(message));
+            }
+
+            if num_failures > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Command::Verify {
+            strategy,
+            all,
+            backup_filepaths,
+        } => {
+            let backup_filepaths = if all {
+                let catalog =
+                    init_catalog(&config.backup_dirpath, strategy, identity_filepath, format).await;
+                all_backup_filepaths(&catalog)
+            } else {
+                backup_filepaths
+            };
+
+            // Verify every backup, even after a corrupt one, then report the failure.
+            let mut num_failures = 0;
+            for backup_filepath in &backup_filepaths {
+                let path = backup_filepath.to_string_lossy();
+                let verification = match verify(backup_filepath, identity_filepath).await {
+                    Ok(verification) => verification,
+                    Err(e) => {
+                        num_failures += 1;
+                        let report = Report {
+                            backup_filepath: Some(backup_filepath),
+                            ..Report::failure(Action::Verify, &e)
+                        };
+                        match report::to_string(&report, format) {
+                            Some(record) => println!("{record}"),
+                            None => eprintln!("🛑 Could not verify `{path}`: {e}"),
+                        }
+                        continue;
+                    }
+                };
+
+                let report = Report::verified(
+                    backup_filepath,
+                    verification.overview.as_ref(),
+                    &verification.problems,
+                );
+                if let Some(record) = report::to_string(&report, format) {
+                    println!("{record}");
+                } else if verification.is_intact() {
+                    println!("✅ `{path}` is intact");
+                } else {
+                    println!("🛑 `{path}` is corrupt:");
+                    for problem in &verification.problems {
+                        println!("  - {problem}");
+                    }
+                }
+                if !verification.is_intact() {
+                    num_failures += 1;
+                }
+            }
+
+            if format == Format::Text && backup_filepaths.len() > 1 {
+                println!(
+                    "{} backups verified, {num_failures} failed",
+                    backup_filepaths.len()
+                );
+            }
+            if num_failures > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Command::Config {
+            command: ConfigSubcommand::Show,
+        } => {
+            let effective = config
+                .file_config
+                .effective(&config.backup_dirpath, identity_filepath);
+            match report::to_string(&effective, format) {
+                Some(document) => println!("{document}"),
+                None => {
+                    let filepath = config.config_filepath.to_string_lossy();
+                    if config.config_filepath.exists() {
+                        println!("# Effective config, with settings from `{filepath}`\n");
+                    } else {
+                        println!("# Default config, there is no config file at `{filepath}`\n");
+                    }
+                    let document =
+                        toml::to_string(&effective).expect("the config serializes to TOML");
+                    print!("{document}");
                 }
             }
         }
@@ -192,7 +542,13 @@ async fn run(config: Config) {
 }
 
 fn main() {
-    let config = Config::parse();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("🛑 Could not load the config: {e}");
+            std::process::exit(1);
+        }
+    };
     smol::block_on(run(config));
 }
 
@@ -212,11 +568,44 @@ impl From<bool> for Output {
     }
 }
 
+/// Return the end of the save message, listing the redacted secrets if any.
+fn redactions_message(redactions: &Redactions) -> String {
+    if redactions.is_empty() {
+        String::new()
+    } else {
+        format!(", {redactions}")
+    }
+}
+
+fn compaction_message(compaction: &Compaction) -> String {
+    match &compaction.blobs_error {
+        Some(error) => format!(", unreferenced blobs were kept: {error}"),
+        None => String::new(),
+    }
+}
+
+fn skipped_sessions_message(skipped_sessions: &[SkippedSession]) -> String {
+    skipped_sessions
+        .iter()
+        .map(|s| format!(", skipped session `{}`: {}", s.name, s.reason))
+        .collect()
+}
+
 fn autosave_success(
     message: String,
+    report: Report,
     to_tmux: Option<AutosaveTmuxOutput>,
     context: &AutosaveContext,
+    format: Format,
 ) {
+    if let Some(record) = report::to_string(&report, format) {
+        println!("{record}");
+        if let Some(AutosaveTmuxOutput::All) = to_tmux {
+            display_autosave_message(context, &message);
+        }
+        return;
+    }
+
     match to_tmux {
         Some(AutosaveTmuxOutput::All) => display_autosave_message(context, &message),
         None | Some(AutosaveTmuxOutput::Errors) => println!("{message}"),
@@ -225,32 +614,87 @@ fn autosave_success(
 
 fn autosave_failure(
     message: String,
+    report: Report,
     to_tmux: Option<AutosaveTmuxOutput>,
     context: &AutosaveContext,
+    format: Format,
 ) {
-    eprintln!("{message}");
+    match report::to_string(&report, format) {
+        Some(record) => println!("{record}"),
+        None => eprintln!("{message}"),
+    }
     if to_tmux.is_some() {
         display_autosave_message(context, &message);
     }
     std::process::exit(1);
 }
 
-fn success_message<O: Into<Output>>(message: String, output: O) {
-    match output.into() {
-        Output::ToTmux => tmux::display_message(&message),
-        Output::Stdout => println!("{message}"),
-        Output::Both => {
+/// Print the outcome of pinning or unpinning the backup at `backup_filepath`.
+fn pin_message(
+    action: Action,
+    backup_filepath: &Path,
+    result: tmux_backup::Result<PathBuf>,
+    format: Format,
+) {
+    let verb = if action == Action::Pin {
+        "pin"
+    } else {
+        "unpin"
+    };
+    match result {
+        Ok(filepath) => success_message(
+            format!("✅ {verb}ned `{}`", filepath.to_string_lossy()),
+            Report::pinned(action, &filepath),
+            Output::Stdout,
+            format,
+        ),
+        Err(e) => failure_message(
+            format!(
+                "🛑 Could not {verb} `{}`: {e}",
+                backup_filepath.to_string_lossy()
+            ),
+            Report::failure(action, &e),
+            Output::Stdout,
+            format,
+        ),
+    }
+}
+
+/// Print the report in the json formats, otherwise print the text message.
+///
+/// In every format, the text message is still displayed in Tmux if requested.
+fn success_message<O: Into<Output>>(message: String, report: Report, output: O, format: Format) {
+    let record = report::to_string(&report, format);
+    match (output.into(), record) {
+        (Output::ToTmux, Some(record)) | (Output::Both, Some(record)) => {
+            println!("{record}");
+            tmux::display_message(&message)
+        }
+        (Output::Stdout, Some(record)) => println!("{record}"),
+        (Output::ToTmux, None) => tmux::display_message(&message),
+        (Output::Stdout, None) => println!("{message}"),
+        (Output::Both, None) => {
             println!("{message}");
             tmux::display_message(&message)
         }
     }
 }
 
-fn failure_message<O: Into<Output>>(message: String, output: O) {
-    match output.into() {
-        Output::ToTmux => tmux::display_message(&message),
-        Output::Stdout => eprintln!("{message}"),
-        Output::Both => {
+/// Print the report in the json formats, otherwise print the text message, then exit.
+///
+/// In the json formats, the error record is printed to stdout, in order to keep a single stream
+/// of records.
+fn failure_message<O: Into<Output>>(message: String, report: Report, output: O, format: Format) {
+    let record = report::to_string(&report, format);
+    match (output.into(), record) {
+        (Output::ToTmux, Some(record)) | (Output::Both, Some(record)) => {
+            println!("{record}");
+            tmux::display_message(&message)
+        }
+        (Output::Stdout, Some(record)) => println!("{record}"),
+        (Output::ToTmux, None) => tmux::display_message(&message),
+        (Output::Stdout, None) => eprintln!("{message}"),
+        (Output::Both, None) => {
             eprintln!("{message}");
             tmux::display_message(&message)
         }
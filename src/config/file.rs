@@ -0,0 +1,680 @@
+//! Config file.
+//!
+//! The settings of the config file replace the default values of the corresponding command-line
+//! options. For instance:
+//!
+//! ```toml
+//! dirpath = "~/backups/tmux"
+//! strategy = "tiered"
+//! keep = "hourly=48,daily=14,weekly=8,monthly=24,yearly=5"
+//!
+//! [save]
+//! ignore-last-lines = 1
+//! shells = ["zsh=2", "bash", "fish", "nu"]
+//!
+//! [restore]
+//! existing = "rename-live"
+//! relaunch = ["nvim", "htop"]
+//! ```
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use age::x25519;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{DEFAULT_NUM_BACKUPS, StrategyValues};
+use crate::{
+    Result,
+    actions::{ExistingSessions, HistoryOverride, ShellPattern, default_shells},
+    error::Error,
+    management::{
+        archive::HistoryLines,
+        compaction::{Age, Size, Tiers},
+    },
+    pattern::NamePattern,
+};
+
+/// Settings read from the config file, all optional.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FileConfig {
+    /// Location of backups.
+    #[serde(default, deserialize_with = "home_path")]
+    pub dirpath: Option<PathBuf>,
+
+    /// Identity file decrypting the encrypted backups.
+    #[serde(
+        default,
+        deserialize_with = "home_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub identity: Option<PathBuf>,
+
+    /// Strategy for managing backups.
+    pub strategy: Option<StrategyValues>,
+
+    /// Number of recent backups to keep with the most-recent strategy.
+    pub num_backups: Option<u16>,
+
+    /// Tiers of the tiered strategy.
+    pub keep: Option<Tiers>,
+
+    /// Age beyond which backups are purged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<Age>,
+
+    /// Total size of the backup files beyond which the oldest backups are purged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_total_size: Option<Size>,
+
+    /// Whether the labelled backups are always retained.
+    pub keep_labelled: Option<bool>,
+
+    /// Settings of the `[save]` table.
+    #[serde(default)]
+    pub save: SaveSection,
+
+    /// Settings of the `[restore]` table.
+    #[serde(default)]
+    pub restore: RestoreSection,
+}
+
+/// Settings of the `[save]` table of the config file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SaveSection {
+    /// Number of lines to ignore during capture if the active command is a shell.
+    pub ignore_last_lines: Option<u8>,
+
+    /// Shells whose prompt lines are dropped.
+    pub shells: Option<Vec<ShellPattern>>,
+
+    /// Regular expression matching the first line of the prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_pattern: Option<String>,
+
+    /// Capture the escape sequences of colours and attributes.
+    pub escapes: Option<bool>,
+
+    /// Depth of the history captured in each pane.
+    #[serde(default, with = "history_lines")]
+    pub history_lines: Option<HistoryLines>,
+
+    /// Depth of the history captured in the panes running some programs.
+    #[serde(rename = "history-lines-for")]
+    pub history_overrides: Option<Vec<HistoryOverride>>,
+
+    /// Patterns of the sessions to save.
+    pub include_sessions: Option<Vec<NamePattern>>,
+
+    /// Patterns of the sessions left out of saves.
+    pub exclude_sessions: Option<Vec<NamePattern>>,
+
+    /// Patterns of the windows to save.
+    pub include_windows: Option<Vec<NamePattern>>,
+
+    /// Patterns of the windows left out of saves.
+    pub exclude_windows: Option<Vec<NamePattern>>,
+
+    /// Patterns of the programs whose panes are saved.
+    pub include_commands: Option<Vec<NamePattern>>,
+
+    /// Patterns of the programs whose panes are left out of saves.
+    pub exclude_commands: Option<Vec<NamePattern>>,
+
+    /// Redact secrets with the built-in detectors.
+    pub redact: Option<bool>,
+
+    /// Regular expressions of additional secrets.
+    pub redact_patterns: Option<Vec<String>>,
+
+    /// Age recipients to which backups are encrypted.
+    #[serde(default, with = "recipients")]
+    pub encrypt_to: Option<Vec<x25519::Recipient>>,
+
+    /// Encrypt backups with the passphrase of the environment.
+    pub encrypt_with_passphrase: Option<bool>,
+}
+
+/// Settings of the `[restore]` table of the config file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RestoreSection {
+    /// Handling of archived sessions which already exist.
+    pub existing: Option<ExistingSessions>,
+
+    /// Suffix of renamed sessions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+
+    /// Programs relaunched in their pane on restore.
+    pub relaunch: Option<Vec<String>>,
+}
+
+impl FileConfig {
+    /// Read the config file at `filepath`.
+    ///
+    /// If the file does not exist, this returns an empty config, unless `must_exist` is `true`.
+    pub fn read<P: AsRef<Path>>(filepath: P, must_exist: bool) -> Result<Self> {
+        let filepath = filepath.as_ref();
+        let text = match std::fs::read_to_string(filepath) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !must_exist => {
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(Error::ConfigError(format!(
+                    "could not read {}: {e}",
+                    filepath.to_string_lossy()
+                )));
+            }
+        };
+
+        toml::from_str(&text)
+            .map_err(|e| Error::ConfigError(format!("{}: {e}", filepath.to_string_lossy())))
+    }
+
+    /// Return this config completed with the default value of each missing setting, the
+    /// location of backups `backup_dirpath` and the `identity_filepath`, as resolved from the
+    /// command line and this config.
+    ///
+    /// The identity file, the prompt pattern, the suffix of renamed sessions and the limits on the
+    /// backups are left out if not set: they have no default value.
+    pub fn effective(&self, backup_dirpath: &Path, identity_filepath: Option<&Path>) -> Self {
+        let save = &self.save;
+        let restore = &self.restore;
+
+        Self {
+            dirpath: Some(backup_dirpath.to_path_buf()),
+            identity: identity_filepath.map(Path::to_path_buf),
+            strategy: Some(self.strategy.clone().unwrap_or(StrategyValues::MostRecent)),
+            num_backups: Some(self.num_backups.unwrap_or(DEFAULT_NUM_BACKUPS)),
+            keep: Some(self.keep.clone().unwrap_or_default()),
+            max_age: self.max_age,
+            max_total_size: self.max_total_size,
+            keep_labelled: Some(self.keep_labelled.unwrap_or(false)),
+            save: SaveSection {
+                ignore_last_lines: Some(save.ignore_last_lines.unwrap_or(0)),
+                shells: Some(save.shells.clone().unwrap_or_else(default_shells)),
+                prompt_pattern: save.prompt_pattern.clone(),
+                escapes: Some(save.escapes.unwrap_or(true)),
+                history_lines: Some(save.history_lines.unwrap_or_default()),
+                history_overrides: Some(save.history_overrides.clone().unwrap_or_default()),
+                include_sessions: Some(save.include_sessions.clone().unwrap_or_default()),
+                exclude_sessions: Some(save.exclude_sessions.clone().unwrap_or_default()),
+                include_windows: Some(save.include_windows.clone().unwrap_or_default()),
+                exclude_windows: Some(save.exclude_windows.clone().unwrap_or_default()),
+                include_commands: Some(save.include_commands.clone().unwrap_or_default()),
+                exclude_commands: Some(save.exclude_commands.clone().unwrap_or_default()),
+                redact: Some(save.redact.unwrap_or(false)),
+                redact_patterns: Some(save.redact_patterns.clone().unwrap_or_default()),
+                encrypt_to: Some(save.encrypt_to.clone().unwrap_or_default()),
+                encrypt_with_passphrase: Some(save.encrypt_with_passphrase.unwrap_or(false)),
+            },
+            restore: RestoreSection {
+                existing: Some(restore.existing.unwrap_or_default()),
+                suffix: restore.suffix.clone(),
+                relaunch: Some(restore.relaunch.clone().unwrap_or_default()),
+            },
+        }
+    }
+}
+
+/// Return the default location of the config file.
+///
+/// If `$XDG_CONFIG_HOME` is defined, this returns `$XDG_CONFIG_HOME/tmux-backup/config.toml`,
+/// otherwise `$HOME/.config/tmux-backup/config.toml`.
+///
+/// # Panics
+///
+/// This function panics if even `$HOME` cannot be obtained from the environment.
+pub fn default_filepath() -> PathBuf {
+    let config_home = match env::var("XDG_CONFIG_HOME") {
+        Ok(v) => PathBuf::from(v),
+        Err(_) => match env::var("HOME") {
+            Ok(v) => PathBuf::from(v).join(".config"),
+            Err(_) => panic!("Cannot find `$HOME` in the environment"),
+        },
+    };
+
+    config_home.join("tmux-backup").join("config.toml")
+}
+
+/// Replace a leading `~` in `path` by the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Read a path, replacing a leading `~` by the home directory.
+fn home_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<PathBuf>, D::Error> {
+    let path = Option::<String>::deserialize(deserializer)?;
+    Ok(path.map(|path| expand_home(&path)))
+}
+
+/// Depth of history, written as an integer if it is a number of lines, as a string otherwise.
+mod history_lines {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    use crate::management::archive::HistoryLines;
+
+    pub fn serialize<S: Serializer>(
+        history_lines: &Option<HistoryLines>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match history_lines {
+            Some(HistoryLines::Lines(n)) => serializer.serialize_u32(*n),
+            _ => history_lines.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<HistoryLines>, D::Error> {
+        match Option::<toml::Value>::deserialize(deserializer)? {
+            None => Ok(None),
+            Some(toml::Value::Integer(n)) => u32::try_from(n)
+                .map(|n| Some(HistoryLines::Lines(n)))
+                .map_err(|_| de::Error::custom(format!("invalid history lines `{n}`"))),
+            Some(toml::Value::String(s)) => s.parse().map(Some).map_err(de::Error::custom),
+            Some(value) => Err(de::Error::custom(format!(
+                "expected an integer or a string, not {}",
+                value.type_str()
+            ))),
+        }
+    }
+}
+
+/// Age recipients, written in their Bech32 encoding.
+mod recipients {
+    use age::x25519;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    pub fn serialize<S: Serializer>(
+        recipients: &Option<Vec<x25519::Recipient>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let recipients: Option<Vec<String>> = recipients
+            .as_ref()
+            .map(|recipients| recipients.iter().map(|r| r.to_string()).collect());
+        recipients.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<x25519::Recipient>>, D::Error> {
+        let Some(recipients) = Option::<Vec<String>>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        recipients
+            .iter()
+            .map(|recipient| {
+                recipient
+                    .parse()
+                    .map_err(|e| de::Error::custom(format!("invalid recipient `{recipient}`: {e}")))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> std::result::Result<FileConfig, String> {
+        toml::from_str(text).map_err(|e: toml::de::Error| e.message().to_string())
+    }
+
+    mod parsing {
+        use super::*;
+
+        #[test]
+        fn reads_every_setting() {
+            let text = r#"
+dirpath = "/srv/backups"
+identity = "/srv/identity.txt"
+strategy = "classic"
+num-backups = 20
+keep = "daily=14,monthly=6"
+max-age = "30d"
+max-total-size = "500MB"
+keep-labelled = true
+
+[save]
+ignore-last-lines = 1
+shells = ["zsh=2", "nu"]
+prompt-pattern = '^❯ '
+escapes = false
+history-lines = "visible"
+history-lines-for = ["re:^(zsh|bash)$=all"]
+include-sessions = ["proj-*"]
+exclude-sessions = ["tmux-backup"]
+include-windows = []
+exclude-windows = ["scratch"]
+include-commands = []
+exclude-commands = ["pass", "re:^gpg"]
+redact = true
+redact-patterns = ['token: (?P<secret>\S+)']
+encrypt-to = ["age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"]
+
+[restore]
+existing = "rename-live"
+suffix = "old"
+relaunch = ["nvim", "htop"]
+"#;
+            let config = parse(text).unwrap();
+
+            assert_eq!(
+                config,
+                FileConfig {
+                    dirpath: Some(PathBuf::from("/srv/backups")),
+                    identity: Some(PathBuf::from("/srv/identity.txt")),
+                    strategy: Some(StrategyValues::Classic),
+                    num_backups: Some(20),
+                    keep: Some("daily=14,monthly=6".parse().unwrap()),
+                    max_age: Some("30d".parse().unwrap()),
+                    max_total_size: Some("500MB".parse().unwrap()),
+                    keep_labelled: Some(true),
+                    save: SaveSection {
+                        ignore_last_lines: Some(1),
+                        shells: Some(vec!["zsh=2".parse().unwrap(), "nu".parse().unwrap()]),
+                        prompt_pattern: Some("^❯ ".into()),
+                        escapes: Some(false),
+                        history_lines: Some(HistoryLines::Visible),
+                        history_overrides: Some(vec!["re:^(zsh|bash)$=all".parse().unwrap()]),
+                        include_sessions: Some(vec!["proj-*".parse().unwrap()]),
+                        exclude_sessions: Some(vec!["tmux-backup".parse().unwrap()]),
+                        include_windows: Some(vec![]),
+                        exclude_windows: Some(vec!["scratch".parse().unwrap()]),
+                        include_commands: Some(vec![]),
+                        exclude_commands: Some(vec![
+                            "pass".parse().unwrap(),
+                            "re:^gpg".parse().unwrap()
+                        ]),
+                        redact: Some(true),
+                        redact_patterns: Some(vec![r"token: (?P<secret>\S+)".into()]),
+                        encrypt_to: Some(vec![
+                            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"
+                                .parse()
+                                .unwrap()
+                        ]),
+                        encrypt_with_passphrase: None,
+                    },
+                    restore: RestoreSection {
+                        existing: Some(ExistingSessions::RenameLive),
+                        suffix: Some("old".into()),
+                        relaunch: Some(vec!["nvim".into(), "htop".into()]),
+                    },
+                }
+            );
+        }
+
+        #[test]
+        fn missing_settings_are_none() {
+            let config = parse("[save]\nignore-last-lines = 2\n").unwrap();
+
+            assert_eq!(
+                config,
+                FileConfig {
+                    save: SaveSection {
+                        ignore_last_lines: Some(2),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+            );
+        }
+
+        #[test]
+        fn reads_history_lines_as_a_number() {
+            let config = parse("[save]\nhistory-lines = 2000\n").unwrap();
+
+            assert_eq!(config.save.history_lines, Some(HistoryLines::Lines(2000)));
+        }
+
+        #[test]
+        fn expands_the_home_directory() {
+            let home = PathBuf::from(env::var("HOME").unwrap());
+
+            assert_eq!(expand_home("~/backups"), home.join("backups"));
+            assert_eq!(expand_home("~"), home);
+            assert_eq!(expand_home("~other/x"), PathBuf::from("~other/x"));
+            assert_eq!(expand_home("/abs/~/x"), PathBuf::from("/abs/~/x"));
+        }
+    }
+
+    mod errors {
+        use super::*;
+
+        fn error(text: &str) -> String {
+            parse(text).unwrap_err()
+        }
+
+        #[test]
+        fn rejects_unknown_keys() {
+            assert!(error("dir = \"/tmp\"\n").starts_with("unknown field `dir`, expected one of "));
+            assert!(
+                error("[save]\nignore-lines = 1\n")
+                    .starts_with("unknown field `ignore-lines`, expected one of ")
+            );
+        }
+
+        #[test]
+        fn rejects_values_of_the_wrong_type() {
+            assert_eq!(
+                error("num-backups = \"10\"\n"),
+                "invalid type: string \"10\", expected u16"
+            );
+            assert_eq!(
+                error("[save]\nshells = [\"zsh\", 1]\n"),
+                "invalid type: integer `1`, expected a string"
+            );
+            assert_eq!(
+                error("save = 1\n"),
+                "invalid type: integer `1`, expected struct SaveSection"
+            );
+            assert_eq!(
+                error("[save]\nescapes = \"no\"\n"),
+                "invalid type: string \"no\", expected a boolean"
+            );
+            assert_eq!(
+                error("[save]\nhistory-lines = true\n"),
+                "expected an integer or a string, not boolean"
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_shells() {
+            assert_eq!(
+                error("[save]\nshells = [\"re:(\"]\n"),
+                "invalid shell pattern `re:(`: regex parse error:\n    (\n    ^\nerror: unclosed group"
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_recipients() {
+            assert!(
+                error("[save]\nencrypt-to = [\"age1nope\"]\n")
+                    .starts_with("invalid recipient `age1nope`: ")
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_history_lines() {
+            assert_eq!(
+                error("[save]\nhistory-lines = \"lots\"\n"),
+                "invalid history lines `lots`, expected `all`, `visible` or a number"
+            );
+            assert_eq!(
+                error("[save]\nhistory-lines = -1\n"),
+                "invalid history lines `-1`"
+            );
+            assert_eq!(
+                error("[save]\nhistory-lines-for = [\"htop\"]\n"),
+                "expected `PATTERN=DEPTH`, not `htop`"
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_filters() {
+            assert!(
+                error("[save]\nexclude-windows = [\"re:(\"]\n")
+                    .starts_with("invalid pattern `re:(`: ")
+            );
+        }
+
+        #[test]
+        fn rejects_values_out_of_range() {
+            assert_eq!(
+                error("[save]\nignore-last-lines = 300\n"),
+                "invalid value: integer `300`, expected u8"
+            );
+        }
+
+        #[test]
+        fn lists_the_accepted_values() {
+            assert_eq!(
+                error("strategy = \"newest\"\n"),
+                "unknown variant `newest`, expected one of `most-recent`, `classic`, `tiered`"
+            );
+            assert_eq!(
+                error("keep = \"daily=14,fortnightly=2\"\n"),
+                "invalid period `fortnightly`, expected one of `hourly`, `daily`, `weekly`, \
+                 `monthly`, `yearly`"
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_limits() {
+            assert!(error("max-age = \"30m\"\n").starts_with("expected a number"));
+            assert_eq!(
+                error("max-total-size = \"lots\"\n"),
+                "expected a size such as `500MB`, not `lots`"
+            );
+        }
+
+        #[test]
+        fn reports_errors_with_their_line() {
+            let Err(e) = toml::from_str::<FileConfig>("\n[save\n") else {
+                panic!("expected a syntax error");
+            };
+            assert!(e.to_string().starts_with("TOML parse error at line 2"));
+        }
+
+        #[test]
+        fn prefixes_errors_with_the_filepath() {
+            let dir = tempfile::TempDir::new().unwrap();
+            let filepath = dir.path().join("config.toml");
+            std::fs::write(&filepath, "nope = 1\n").unwrap();
+
+            let Err(Error::ConfigError(message)) = FileConfig::read(&filepath, false) else {
+                panic!("expected a config error");
+            };
+            assert!(message.contains("config.toml: TOML parse error at line 1"));
+            assert!(message.contains("unknown field `nope`"));
+        }
+    }
+
+    mod reading {
+        use super::*;
+
+        #[test]
+        fn missing_default_file_is_an_empty_config() {
+            let dir = tempfile::TempDir::new().unwrap();
+
+            let config = FileConfig::read(dir.path().join("config.toml"), false).unwrap();
+
+            assert_eq!(config, FileConfig::default());
+        }
+
+        #[test]
+        fn missing_explicit_file_is_an_error() {
+            let dir = tempfile::TempDir::new().unwrap();
+
+            assert!(FileConfig::read(dir.path().join("config.toml"), true).is_err());
+        }
+    }
+
+    mod effective {
+        use super::*;
+
+        #[test]
+        fn fills_in_the_defaults() {
+            let config = FileConfig {
+                num_backups: Some(3),
+                ..Default::default()
+            };
+
+            let effective = config.effective(Path::new("/tmp/backups"), None);
+
+            assert_eq!(
+                toml::to_string(&effective).unwrap(),
+                "\
+dirpath = \"/tmp/backups\"
+strategy = \"most-recent\"
+num-backups = 3
+keep = \"hourly=24,daily=7,weekly=4,monthly=12\"
+keep-labelled = false
+
+[save]
+ignore-last-lines = 0
+shells = [\"zsh\", \"bash\", \"fish\"]
+escapes = true
+history-lines = \"all\"
+history-lines-for = []
+include-sessions = []
+exclude-sessions = []
+include-windows = []
+exclude-windows = []
+include-commands = []
+exclude-commands = []
+redact = false
+redact-patterns = []
+encrypt-to = []
+encrypt-with-passphrase = false
+
+[restore]
+existing = \"skip\"
+relaunch = []
+"
+            );
+        }
+
+        #[test]
+        fn written_config_reads_back() {
+            let config = FileConfig {
+                strategy: Some(StrategyValues::Tiered),
+                keep: Some("hourly=48,yearly=5".parse().unwrap()),
+                max_age: Some("8w".parse().unwrap()),
+                max_total_size: Some("1.5GiB".parse().unwrap()),
+                keep_labelled: Some(true),
+                save: SaveSection {
+                    escapes: Some(false),
+                    history_lines: Some(HistoryLines::Lines(200)),
+                    include_windows: Some(vec!["re:^dev".parse().unwrap()]),
+                    ..Default::default()
+                },
+                restore: RestoreSection {
+                    suffix: Some("old".into()),
+                    relaunch: Some(vec!["nvim".into()]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            let text = toml::to_string(&config).unwrap();
+
+            assert!(text.contains("history-lines = 200\n"));
+            assert_eq!(parse(&text).unwrap(), config);
+        }
+    }
+}
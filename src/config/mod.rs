@@ -0,0 +1,2028 @@
+//! Configuration.
+
+use std::env;
+use std::path::PathBuf;
+
+use age::x25519;
+use clap::{
+    ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    ValueHint, parser::ValueSource,
+};
+use clap_complete::Shell;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Result,
+    actions::{
+        ExistingSessions, HistoryOverride, RestoreOptions, SaveOptions, ShellPattern,
+        default_shells,
+    },
+    error::Error,
+    management::{
+        archive::{HistoryLines, Label, encryption::Encryption},
+        backup::BackupStatus,
+        compaction::{Age, Limits, Size, Strategy, Tiers},
+    },
+    pattern::{NameFilter, NamePattern},
+    redaction::Redactor,
+    report::Format,
+};
+
+pub mod file;
+
+use file::{FileConfig, RestoreSection, SaveSection};
+
+/// Number of recent backups kept by default with the most-recent strategy.
+const DEFAULT_NUM_BACKUPS: u16 = 10;
+
+/// Save or restore Tmux sessions.
+#[derive(Debug, Parser)]
+#[clap(author, about, version)]
+#[clap(propagate_version = true)]
+pub struct Config {
+    /// Location of backups.
+    ///
+    /// If unspecified, it falls back on the `dirpath` of the config file, then on
+    /// `$XDG_STATE_HOME/tmux-backup`, then on `$HOME/.local/state/tmux-backup`.
+    #[arg(short = 'd', long = "dirpath", env = "TMUX_BACKUP_DIRPATH", value_hint = ValueHint::DirPath,
+        default_value_os_t = default_backup_dirpath())]
+    pub backup_dirpath: PathBuf,
+
+    /// Location of the config file.
+    ///
+    /// If unspecified, it falls back on `$XDG_CONFIG_HOME/tmux-backup/config.toml`, then on
+    /// `$HOME/.config/tmux-backup/config.toml`, which may not exist. Options set on the command
+    /// line or in the environment take precedence over the config file.
+    #[arg(long = "config", env = "TMUX_BACKUP_CONFIG", value_name = "FILE",
+        value_hint = ValueHint::FilePath, default_value_os_t = file::default_filepath())]
+    pub config_filepath: PathBuf,
+
+    /// Format of the reports printed to stdout.
+    ///
+    /// The `json` and `ndjson` formats print structured records for `catalog list`, `describe`,
+    /// and for the outcome of `save`, `autosave`, `restore` and `catalog compact`, including
+    /// errors.
+    #[arg(long, value_enum, global = true, default_value_t = Format::Text)]
+    pub format: Format,
+
+    /// Identity file decrypting the encrypted backups.
+    ///
+    /// This is an age identity file, such as written by `age-keygen`. If unspecified, it falls
+    /// back on the `identity` of the config file. Backups encrypted with a passphrase are
+    /// decrypted with the `TMUX_BACKUP_PASSPHRASE` environment variable instead.
+    #[arg(long = "identity", env = "TMUX_BACKUP_IDENTITY", value_name = "FILE", global = true,
+        value_hint = ValueHint::FilePath)]
+    pub identity_filepath: Option<PathBuf>,
+
+    /// Selection of commands.
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Settings read from the config file.
+    #[arg(skip)]
+    pub file_config: FileConfig,
+}
+
+/// Indicate whether to save (resp. restore) the Tmux sessions to (resp. from) a backup.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Save the Tmux sessions to a new backup file.
+    ///
+    /// Sessions, windows, and panes geometry + content are saved in an archive format inside the
+    /// backup folder. In that folder, the backup name is expected to be similar to
+    /// `backup-20220531T123456.tar.zst`.
+    ///
+    /// If you run this command via a Tmux keybinding, use the `--to-tmux` flag in order to send a
+    /// one-line report to the Tmux status bar. If you run this command from the terminal, ignore
+    /// this flag in order to print the one-line report in the terminal.
+    Save {
+        /// Choose a strategy for managing backups.
+        #[command(flatten)]
+        strategy: StrategyConfig,
+
+        /// Print a one-line report in the Tmux status bar, otherwise print to stdout.
+        #[arg(long, action = ArgAction::SetTrue)]
+        to_tmux: bool,
+
+        /// Delete purgeable backups after saving.
+        #[arg(long, action = ArgAction::SetTrue)]
+        compact: bool,
+
+        /// Label of the backup, such as `before-k8s-rebuild`.
+        ///
+        /// The label is made of ASCII letters, digits, `-` and `_`. It follows the timestamp in the
+        /// name of the backup, as in `backup-20220531T123456.123456-before-k8s-rebuild.tar.zst`,
+        /// and is stored in its metadata. Restore the latest backup with this label with `restore
+        /// --label`.
+        #[arg(long, value_name = "NAME")]
+        label: Option<Label>,
+
+        /// Note describing the backup, stored in its metadata and printed by `describe`.
+        #[arg(long, value_name = "TEXT")]
+        note: Option<String>,
+
+        /// Choose how panes are captured.
+        #[command(flatten)]
+        save: SaveConfig,
+    },
+
+    /// Save a rolling autosave archive for recovery.
+    ///
+    /// This always replaces `autosave.tar.zst` in the backup folder. It is excluded from retention
+    /// and compaction, and is intended for an external scheduler.
+    Autosave {
+        /// Print errors, or all reports, in the Tmux status bar.
+        #[arg(long, value_enum)]
+        to_tmux: Option<AutosaveTmuxOutput>,
+
+        /// Choose how panes are captured.
+        #[command(flatten)]
+        save: SaveConfig,
+    },
+
+    /// Restore the Tmux sessions from a backup file.
+    ///
+    /// Sessions, windows and panes geometry + content are read from the backup marked as "current"
+    /// (often the most recent backup) inside the backup folder. In that folder, the backup name is
+    /// expected to be similar to `backup-20220531T123456.tar.zst`.
+    ///
+    /// If you run this command via a Tmux keybinding, use the `--to-tmux` flag in order to send a
+    /// one-line report to the Tmux status bar. If you run this command from the terminal, ignore
+    /// this flag in order to print the one-line report in the terminal.
+    Restore {
+        /// Choose a strategy for managing backups.
+        #[command(flatten)]
+        strategy: StrategyConfig,
+
+        /// Print a one-line report in the Tmux status bar, otherwise print to stdout.
+        #[arg(long, action = ArgAction::SetTrue)]
+        to_tmux: bool,
+
+        /// Choose which sessions to restore and how.
+        #[command(flatten)]
+        restore: RestoreConfig,
+
+        /// Print what the restore would do, without changing anything.
+        #[arg(long, action = ArgAction::SetTrue)]
+        dry_run: bool,
+
+        /// Restore the latest backup having this label, see `save --label`.
+        #[arg(long, value_name = "NAME", conflicts_with = "backup_filepath")]
+        label: Option<Label>,
+
+        /// Filepath of the backup to restore, by default, pick latest.
+        #[arg(value_parser)]
+        backup_filepath: Option<PathBuf>,
+    },
+
+    /// Catalog commands.
+    Catalog {
+        /// Choose a strategy for managing backups.
+        #[command(flatten)]
+        strategy: StrategyConfig,
+
+        /// Catalog commands.
+        #[command(subcommand)]
+        command: CatalogSubcommand,
+    },
+
+    /// Describe the content of a backup file.
+    Describe {
+        /// Path to the backup file.
+        #[arg(value_parser, value_hint = ValueHint::FilePath)]
+        backup_filepath: PathBuf,
+    },
+
+    /// Rewrite backups in older archive formats into the current format.
+    ///
+    /// The panes content of each backup is moved into the content store of its folder. Backups
+    /// already in the current format are left untouched.
+    Migrate {
+        /// Choose a strategy for managing backups.
+        #[command(flatten)]
+        strategy: StrategyConfig,
+
+        /// Backup files to migrate, by default all backups of the catalog and the autosave.
+        #[arg(value_parser, value_hint = ValueHint::FilePath)]
+        backup_filepaths: Vec<PathBuf>,
+    },
+
+    /// Compare two backups, or a backup and the live Tmux sessions.
+    ///
+    /// Lists the sessions and windows added, removed or renamed, the windows whose layout
+    /// changed, and the panes whose program or directory changed. With `--live`, the Tmux
+    /// sessions are captured as `save` would, with the same options.
+    Diff {
+        /// Also print a unified diff of the panes whose content changed.
+        #[arg(long, action = ArgAction::SetTrue)]
+        content: bool,
+
+        /// Compare the backup to the live Tmux sessions.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "new_backup_filepath")]
+        live: bool,
+
+        /// Choose how the live panes are captured, with `--live`.
+        #[command(flatten)]
+        save: SaveConfig,
+
+        /// Path to the older backup file.
+        #[arg(value_parser, value_hint = ValueHint::FilePath)]
+        old_backup_filepath: PathBuf,
+
+        /// Path to the newer backup file.
+        #[arg(value_parser, value_hint = ValueHint::FilePath, required_unless_present = "live")]
+        new_backup_filepath: Option<PathBuf>,
+    },
+
+    /// Check the integrity of backups.
+    ///
+    /// Each archive is fully decoded, its metadata is checked against the sessions, windows and
+    /// panes it describes, and the content of each pane against the digest recorded at save time.
+    /// Exits with an error if any backup is corrupt.
+    Verify {
+        /// Choose a strategy for managing backups.
+        #[command(flatten)]
+        strategy: StrategyConfig,
+
+        /// Verify all backups of the catalog and the autosave.
+        #[arg(long, conflicts_with = "backup_filepaths")]
+        all: bool,
+
+        /// Backup files to verify.
+        #[arg(
+            value_parser,
+            value_hint = ValueHint::FilePath,
+            required_unless_present = "all"
+        )]
+        backup_filepaths: Vec<PathBuf>,
+    },
+
+    /// Config file commands.
+    Config {
+        /// Config file commands.
+        #[command(subcommand)]
+        command: ConfigSubcommand,
+    },
+
+    /// Print a shell completion script to stdout.
+    GenerateCompletion {
+        /// Shell for which you want completion.
+        #[arg(value_enum, value_parser = clap::value_parser!(Shell))]
+        shell: Shell,
+    },
+
+    /// Outputs the default tmux plugin config to stdout.
+    ///
+    /// Similar to shell completions, this is done once when installing tmux-backup. Type
+    /// `tmux-backup init > ~/.tmux/plugins/tmux-backup.tmux`. and source it
+    /// from your `~/.tmux.conf`. See the README for details.
+    Init,
+}
+
+/// Reporting mode for autosaves triggered from an external scheduler.
+#[derive(Debug, Clone, ValueEnum)]
+pub enum AutosaveTmuxOutput {
+    /// Report errors to both stderr and the Tmux status bar.
+    Errors,
+
+    /// Report successful saves to Tmux as well as errors.
+    All,
+}
+
+/// Catalog subcommands.
+#[derive(Debug, Subcommand)]
+pub enum CatalogSubcommand {
+    /// Print a list of backups to stdout.
+    ///
+    /// By default, this prints a table of backups, age and status with colors. The flag `--details`
+    /// prints additional columns.
+    ///
+    /// If the flag `--filepaths` is set, only absolute filepaths are printed. This can be used in
+    /// scripting scenarios.
+    ///
+    /// Options `--only purgeable` or `--only retainable` will list only the corresponding backups.
+    /// They will activate the flag `--filepaths` automatically. Option `--only pinned` lists the
+    /// pinned backups, and option `--only broken` the backups which cannot be read, which
+    /// requires reading each backup file.
+    List {
+        /// Add details columns to the table.
+        ///
+        /// Print number of sessions, windows and panes in the backup and the backup's format
+        /// version. This is slightly slower because it requires each backup file to be partially
+        /// read.
+        #[arg(long = "details", action = ArgAction::SetTrue)]
+        details_flag: bool,
+
+        /// List only backups having this status.
+        #[arg(long = "only", value_enum, value_parser)]
+        only_backup_status: Option<BackupStatus>,
+
+        /// Print filepaths instead of the table format.
+        #[arg(long = "filepaths", action = ArgAction::SetTrue)]
+        filepaths_flag: bool,
+    },
+
+    /// Apply the catalog's compaction strategy: this deletes all purgable backups.
+    Compact,
+
+    /// Pin a backup, so that it is never deleted by the compaction.
+    ///
+    /// A pinned backup is always retained, whatever the strategy and the limits. The pin is an
+    /// empty file next to the backup file, with the same name followed by `.pin`.
+    Pin {
+        /// Path to the backup file, or its name in the catalog.
+        #[arg(value_parser, value_hint = ValueHint::FilePath)]
+        backup_filepath: PathBuf,
+    },
+
+    /// Unpin a backup, leaving it to the compaction strategy again.
+    Unpin {
+        /// Path to the backup file, or its name in the catalog.
+        #[arg(value_parser, value_hint = ValueHint::FilePath)]
+        backup_filepath: PathBuf,
+    },
+}
+
+/// Config file subcommands.
+#[derive(Debug, Subcommand)]
+pub enum ConfigSubcommand {
+    /// Print the effective config: the config file completed with the default values.
+    ///
+    /// The output is itself a valid config file. With `--format json`, it is printed as a JSON
+    /// document.
+    Show,
+}
+
+/// Strategy values
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyValues {
+    /// Apply a most-recent strategy, keeping only n backups.
+    MostRecent,
+
+    /// Apply a classic backup strategy.
+    ///
+    /// Keep
+    /// the lastest per hour for the past 24 hours,
+    /// the lastest per day for the past 7 days,
+    /// the lastest per week of the past 4 weeks,
+    /// the lastest per month of this year.
+    Classic,
+
+    /// Apply a tiered backup strategy, keeping the latest backup per period of each tier (see
+    /// `--keep`).
+    Tiered,
+}
+
+/// Strategy configuration.
+#[derive(Debug, clap::Args)]
+pub struct StrategyConfig {
+    #[arg(short = 's', long = "strategy", value_enum, default_value_t = StrategyValues::MostRecent)]
+    strategy: StrategyValues,
+
+    /// Number of recent backups to keep, for instance 10.
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "NUMBER",
+        value_parser = clap::value_parser!(u16).range(1..),
+        default_value_t = DEFAULT_NUM_BACKUPS,
+    )]
+    num_backups: u16,
+
+    /// Tiers of the tiered strategy, for instance `hourly=48,daily=14,weekly=8,monthly=24`.
+    ///
+    /// Each tier keeps the latest backup of each of its most recent periods which have a backup.
+    /// The periods are `hourly`, `daily`, `weekly`, `monthly` and `yearly`. A backup is kept if
+    /// any tier keeps it.
+    #[arg(long, value_name = "TIERS", default_value_t = Tiers::default())]
+    keep: Tiers,
+
+    /// Purge the backups older than this age, such as `30d`, whatever the strategy.
+    ///
+    /// The age is a number of hours, days, weeks or years (365 days), such as `36h`, `30d`, `8w`
+    /// or `1y`. The most recent backup is always kept.
+    #[arg(long, value_name = "AGE")]
+    max_age: Option<Age>,
+
+    /// Purge the oldest backups beyond this total size, such as `500MB`, whatever the strategy.
+    ///
+    /// The backups kept by the strategy are counted from the most recent one, with the size of
+    /// their file; the content store shared by the backups is not counted. The units are `kB`,
+    /// `MB`, `GB` and `TB`, or `KiB`, `MiB`, `GiB` and `TiB`. The most recent backup is always
+    /// kept.
+    #[arg(long, value_name = "SIZE")]
+    max_total_size: Option<Size>,
+
+    /// Always retain the labelled backups, as if they were pinned.
+    ///
+    /// Labelled backups are then shown as `pinned` in the catalog, whatever the strategy and the
+    /// limits. This is also set with `keep-labelled = true` in the config file.
+    #[arg(long, action = ArgAction::SetTrue)]
+    keep_labelled: bool,
+}
+
+/// Save configuration, shared by save and autosave.
+#[derive(Debug, clap::Args)]
+pub struct SaveConfig {
+    /// Number of lines to ignore during capture if the active command is a shell.
+    ///
+    /// At the time of saving, for each pane where the active command is a shell (see `--shell`),
+    /// the shell prompt is waiting for input. If tmux-backup naively captures the
+    /// entire history, on restoring that backup, a new shell prompt will also appear. This
+    /// obviously pollutes history with repeated shell prompts.
+    ///
+    /// If you know the number of lines your shell prompt occupies on screen, set this option
+    /// to that number (simply `1` in my case). These last lines will not be captured. On
+    /// restore, this gives the illusion of history continuity without repetition.
+    #[arg(
+        short = 'i',
+        long = "ignore-last-lines",
+        value_name = "NUMBER",
+        default_value_t = 0
+    )]
+    pub num_lines_to_drop: u8,
+
+    /// Recognize panes running this shell, and drop its prompt lines (repeatable).
+    ///
+    /// The shell is written `PATTERN` or `PATTERN=LINES`, where `LINES` replaces the number of
+    /// `--ignore-last-lines` for this shell, such as `zsh=2` for a two-line prompt. The pattern is
+    /// a glob, or a regular expression if prefixed with `re:`. It matches the program name of
+    /// the pane, without its directory and the leading `-` of login shells, so that `bash` also
+    /// matches `/bin/bash` and `-bash`. The first matching shell applies.
+    ///
+    /// Shells given here replace the default ones, `zsh`, `bash` and `fish`.
+    #[arg(long = "shell", value_name = "PATTERN[=LINES]", default_values_t = default_shells())]
+    pub shells: Vec<ShellPattern>,
+
+    /// Drop the prompt by finding it, instead of dropping a fixed number of lines.
+    ///
+    /// In the panes running a shell, the last line matching this regular expression is taken as
+    /// the first line of the prompt: this line and the lines below it, including any partially
+    /// typed command, are not captured. This suits prompts whose height varies. For instance,
+    /// `^❯ ` for a one-line prompt, or `^~?/\S* on ` for a prompt starting with the directory
+    /// and the git branch. If no line matches, `--ignore-last-lines` applies.
+    #[arg(long, value_name = "REGEX")]
+    pub prompt_pattern: Option<Regex>,
+
+    /// Capture the escape sequences of colours and attributes along with the panes content.
+    ///
+    /// On restore, the scrollback of each pane shows the same colours as when it was saved:
+    /// compiler errors, diffs or `ls --color` output. Use `--escapes false` to capture plain
+    /// text instead. The catalog details show whether a backup includes escape sequences.
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    pub escapes: bool,
+
+    /// Depth of the history captured in each pane: `all`, `visible` or a number of lines.
+    ///
+    /// A number is the maximum number of history lines kept above the visible lines of the pane,
+    /// and `visible` captures only what is on screen. This bounds the size of panes holding long
+    /// build logs. See `--history-lines-for` to choose another depth for some programs.
+    #[arg(long, value_name = "DEPTH", default_value_t = HistoryLines::All)]
+    pub history_lines: HistoryLines,
+
+    /// Depth of the history captured in the panes running this program (repeatable).
+    ///
+    /// The override is written `PATTERN=DEPTH`, such as `htop=visible` or `re:^(zsh|bash)$=all`.
+    /// The pattern matches the program name of the pane, as with `--shell`. The first matching
+    /// override replaces `--history-lines` for the pane.
+    #[arg(long = "history-lines-for", value_name = "PATTERN=DEPTH")]
+    pub history_overrides: Vec<HistoryOverride>,
+
+    /// Save only the sessions matching this pattern (repeatable).
+    ///
+    /// The pattern is a glob matching the whole session name, such as `proj-*`, or a regular
+    /// expression if prefixed with `re:`. Without this option, all sessions are saved.
+    #[arg(long = "include-session", value_name = "PATTERN")]
+    pub include_sessions: Vec<NamePattern>,
+
+    /// Leave out the sessions matching this pattern (repeatable).
+    ///
+    /// This takes precedence over `--include-session`. For instance, `--exclude-session
+    /// tmux-backup` leaves out the popup session of the tmux plugin bindings.
+    #[arg(long = "exclude-session", value_name = "PATTERN")]
+    pub exclude_sessions: Vec<NamePattern>,
+
+    /// Save only the windows whose name matches this pattern (repeatable).
+    #[arg(long = "include-window", value_name = "PATTERN")]
+    pub include_windows: Vec<NamePattern>,
+
+    /// Leave out the windows whose name matches this pattern (repeatable).
+    #[arg(long = "exclude-window", value_name = "PATTERN")]
+    pub exclude_windows: Vec<NamePattern>,
+
+    /// Save only the panes running a program matching this pattern (repeatable).
+    ///
+    /// The pattern matches the program name of the pane, as with `--shell`.
+    #[arg(long = "include-command", value_name = "PATTERN")]
+    pub include_commands: Vec<NamePattern>,
+
+    /// Leave out the panes running a program matching this pattern (repeatable).
+    ///
+    /// For instance, `--exclude-command 're:^(pass|gpg|ssh)$'` leaves out the panes which may
+    /// show secrets. Neither their content nor their metadata is saved. Windows left without
+    /// panes, and sessions left without windows, are left out too.
+    #[arg(long = "exclude-command", value_name = "PATTERN")]
+    pub exclude_commands: Vec<NamePattern>,
+
+    /// Redact secrets from the panes content and command lines with the built-in detectors.
+    ///
+    /// Before being written, the content of each pane is searched for AWS keys, GitHub tokens,
+    /// JWTs, private key blocks and `password=` values, which are replaced with `[REDACTED]`.
+    /// The number of redacted secrets is reported after saving.
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    pub redact: bool,
+
+    /// Also redact the text matching this regular expression (repeatable).
+    ///
+    /// If the expression has a group named `secret`, only this group is replaced, for instance
+    /// `token: (?P<secret>\S+)`. These patterns apply even without `--redact`.
+    #[arg(long = "redact-pattern", value_name = "REGEX")]
+    pub redact_patterns: Vec<regex::bytes::Regex>,
+
+    /// Encrypt the backup to this age recipient (repeatable).
+    ///
+    /// A recipient is an age public key such as printed by `age-keygen`. Encrypted backups are
+    /// named like `backup-20220531T123456.tar.zst.age`, and hold their panes content instead of
+    /// sharing the content store. Decrypt them with `--identity`.
+    #[arg(
+        long = "encrypt-to",
+        value_name = "RECIPIENT",
+        conflicts_with = "encrypt_with_passphrase"
+    )]
+    pub encrypt_to: Vec<x25519::Recipient>,
+
+    /// Encrypt the backup with the passphrase of the `TMUX_BACKUP_PASSPHRASE` environment
+    /// variable.
+    ///
+    /// The same variable decrypts the backup. Deriving the key from the passphrase takes about a
+    /// second, for each save and each read of the backup.
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_value_t = false,
+        default_missing_value = "true",
+        action = ArgAction::Set
+    )]
+    pub encrypt_with_passphrase: bool,
+}
+
+/// Restore configuration.
+#[derive(Debug, clap::Args)]
+pub struct RestoreConfig {
+    /// Restore only the sessions matching this pattern (repeatable).
+    ///
+    /// The pattern is a glob matching the whole session name, such as `work` or `proj-*`,
+    /// or a regular expression if prefixed with `re:`, such as `re:^(api|web)$`. Windows and
+    /// panes of the other sessions are not restored either.
+    #[arg(long = "session", value_name = "PATTERN")]
+    pub sessions: Vec<NamePattern>,
+
+    /// Choose what to do with archived sessions which already exist.
+    ///
+    /// By default, existing sessions are left untouched and not restored. `override` kills
+    /// each existing session and recreates it from the archive (except the session running
+    /// tmux-backup), `rename-live` renames the existing session aside with a suffix first, and
+    /// `rename-restored` keeps the existing session and restores the archived one under a
+    /// suffixed name such as `work-restored`.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = ExistingSessions::Skip)]
+    pub existing: ExistingSessions,
+
+    /// Shorthand for `--existing override`.
+    #[arg(long = "override", action = ArgAction::SetTrue, conflicts_with = "existing")]
+    pub override_flag: bool,
+
+    /// Suffix of renamed sessions, by default `previous` with `--existing rename-live` and
+    /// `restored` with `--existing rename-restored`.
+    #[arg(long, value_name = "SUFFIX")]
+    pub suffix: Option<String>,
+
+    /// Relaunch the panes running this program, after replaying their content (repeatable).
+    ///
+    /// Accepts a comma-separated list, such as `--relaunch nvim,htop,less`. Entries are matched
+    /// against the program saved in each pane by their first word, and their other words, such as
+    /// `-f` in `tail -f`, must be arguments of the saved command line. The saved command line is
+    /// relaunched. The other panes only start a shell, in their saved directory.
+    #[arg(long, value_name = "COMMAND", value_delimiter = ',')]
+    pub relaunch: Vec<String>,
+}
+
+//
+// Helpers
+//
+
+impl Config {
+    /// Parse the command-line arguments, then complete them with the config file.
+    ///
+    /// Options set on the command line or in the environment take precedence over the config
+    /// file, whose settings take precedence over the default values. On invalid arguments, this
+    /// prints the usage and exits.
+    pub fn load() -> Result<Self> {
+        let matches = Self::command().get_matches();
+        let mut config = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+        let must_exist = !is_default(&matches, "config_filepath");
+        let file_config = FileConfig::read(&config.config_filepath, must_exist)?;
+        validate(&file_config).map_err(|message| {
+            Error::ConfigError(format!(
+                "{}: {message}",
+                config.config_filepath.to_string_lossy()
+            ))
+        })?;
+        config.merge(file_config, &matches);
+
+        Ok(config)
+    }
+
+    /// Replace the options left to their default value in `matches` by the settings of
+    /// `file_config`, and keep the latter.
+    fn merge(&mut self, file_config: FileConfig, matches: &ArgMatches) {
+        if let Some(dirpath) = &file_config.dirpath
+            && is_default(matches, "backup_dirpath")
+        {
+            self.backup_dirpath = dirpath.clone();
+        }
+        if self.identity_filepath.is_none() {
+            self.identity_filepath = file_config.identity.clone();
+        }
+
+        if let Some((_, matches)) = matches.subcommand() {
+            match &mut self.command {
+                Command::Save { strategy, save, .. } => {
+                    strategy.merge(&file_config, matches);
+                    save.merge(&file_config.save, matches);
+                }
+                Command::Autosave { save, .. } | Command::Diff { save, .. } => {
+                    save.merge(&file_config.save, matches)
+                }
+                Command::Restore {
+                    strategy, restore, ..
+                } => {
+                    strategy.merge(&file_config, matches);
+                    restore.merge(&file_config.restore, matches);
+                }
+                Command::Catalog { strategy, .. }
+                | Command::Migrate { strategy, .. }
+                | Command::Verify { strategy, .. } => strategy.merge(&file_config, matches),
+                Command::Describe { .. }
+                | Command::Config { .. }
+                | Command::GenerateCompletion { .. }
+                | Command::Init => {}
+            }
+        }
+
+        self.file_config = file_config;
+    }
+}
+
+/// Return `true` if the argument `id` was not set on the command line nor in the environment.
+fn is_default(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        None | Some(ValueSource::DefaultValue)
+    )
+}
+
+/// Check the settings of `file_config` which their types do not constrain.
+fn validate(file_config: &FileConfig) -> std::result::Result<(), String> {
+    if file_config.num_backups == Some(0) {
+        return Err("`num-backups` must be at least 1".to_string());
+    }
+
+    let save = &file_config.save;
+    if let Some(prompt_pattern) = &save.prompt_pattern
+        && let Err(e) = Regex::new(prompt_pattern)
+    {
+        return Err(format!("invalid `save.prompt-pattern`: {e}"));
+    }
+    for pattern in save.redact_patterns.iter().flatten() {
+        if let Err(e) = regex::bytes::Regex::new(pattern) {
+            return Err(format!("invalid `save.redact-patterns`: {e}"));
+        }
+    }
+    if save.encrypt_to.as_ref().is_some_and(|r| !r.is_empty())
+        && save.encrypt_with_passphrase == Some(true)
+    {
+        return Err(
+            "`save.encrypt-to` and `save.encrypt-with-passphrase` cannot both be set".to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+impl StrategyConfig {
+    /// Replace the options left to their default value by the settings of `file_config`.
+    fn merge(&mut self, file_config: &FileConfig, matches: &ArgMatches) {
+        if let Some(strategy) = &file_config.strategy
+            && is_default(matches, "strategy")
+        {
+            self.strategy = strategy.clone();
+        }
+        if let Some(num_backups) = file_config.num_backups
+            && is_default(matches, "num_backups")
+        {
+            self.num_backups = num_backups;
+        }
+        if let Some(keep) = &file_config.keep
+            && is_default(matches, "keep")
+        {
+            self.keep = keep.clone();
+        }
+        if self.max_age.is_none() {
+            self.max_age = file_config.max_age;
+        }
+        if self.max_total_size.is_none() {
+            self.max_total_size = file_config.max_total_size;
+        }
+        if let Some(keep_labelled) = file_config.keep_labelled
+            && is_default(matches, "keep_labelled")
+        {
+            self.keep_labelled = keep_labelled;
+        }
+    }
+}
+
+impl SaveConfig {
+    /// Save options corresponding to the CLI arguments.
+    pub fn options(self) -> SaveOptions {
+        SaveOptions {
+            num_lines_to_drop: self.num_lines_to_drop as usize,
+            shells: self.shells,
+            prompt_pattern: self.prompt_pattern,
+            escapes: self.escapes,
+            history_lines: self.history_lines,
+            history_overrides: self.history_overrides,
+            sessions: NameFilter {
+                include: self.include_sessions,
+                exclude: self.exclude_sessions,
+            },
+            windows: NameFilter {
+                include: self.include_windows,
+                exclude: self.exclude_windows,
+            },
+            commands: NameFilter {
+                include: self.include_commands,
+                exclude: self.exclude_commands,
+            },
+            redactor: Redactor::new(self.redact, &self.redact_patterns),
+            encryption: if !self.encrypt_to.is_empty() {
+                Some(Encryption::Recipients(self.encrypt_to))
+            } else if self.encrypt_with_passphrase {
+                Some(Encryption::Passphrase)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Replace the options left to their default value by the settings of `file_config`.
+    fn merge(&mut self, file_config: &SaveSection, matches: &ArgMatches) {
+        if let Some(num_lines_to_drop) = file_config.ignore_last_lines
+            && is_default(matches, "num_lines_to_drop")
+        {
+            self.num_lines_to_drop = num_lines_to_drop;
+        }
+        if let Some(shells) = &file_config.shells
+            && is_default(matches, "shells")
+        {
+            self.shells = shells.clone();
+        }
+        if let Some(prompt_pattern) = &file_config.prompt_pattern
+            && is_default(matches, "prompt_pattern")
+        {
+            self.prompt_pattern = Regex::new(prompt_pattern).ok();
+        }
+        if let Some(escapes) = file_config.escapes
+            && is_default(matches, "escapes")
+        {
+            self.escapes = escapes;
+        }
+        if let Some(history_lines) = file_config.history_lines
+            && is_default(matches, "history_lines")
+        {
+            self.history_lines = history_lines;
+        }
+        if let Some(history_overrides) = &file_config.history_overrides
+            && is_default(matches, "history_overrides")
+        {
+            self.history_overrides = history_overrides.clone();
+        }
+        for (patterns, file_patterns, id) in [
+            (
+                &mut self.include_sessions,
+                &file_config.include_sessions,
+                "include_sessions",
+            ),
+            (
+                &mut self.exclude_sessions,
+                &file_config.exclude_sessions,
+                "exclude_sessions",
+            ),
+            (
+                &mut self.include_windows,
+                &file_config.include_windows,
+                "include_windows",
+            ),
+            (
+                &mut self.exclude_windows,
+                &file_config.exclude_windows,
+                "exclude_windows",
+            ),
+            (
+                &mut self.include_commands,
+                &file_config.include_commands,
+                "include_commands",
+            ),
+            (
+                &mut self.exclude_commands,
+                &file_config.exclude_commands,
+                "exclude_commands",
+            ),
+        ] {
+            if let Some(file_patterns) = file_patterns
+                && is_default(matches, id)
+            {
+                *patterns = file_patterns.clone();
+            }
+        }
+        if let Some(redact) = file_config.redact
+            && is_default(matches, "redact")
+        {
+            self.redact = redact;
+        }
+        if let Some(redact_patterns) = &file_config.redact_patterns
+            && is_default(matches, "redact_patterns")
+        {
+            self.redact_patterns = redact_patterns
+                .iter()
+                .filter_map(|pattern| regex::bytes::Regex::new(pattern).ok())
+                .collect();
+        }
+        // The config file cannot add an encryption to the one of the command line.
+        let no_encryption =
+            is_default(matches, "encrypt_to") && is_default(matches, "encrypt_with_passphrase");
+        if let Some(encrypt_to) = &file_config.encrypt_to
+            && no_encryption
+        {
+            self.encrypt_to = encrypt_to.clone();
+        }
+        if let Some(encrypt_with_passphrase) = file_config.encrypt_with_passphrase
+            && no_encryption
+        {
+            self.encrypt_with_passphrase = encrypt_with_passphrase;
+        }
+    }
+}
+
+impl RestoreConfig {
+    /// Replace the options left to their default value by the settings of `file_config`.
+    fn merge(&mut self, file_config: &RestoreSection, matches: &ArgMatches) {
+        if let Some(existing) = file_config.existing
+            && is_default(matches, "existing")
+        {
+            self.existing = existing;
+        }
+        if let Some(suffix) = &file_config.suffix
+            && is_default(matches, "suffix")
+        {
+            self.suffix = Some(suffix.clone());
+        }
+        if let Some(relaunch) = &file_config.relaunch
+            && is_default(matches, "relaunch")
+        {
+            self.relaunch = relaunch.clone();
+        }
+    }
+
+    /// Restore options corresponding to the CLI arguments.
+    pub fn options(self) -> RestoreOptions {
+        RestoreOptions {
+            sessions: self.sessions,
+            existing: if self.override_flag {
+                ExistingSessions::Override
+            } else {
+                self.existing
+            },
+            suffix: self.suffix,
+            relaunch: self.relaunch,
+            // The identity file is a global option, see `Config::identity_filepath`.
+            identity_filepath: None,
+        }
+    }
+}
+
+impl StrategyConfig {
+    /// Compaction Strategy corresponding to the CLI arguments.
+    pub fn strategy(&self) -> Strategy {
+        match self.strategy {
+            StrategyValues::MostRecent => Strategy::most_recent(self.num_backups as usize),
+            StrategyValues::Classic => Strategy::Classic,
+            StrategyValues::Tiered => Strategy::Tiered {
+                tiers: self.keep.clone(),
+            },
+        }
+    }
+
+    /// Limits on the backups kept by the strategy, corresponding to the CLI arguments.
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_age: self.max_age,
+            max_total_size: self.max_total_size,
+        }
+    }
+
+    /// Whether the labelled backups are always retained, corresponding to the CLI arguments.
+    pub fn keep_labelled(&self) -> bool {
+        self.keep_labelled
+    }
+}
+
+/// Determine the folder where to save backups.
+///
+/// If `$XDG_STATE_HOME` is defined, the function returns `$XDG_STATE_HOME/tmux-backup`, otherwise,
+/// it returns `$HOME/.local/state/tmux-backup`.
+///
+/// # Panics
+///
+/// This function panics if even `$HOME` cannot be obtained from the environment.
+fn default_backup_dirpath() -> PathBuf {
+    let state_home = match env::var("XDG_STATE_HOME") {
+        Ok(v) => PathBuf::from(v),
+        Err(_) => match env::var("HOME") {
+            Ok(v) => PathBuf::from(v).join(".local").join("state"),
+            Err(_) => panic!("Cannot find `$HOME` in the environment"),
+        },
+    };
+
+    state_home.join("tmux-backup")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    mod strategy_config {
+        use super::*;
+
+        // Helper to parse a save command and extract its strategy
+        // Note: strategy flags (-s, -n) belong to the subcommand, not the root
+        fn parse_save_strategy(subcommand_args: &[&str]) -> Strategy {
+            parse_save_strategy_config(subcommand_args).strategy()
+        }
+
+        fn parse_save_strategy_config(subcommand_args: &[&str]) -> StrategyConfig {
+            let mut full_args = vec!["tmux-backup", "save"];
+            full_args.extend(subcommand_args);
+
+            let config = Config::try_parse_from(full_args).unwrap();
+            match config.command {
+                Command::Save { strategy, .. } => strategy,
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn default_strategy_is_most_recent_with_10() {
+            let strategy = parse_save_strategy(&[]);
+
+            match strategy {
+                Strategy::KeepMostRecent { k } => assert_eq!(k, 10),
+                _ => panic!("Expected KeepMostRecent"),
+            }
+        }
+
+        #[test]
+        fn explicit_most_recent_strategy() {
+            let strategy = parse_save_strategy(&["-s", "most-recent"]);
+
+            match strategy {
+                Strategy::KeepMostRecent { k } => assert_eq!(k, 10), // default n
+                _ => panic!("Expected KeepMostRecent"),
+            }
+        }
+
+        #[test]
+        fn most_recent_with_custom_count() {
+            let strategy = parse_save_strategy(&["-s", "most-recent", "-n", "25"]);
+
+            match strategy {
+                Strategy::KeepMostRecent { k } => assert_eq!(k, 25),
+                _ => panic!("Expected KeepMostRecent"),
+            }
+        }
+
+        #[test]
+        fn classic_strategy() {
+            let strategy = parse_save_strategy(&["-s", "classic"]);
+
+            assert!(matches!(strategy, Strategy::Classic));
+        }
+
+        #[test]
+        fn long_form_arguments_work() {
+            let strategy =
+                parse_save_strategy(&["--strategy", "most-recent", "--num-backups", "42"]);
+
+            match strategy {
+                Strategy::KeepMostRecent { k } => assert_eq!(k, 42),
+                _ => panic!("Expected KeepMostRecent"),
+            }
+        }
+
+        #[test]
+        fn num_backups_ignored_for_classic() {
+            // -n is accepted but ignored for classic strategy
+            let strategy = parse_save_strategy(&["-s", "classic", "-n", "99"]);
+
+            assert!(matches!(strategy, Strategy::Classic));
+        }
+
+        #[test]
+        fn tiered_strategy_reads_the_tiers() {
+            let strategy = parse_save_strategy(&["-s", "tiered", "--keep", "daily=14,yearly=5"]);
+
+            match strategy {
+                Strategy::Tiered { tiers } => assert_eq!(tiers.to_string(), "daily=14,yearly=5"),
+                _ => panic!("Expected Tiered"),
+            }
+        }
+
+        #[test]
+        fn tiered_strategy_has_default_tiers() {
+            let strategy = parse_save_strategy(&["-s", "tiered"]);
+
+            match strategy {
+                Strategy::Tiered { tiers } => assert_eq!(tiers, Tiers::default()),
+                _ => panic!("Expected Tiered"),
+            }
+        }
+
+        #[test]
+        fn limits_are_read() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "catalog",
+                "--max-age",
+                "30d",
+                "--max-total-size",
+                "500MB",
+                "list",
+            ])
+            .unwrap();
+
+            let Command::Catalog { strategy, .. } = config.command else {
+                panic!("Expected Catalog command");
+            };
+            assert_eq!(
+                strategy.limits().to_string(),
+                "max age 30d, max total size 500MB"
+            );
+            assert!(parse_save_strategy_config(&[]).limits().is_empty());
+        }
+
+        #[test]
+        fn labelled_backups_are_optionally_kept() {
+            assert!(!parse_save_strategy_config(&[]).keep_labelled());
+            assert!(parse_save_strategy_config(&["--keep-labelled"]).keep_labelled());
+        }
+
+        #[test]
+        fn invalid_tiers_are_rejected() {
+            let result =
+                Config::try_parse_from(["tmux-backup", "save", "-s", "tiered", "--keep", "daily"]);
+
+            assert!(result.is_err());
+        }
+    }
+
+    mod cli_parsing {
+        use super::*;
+
+        #[test]
+        fn save_command_parses() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            assert!(matches!(config.command, Command::Save { .. }));
+        }
+
+        #[test]
+        fn save_with_compact_flag() {
+            let config = Config::try_parse_from(["tmux-backup", "save", "--compact"]).unwrap();
+            match config.command {
+                Command::Save { compact, .. } => assert!(compact),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_with_label_and_note() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "save",
+                "--label",
+                "before-k8s",
+                "--note",
+                "before rebuilding the k8s sessions",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Save { label, note, .. } => {
+                    assert_eq!(label.unwrap().as_str(), "before-k8s");
+                    assert_eq!(note.unwrap(), "before rebuilding the k8s sessions");
+                }
+                _ => panic!("Expected Save command"),
+            }
+
+            let result = Config::try_parse_from(["tmux-backup", "save", "--label", "my label"]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn save_with_to_tmux_flag() {
+            let config = Config::try_parse_from(["tmux-backup", "save", "--to-tmux"]).unwrap();
+            match config.command {
+                Command::Save { to_tmux, .. } => assert!(to_tmux),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_with_ignore_lines() {
+            let config = Config::try_parse_from(["tmux-backup", "save", "-i", "2"]).unwrap();
+            match config.command {
+                Command::Save { save, .. } => assert_eq!(save.num_lines_to_drop, 2),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_shells_default_to_common_shells() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            match config.command {
+                Command::Save { save, .. } => assert_eq!(save.shells, default_shells()),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_captures_escapes_by_default() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            match config.command {
+                Command::Save { save, .. } => assert!(save.escapes),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_with_plain_text() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "save", "--escapes", "false"]).unwrap();
+            match config.command {
+                Command::Save { save, .. } => assert!(!save.escapes),
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_with_history_lines() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "save",
+                "--history-lines",
+                "2000",
+                "--history-lines-for",
+                "htop=visible",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Save { save, .. } => {
+                    assert_eq!(save.history_lines, HistoryLines::Lines(2000));
+                    assert_eq!(save.history_overrides, ["htop=visible".parse().unwrap()]);
+                }
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_captures_the_entire_history_by_default() {
+            let config = Config::try_parse_from(["tmux-backup", "autosave"]).unwrap();
+            match config.command {
+                Command::Autosave { save, .. } => {
+                    assert_eq!(save.history_lines, HistoryLines::All);
+                    assert!(save.history_overrides.is_empty());
+                }
+                _ => panic!("Expected Autosave command"),
+            }
+        }
+
+        #[test]
+        fn save_rejects_invalid_history_lines() {
+            let result = Config::try_parse_from(["tmux-backup", "save", "--history-lines", "lots"]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn save_with_filters() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "autosave",
+                "--exclude-session",
+                "tmux-backup",
+                "--include-window",
+                "re:^dev",
+                "--exclude-command",
+                "pass",
+                "--exclude-command",
+                "gpg",
+            ])
+            .unwrap();
+            let Command::Autosave { save, .. } = config.command else {
+                panic!("Expected Autosave command");
+            };
+            let options = save.options();
+            assert!(!options.sessions.is_match("tmux-backup"));
+            assert!(options.windows.is_match("dev-api"));
+            assert!(!options.windows.is_match("scratch"));
+            assert!(!options.commands.is_match("gpg"));
+            assert!(options.commands.is_match("nvim"));
+        }
+
+        #[test]
+        fn save_with_redaction() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "save",
+                "--redact",
+                "--redact-pattern",
+                "sk-[a-z0-9]{8}",
+            ])
+            .unwrap();
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert!(save.redact);
+            assert_eq!(save.redact_patterns[0].as_str(), "sk-[a-z0-9]{8}");
+
+            let (content, redactions) = save.options().redactor.redact(b"sk-abcd1234 password=x");
+            assert_eq!(content, b"[REDACTED] password=[REDACTED]");
+            assert_eq!(redactions.total(), 2);
+        }
+
+        #[test]
+        fn save_does_not_redact_by_default() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert!(save.options().redactor.is_empty());
+        }
+
+        #[test]
+        fn save_with_encryption() {
+            let recipient = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+            let config =
+                Config::try_parse_from(["tmux-backup", "save", "--encrypt-to", recipient]).unwrap();
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert_eq!(
+                save.options().encryption,
+                Some(Encryption::Recipients(vec![recipient.parse().unwrap()]))
+            );
+
+            let config =
+                Config::try_parse_from(["tmux-backup", "autosave", "--encrypt-with-passphrase"])
+                    .unwrap();
+            let Command::Autosave { save, .. } = config.command else {
+                panic!("Expected Autosave command");
+            };
+            assert_eq!(save.options().encryption, Some(Encryption::Passphrase));
+        }
+
+        #[test]
+        fn save_does_not_encrypt_by_default() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert_eq!(save.options().encryption, None);
+        }
+
+        #[test]
+        fn save_rejects_invalid_encryption() {
+            assert!(
+                Config::try_parse_from(["tmux-backup", "save", "--encrypt-to", "age1nope"])
+                    .is_err()
+            );
+            assert!(
+                Config::try_parse_from([
+                    "tmux-backup",
+                    "save",
+                    "--encrypt-to",
+                    "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+                    "--encrypt-with-passphrase",
+                ])
+                .is_err()
+            );
+        }
+
+        #[test]
+        fn identity_is_a_global_option() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "catalog",
+                "list",
+                "--identity",
+                "/keys/identity.txt",
+            ])
+            .unwrap();
+            assert_eq!(
+                config.identity_filepath,
+                Some(PathBuf::from("/keys/identity.txt"))
+            );
+        }
+
+        #[test]
+        fn save_with_prompt_pattern() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "save", "--prompt-pattern", r"^\$ "])
+                    .unwrap();
+            match config.command {
+                Command::Save { save, .. } => {
+                    assert_eq!(save.prompt_pattern.unwrap().as_str(), r"^\$ ");
+                }
+                _ => panic!("Expected Save command"),
+            }
+        }
+
+        #[test]
+        fn save_rejects_invalid_prompt_pattern() {
+            let result =
+                Config::try_parse_from(["tmux-backup", "save", "--prompt-pattern", "(unclosed"]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn save_rejects_invalid_shell_patterns() {
+            let result = Config::try_parse_from(["tmux-backup", "save", "--shell", "re:("]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn autosave_command_parses() {
+            let config = Config::try_parse_from(["tmux-backup", "autosave"]).unwrap();
+            assert!(matches!(config.command, Command::Autosave { .. }));
+        }
+
+        #[test]
+        fn autosave_with_ignore_lines() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "autosave", "--ignore-last-lines", "2"])
+                    .unwrap();
+            match config.command {
+                Command::Autosave { save, .. } => assert_eq!(save.num_lines_to_drop, 2),
+                _ => panic!("Expected Autosave command"),
+            }
+        }
+
+        #[test]
+        fn autosave_with_tmux_errors() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "autosave", "--to-tmux", "errors"]).unwrap();
+            match config.command {
+                Command::Autosave { to_tmux, .. } => {
+                    assert!(matches!(to_tmux, Some(AutosaveTmuxOutput::Errors)));
+                }
+                _ => panic!("Expected Autosave command"),
+            }
+        }
+
+        #[test]
+        fn autosave_with_tmux_all() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "autosave", "--to-tmux", "all"]).unwrap();
+            match config.command {
+                Command::Autosave { to_tmux, .. } => {
+                    assert!(matches!(to_tmux, Some(AutosaveTmuxOutput::All)));
+                }
+                _ => panic!("Expected Autosave command"),
+            }
+        }
+
+        #[test]
+        fn autosave_rejects_save_only_options() {
+            for option in ["--compact", "--strategy", "--num-backups"] {
+                let result = Config::try_parse_from(["tmux-backup", "autosave", option]);
+                assert!(result.is_err(), "Expected {option} to be rejected");
+            }
+        }
+
+        #[test]
+        fn restore_command_parses() {
+            let config = Config::try_parse_from(["tmux-backup", "restore"]).unwrap();
+            assert!(matches!(config.command, Command::Restore { .. }));
+        }
+
+        #[test]
+        fn restore_dry_run() {
+            let config = Config::try_parse_from(["tmux-backup", "restore", "--dry-run"]).unwrap();
+            match config.command {
+                Command::Restore { dry_run, .. } => assert!(dry_run),
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_with_specific_file() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "restore", "/path/to/backup.tar.zst"])
+                    .unwrap();
+            match config.command {
+                Command::Restore {
+                    backup_filepath, ..
+                } => {
+                    assert_eq!(
+                        backup_filepath,
+                        Some(PathBuf::from("/path/to/backup.tar.zst"))
+                    );
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_with_label() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "restore", "--label", "before-k8s"])
+                    .unwrap();
+            match config.command {
+                Command::Restore { label, .. } => {
+                    assert_eq!(label.unwrap().as_str(), "before-k8s");
+                }
+                _ => panic!("Expected Restore command"),
+            }
+
+            let result = Config::try_parse_from([
+                "tmux-backup",
+                "restore",
+                "--label",
+                "before-k8s",
+                "/path/to/backup.tar.zst",
+            ]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn restore_with_repeated_session_patterns() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "restore",
+                "--session",
+                "work",
+                "--session",
+                "re:^proj-",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Restore { restore, .. } => {
+                    let patterns: Vec<_> = restore.sessions.iter().map(|p| p.to_string()).collect();
+                    assert_eq!(patterns, ["work", "re:^proj-"]);
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_override_flag_is_a_shorthand() {
+            let config = Config::try_parse_from(["tmux-backup", "restore", "--override"]).unwrap();
+            match config.command {
+                Command::Restore { restore, .. } => {
+                    assert_eq!(restore.options().existing, ExistingSessions::Override);
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_existing_defaults_to_skip() {
+            let config = Config::try_parse_from(["tmux-backup", "restore"]).unwrap();
+            match config.command {
+                Command::Restore { restore, .. } => {
+                    assert_eq!(restore.options().existing, ExistingSessions::Skip);
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_override_conflicts_with_existing() {
+            let result = Config::try_parse_from([
+                "tmux-backup",
+                "restore",
+                "--override",
+                "--existing",
+                "rename-restored",
+            ]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn restore_relaunch_accepts_repeated_and_comma_separated_commands() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "restore",
+                "--relaunch",
+                "nvim,htop",
+                "--relaunch",
+                "less",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Restore { restore, .. } => {
+                    assert_eq!(restore.options().relaunch, vec!["nvim", "htop", "less"]);
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_relaunch_accepts_arguments() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "restore", "--relaunch", "nvim,tail -f"])
+                    .unwrap();
+            match config.command {
+                Command::Restore { restore, .. } => {
+                    assert_eq!(restore.options().relaunch, vec!["nvim", "tail -f"]);
+                }
+                _ => panic!("Expected Restore command"),
+            }
+        }
+
+        #[test]
+        fn restore_rejects_invalid_session_regex() {
+            let result =
+                Config::try_parse_from(["tmux-backup", "restore", "--session", "re:(unclosed"]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn catalog_list_command() {
+            let config = Config::try_parse_from(["tmux-backup", "catalog", "list"]).unwrap();
+            match config.command {
+                Command::Catalog { command, .. } => {
+                    assert!(matches!(command, CatalogSubcommand::List { .. }));
+                }
+                _ => panic!("Expected Catalog command"),
+            }
+        }
+
+        #[test]
+        fn catalog_list_with_details() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "catalog", "list", "--details"]).unwrap();
+            match config.command {
+                Command::Catalog { command, .. } => match command {
+                    CatalogSubcommand::List { details_flag, .. } => {
+                        assert!(details_flag);
+                    }
+                    _ => panic!("Expected List subcommand"),
+                },
+                _ => panic!("Expected Catalog command"),
+            }
+        }
+
+        #[test]
+        fn catalog_list_with_only_purgeable() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "catalog", "list", "--only", "purgeable"])
+                    .unwrap();
+            match config.command {
+                Command::Catalog { command, .. } => match command {
+                    CatalogSubcommand::List {
+                        only_backup_status, ..
+                    } => {
+                        assert!(matches!(only_backup_status, Some(BackupStatus::Purgeable)));
+                    }
+                    _ => panic!("Expected List subcommand"),
+                },
+                _ => panic!("Expected Catalog command"),
+            }
+        }
+
+        #[test]
+        fn catalog_compact_command() {
+            let config = Config::try_parse_from(["tmux-backup", "catalog", "compact"]).unwrap();
+            match config.command {
+                Command::Catalog { command, .. } => {
+                    assert!(matches!(command, CatalogSubcommand::Compact));
+                }
+                _ => panic!("Expected Catalog command"),
+            }
+        }
+
+        #[test]
+        fn catalog_pin_command() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "catalog",
+                "pin",
+                "backup-20220910T172024.141993.tar.zst",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Catalog {
+                    command: CatalogSubcommand::Pin { backup_filepath },
+                    ..
+                } => {
+                    assert_eq!(
+                        backup_filepath,
+                        PathBuf::from("backup-20220910T172024.141993.tar.zst")
+                    );
+                }
+                _ => panic!("Expected Pin subcommand"),
+            }
+            assert!(Config::try_parse_from(["tmux-backup", "catalog", "unpin"]).is_err());
+        }
+
+        #[test]
+        fn custom_backup_dirpath() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "-d", "/custom/path", "save"]).unwrap();
+            assert_eq!(config.backup_dirpath, PathBuf::from("/custom/path"));
+        }
+
+        #[test]
+        fn format_defaults_to_text() {
+            let config = Config::try_parse_from(["tmux-backup", "save"]).unwrap();
+            assert_eq!(config.format, Format::Text);
+        }
+
+        #[test]
+        fn format_is_accepted_after_the_subcommand() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "catalog", "list", "--format", "ndjson"])
+                    .unwrap();
+            assert_eq!(config.format, Format::Ndjson);
+        }
+
+        #[test]
+        fn describe_command() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "describe", "/path/to/backup.tar.zst"])
+                    .unwrap();
+            match config.command {
+                Command::Describe { backup_filepath } => {
+                    assert_eq!(backup_filepath, PathBuf::from("/path/to/backup.tar.zst"));
+                }
+                _ => panic!("Expected Describe command"),
+            }
+        }
+
+        #[test]
+        fn migrate_command_defaults_to_the_catalog() {
+            let config = Config::try_parse_from(["tmux-backup", "migrate"]).unwrap();
+            match config.command {
+                Command::Migrate {
+                    backup_filepaths, ..
+                } => assert!(backup_filepaths.is_empty()),
+                _ => panic!("Expected Migrate command"),
+            }
+        }
+
+        #[test]
+        fn migrate_command_with_files() {
+            let config = Config::try_parse_from([
+                "tmux-backup",
+                "migrate",
+                "/tmp/a.tar.zst",
+                "/tmp/b.tar.zst",
+            ])
+            .unwrap();
+            match config.command {
+                Command::Migrate {
+                    backup_filepaths, ..
+                } => assert_eq!(backup_filepaths.len(), 2),
+                _ => panic!("Expected Migrate command"),
+            }
+        }
+
+        #[test]
+        fn diff_command_needs_a_second_backup_or_live() {
+            assert!(Config::try_parse_from(["tmux-backup", "diff", "/tmp/a.tar.zst"]).is_err());
+            assert!(
+                Config::try_parse_from([
+                    "tmux-backup",
+                    "diff",
+                    "--live",
+                    "/tmp/a.tar.zst",
+                    "/tmp/b.tar.zst"
+                ])
+                .is_err()
+            );
+
+            let config =
+                Config::try_parse_from(["tmux-backup", "diff", "--live", "/tmp/a.tar.zst"])
+                    .unwrap();
+            match config.command {
+                Command::Diff {
+                    live,
+                    new_backup_filepath,
+                    ..
+                } => {
+                    assert!(live);
+                    assert!(new_backup_filepath.is_none());
+                }
+                _ => panic!("Expected Diff command"),
+            }
+        }
+
+        #[test]
+        fn verify_command_needs_files_or_all() {
+            assert!(Config::try_parse_from(["tmux-backup", "verify"]).is_err());
+            assert!(
+                Config::try_parse_from(["tmux-backup", "verify", "--all", "/tmp/a.tar.zst"])
+                    .is_err()
+            );
+
+            let config = Config::try_parse_from(["tmux-backup", "verify", "--all"]).unwrap();
+            match config.command {
+                Command::Verify {
+                    all,
+                    backup_filepaths,
+                    ..
+                } => {
+                    assert!(all);
+                    assert!(backup_filepaths.is_empty());
+                }
+                _ => panic!("Expected Verify command"),
+            }
+        }
+
+        #[test]
+        fn generate_completion_command() {
+            let config =
+                Config::try_parse_from(["tmux-backup", "generate-completion", "bash"]).unwrap();
+            match config.command {
+                Command::GenerateCompletion { shell } => {
+                    assert!(matches!(shell, Shell::Bash));
+                }
+                _ => panic!("Expected GenerateCompletion command"),
+            }
+        }
+
+        #[test]
+        fn init_command() {
+            let config = Config::try_parse_from(["tmux-backup", "init"]).unwrap();
+            assert!(matches!(config.command, Command::Init));
+        }
+
+        #[test]
+        fn rejects_invalid_num_backups_zero() {
+            let result = Config::try_parse_from(["tmux-backup", "-n", "0", "save"]);
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn rejects_negative_num_backups() {
+            let result = Config::try_parse_from(["tmux-backup", "-n", "-5", "save"]);
+            assert!(result.is_err());
+        }
+    }
+
+    mod config_file {
+        use super::*;
+
+        /// Parse the command-line `args` and merge the config file `text` into them.
+        fn load(args: &[&str], text: &str) -> Config {
+            let mut full_args = vec!["tmux-backup"];
+            full_args.extend(args);
+            let matches = Config::command().try_get_matches_from(full_args).unwrap();
+            let mut config = Config::from_arg_matches(&matches).unwrap();
+
+            let dir = tempfile::TempDir::new().unwrap();
+            let filepath = dir.path().join("config.toml");
+            std::fs::write(&filepath, text).unwrap();
+            config.merge(FileConfig::read(&filepath, true).unwrap(), &matches);
+            config
+        }
+
+        const TEXT: &str = r#"
+dirpath = "/srv/backups"
+strategy = "most-recent"
+num-backups = 20
+
+[save]
+ignore-last-lines = 1
+shells = ["zsh=2", "nu"]
+prompt-pattern = '^❯ '
+escapes = false
+history-lines = 5000
+history-lines-for = ["htop=visible"]
+exclude-sessions = ["tmux-backup"]
+redact = true
+
+[restore]
+existing = "rename-live"
+relaunch = ["nvim"]
+"#;
+
+        #[test]
+        fn settings_replace_the_defaults() {
+            let config = load(&["save"], TEXT);
+
+            assert_eq!(config.backup_dirpath, PathBuf::from("/srv/backups"));
+            let Command::Save { strategy, save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert!(matches!(
+                strategy.strategy(),
+                Strategy::KeepMostRecent { k: 20 }
+            ));
+            let options = save.options();
+            assert_eq!(options.num_lines_to_drop, 1);
+            let shells: Vec<String> = options.shells.iter().map(|s| s.to_string()).collect();
+            assert_eq!(shells, ["zsh=2", "nu"]);
+            assert_eq!(options.prompt_pattern.unwrap().as_str(), "^❯ ");
+            assert!(!options.escapes);
+            assert_eq!(options.history_lines, HistoryLines::Lines(5000));
+            assert_eq!(options.history_overrides, ["htop=visible".parse().unwrap()]);
+            assert!(!options.sessions.is_match("tmux-backup"));
+            assert!(!options.redactor.is_empty());
+        }
+
+        #[test]
+        fn command_line_options_take_precedence() {
+            let config = load(
+                &["-d", "/tmp/elsewhere", "save", "-n", "3", "-i", "0"],
+                TEXT,
+            );
+
+            assert_eq!(config.backup_dirpath, PathBuf::from("/tmp/elsewhere"));
+            let Command::Save { strategy, save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert!(matches!(
+                strategy.strategy(),
+                Strategy::KeepMostRecent { k: 3 }
+            ));
+            assert_eq!(save.num_lines_to_drop, 0);
+        }
+
+        #[test]
+        fn effective_config_shows_the_command_line_identity() {
+            let text = format!("identity = \"/keys/file.txt\"\n{TEXT}");
+            let config = load(&["--identity", "/keys/cli.txt", "config", "show"], &text);
+
+            let effective = config
+                .file_config
+                .effective(&config.backup_dirpath, config.identity_filepath.as_deref());
+
+            assert_eq!(effective.identity, Some(PathBuf::from("/keys/cli.txt")));
+        }
+
+        #[test]
+        fn tiers_are_read_from_the_file() {
+            let text = "strategy = \"tiered\"\nkeep = \"daily=14,monthly=6\"\nmax-age = \"1y\"\n\
+                        keep-labelled = true\n";
+
+            let Command::Catalog { strategy, .. } = load(&["catalog", "list"], text).command else {
+                panic!("Expected Catalog command");
+            };
+            assert!(matches!(
+                strategy.strategy(),
+                Strategy::Tiered { tiers } if tiers.to_string() == "daily=14,monthly=6"
+            ));
+            assert_eq!(strategy.limits().to_string(), "max age 1y");
+            assert!(strategy.keep_labelled());
+
+            let Command::Catalog { strategy, .. } =
+                load(&["catalog", "--keep", "hourly=2", "list"], text).command
+            else {
+                panic!("Expected Catalog command");
+            };
+            assert!(matches!(
+                strategy.strategy(),
+                Strategy::Tiered { tiers } if tiers.to_string() == "hourly=2"
+            ));
+        }
+
+        #[test]
+        fn escapes_option_takes_precedence() {
+            let config = load(&["save", "--escapes"], TEXT);
+
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            assert!(save.escapes);
+        }
+
+        #[test]
+        fn shell_options_replace_the_configured_shells() {
+            let config = load(
+                &["save", "--shell", "bash", "--shell", "re:^x?onsh$=2"],
+                TEXT,
+            );
+
+            let Command::Save { save, .. } = config.command else {
+                panic!("Expected Save command");
+            };
+            let shells: Vec<String> = save.shells.iter().map(|s| s.to_string()).collect();
+            assert_eq!(shells, ["bash", "re:^x?onsh$=2"]);
+        }
+
+        #[test]
+        fn restore_settings_apply_to_restore() {
+            let config = load(&["restore"], TEXT);
+
+            let Command::Restore { restore, .. } = config.command else {
+                panic!("Expected Restore command");
+            };
+            let options = restore.options();
+            assert_eq!(options.existing, ExistingSessions::RenameLive);
+            assert_eq!(options.relaunch, ["nvim"]);
+        }
+
+        #[test]
+        fn override_flag_takes_precedence_over_the_file() {
+            let config = load(&["restore", "--override"], TEXT);
+
+            let Command::Restore { restore, .. } = config.command else {
+                panic!("Expected Restore command");
+            };
+            assert_eq!(restore.options().existing, ExistingSessions::Override);
+        }
+
+        #[test]
+        fn autosave_uses_the_save_settings() {
+            let config = load(&["autosave"], TEXT);
+
+            let Command::Autosave { save, .. } = config.command else {
+                panic!("Expected Autosave command");
+            };
+            assert_eq!(save.num_lines_to_drop, 1);
+        }
+
+        #[test]
+        fn keeps_the_file_config() {
+            let config = load(&["config", "show"], TEXT);
+
+            assert!(matches!(
+                config.command,
+                Command::Config {
+                    command: ConfigSubcommand::Show
+                }
+            ));
+            assert_eq!(config.file_config.num_backups, Some(20));
+        }
+
+        /// Return the error of the validation of the config file `text`.
+        fn invalid(text: &str) -> String {
+            validate(&toml::from_str(text).unwrap()).unwrap_err()
+        }
+
+        #[test]
+        fn validates_the_settings() {
+            assert!(validate(&toml::from_str(TEXT).unwrap()).is_ok());
+            assert_eq!(
+                invalid("num-backups = 0\n"),
+                "`num-backups` must be at least 1"
+            );
+            assert!(
+                invalid("[save]\nprompt-pattern = '(unclosed'\n")
+                    .starts_with("invalid `save.prompt-pattern`: ")
+            );
+            assert!(
+                invalid("[save]\nredact-patterns = ['(?P<secret']\n")
+                    .starts_with("invalid `save.redact-patterns`: ")
+            );
+            assert_eq!(
+                invalid(
+                    "[save]\nencrypt-to = [\"age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p\"]\nencrypt-with-passphrase = true\n"
+                ),
+                "`save.encrypt-to` and `save.encrypt-with-passphrase` cannot both be set"
+            );
+        }
+    }
+
+    // Note: Testing `default_backup_dirpath()` would require manipulating
+    // environment variables (XDG_STATE_HOME, HOME), which can interfere with
+    // other tests running in parallel. Consider using a test harness like
+    // `temp_env` or running these tests serially with `#[serial]` if needed.
+}
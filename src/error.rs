@@ -26,6 +26,34 @@ pub enum Error {
     #[error("missing metadata: `{0}`")]
     MissingMetadata(String),
 
+    /// Pane content missing from, or corrupted in, the content store.
+    #[error("missing pane content: `{0}`")]
+    MissingContent(String),
+
+    /// Backup which cannot be encrypted or decrypted.
+    #[error("encryption failed: `{0}`")]
+    Encryption(String),
+
+    /// Backup which is not part of the catalog.
+    #[error("unknown backup: `{0}`")]
+    UnknownBackup(String),
+
+    /// Labelled backup which stays pinned while labelled backups are kept.
+    #[error("labelled backups are kept, see `keep-labelled`: `{0}`")]
+    LabelledBackup(String),
+
+    /// Backup which cannot be read, and the reason why.
+    #[error("cannot read backup `{0}`: {1}")]
+    UnreadableBackup(String, String),
+
+    /// Tmux command which exited with an error.
+    #[error("tmux command failed: {0}")]
+    TmuxCommand(String),
+
+    /// Session patterns which match none of the archived sessions.
+    #[error("no session in the backup matches {0}")]
+    UnknownSession(String),
+
     /// Configuration error.
     #[error("unexpected configuration: `{0}`")]
     ConfigError(String),
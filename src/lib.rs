@@ -13,6 +13,10 @@ pub mod actions;
 pub mod config;
 pub mod error;
 pub mod management;
+pub mod pattern;
+pub mod process;
+pub mod redaction;
+pub mod report;
 pub use tmux_lib as tmux;
 
 /// Result type for this crate.
@@ -0,0 +1,278 @@
+//! Encryption of backup archives with [age](https://age-encryption.org).
+//!
+//! An encrypted backup is the age encryption of the whole tar+zstd archive. The archive then also
+//! holds the blobs of its panes content, instead of referencing them in the content store of the
+//! catalog, so nothing of an encrypted backup is left in clear next to it.
+//!
+//! Backups are encrypted to age X25519 recipients, or with the passphrase of the
+//! `TMUX_BACKUP_PASSPHRASE` environment variable. They are decrypted with the identity file passed
+//! to [`open`], or with the same passphrase, so reading an archive does not depend on whether it
+//! is encrypted.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use age::{secrecy::SecretString, stream::StreamWriter, x25519};
+
+use crate::{Result, error::Error};
+
+/// Extension appended to the filename of encrypted backups.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Environment variable holding the passphrase which encrypts and decrypts backups.
+pub const PASSPHRASE_ENV_VAR: &str = "TMUX_BACKUP_PASSPHRASE";
+
+/// First bytes of every age file.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+/// Encryption of new backups.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encryption {
+    /// Encrypt to age X25519 recipients, such as `age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zm…`.
+    Recipients(Vec<x25519::Recipient>),
+
+    /// Encrypt with the passphrase of the `TMUX_BACKUP_PASSPHRASE` environment variable.
+    Passphrase,
+}
+
+impl Encryption {
+    /// Return a writer encrypting into `output`.
+    ///
+    /// The returned writer must be completed with [`StreamWriter::finish`], otherwise the
+    /// encrypted file is truncated.
+    pub fn wrap_output<W: Write>(&self, output: W) -> Result<StreamWriter<W>> {
+        let encryptor = match self {
+            Self::Recipients(recipients) => age::Encryptor::with_recipients(
+                recipients
+                    .iter()
+                    .map(|recipient| recipient as &dyn age::Recipient),
+            )
+            .map_err(|e| Error::Encryption(e.to_string()))?,
+            Self::Passphrase => {
+                let passphrase = passphrase().ok_or_else(|| {
+                    Error::Encryption(format!("`{PASSPHRASE_ENV_VAR}` is not set"))
+                })?;
+                age::Encryptor::with_user_passphrase(passphrase)
+            }
+        };
+
+        Ok(encryptor.wrap_output(output)?)
+    }
+}
+
+/// Return `true` if the archive file at `filepath` is encrypted.
+pub fn is_encrypted<P: AsRef<Path>>(filepath: P) -> Result<bool> {
+    let mut magic = [0; AGE_MAGIC.len()];
+    match File::open(filepath)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == AGE_MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Open the archive file at `filepath`, and return a reader of its tar+zstd content.
+///
+/// An encrypted archive is decrypted with the identity file at `identity_filepath`, such as
+/// written by `age-keygen`, and with the passphrase of the environment, whichever are set.
+pub fn open<P: AsRef<Path>>(
+    filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<Box<dyn Read + Send>> {
+    open_with(filepath.as_ref(), || identities(identity_filepath))
+}
+
+/// Open the archive file at `filepath`, decrypting it with the result of `identities` if it is
+/// encrypted.
+fn open_with<F>(filepath: &Path, identities: F) -> Result<Box<dyn Read + Send>>
+where
+    F: FnOnce() -> Result<Vec<Box<dyn age::Identity>>>,
+{
+    let file = File::open(filepath)?;
+    if !is_encrypted(filepath)? {
+        return Ok(Box::new(file));
+    }
+
+    let identities = identities()?;
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(file)).map_err(decrypt_error)?;
+    let reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref()))
+        .map_err(decrypt_error)?;
+
+    Ok(Box::new(reader))
+}
+
+/// Return the identities of the identity file at `identity_filepath` and of the passphrase,
+/// whichever are set.
+fn identities(identity_filepath: Option<&Path>) -> Result<Vec<Box<dyn age::Identity>>> {
+    let mut identities: Vec<Box<dyn age::Identity>> = vec![];
+
+    if let Some(filepath) = identity_filepath {
+        let identity_file = age::IdentityFile::from_file(filepath.to_string_lossy().into_owned())
+            .map_err(|e| {
+            Error::Encryption(format!(
+                "cannot read the identity file `{}`: {e}",
+                filepath.to_string_lossy()
+            ))
+        })?;
+        identities.extend(identity_file.into_identities().map_err(decrypt_error)?);
+    }
+    if let Some(passphrase) = passphrase() {
+        identities.push(Box::new(age::scrypt::Identity::new(passphrase)));
+    }
+
+    if identities.is_empty() {
+        return Err(Error::Encryption(format!(
+            "the backup is encrypted, but neither `--identity` nor `{PASSPHRASE_ENV_VAR}` is set"
+        )));
+    }
+    Ok(identities)
+}
+
+/// Return the passphrase of the environment, if set and not empty.
+fn passphrase() -> Option<SecretString> {
+    env::var(PASSPHRASE_ENV_VAR)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+        .map(SecretString::from)
+}
+
+fn decrypt_error(error: age::DecryptError) -> Error {
+    Error::Encryption(format!("cannot decrypt the backup: {error}"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::LazyLock;
+
+    use age::secrecy::ExposeSecret;
+    use tempfile::TempDir;
+
+    /// Identity which decrypts the backups of the tests.
+    static IDENTITY: LazyLock<x25519::Identity> = LazyLock::new(x25519::Identity::generate);
+
+    /// Return the recipient of the identity which decrypts the backups of the tests.
+    pub(crate) fn recipient() -> x25519::Recipient {
+        IDENTITY.to_public()
+    }
+
+    /// Write the identity which decrypts the backups of the tests into `dirpath`, usually the
+    /// temp directory of the test, and return the path of the identity file.
+    pub(crate) fn identity_filepath(dirpath: &Path) -> PathBuf {
+        let filepath = dirpath.join("identity.txt");
+        std::fs::write(&filepath, IDENTITY.to_string().expose_secret()).unwrap();
+        filepath
+    }
+
+    fn encrypt_to(filepath: &Path, recipient: x25519::Recipient, content: &[u8]) {
+        let encryption = Encryption::Recipients(vec![recipient]);
+        let mut writer = encryption
+            .wrap_output(File::create(filepath).unwrap())
+            .unwrap();
+        writer.write_all(content).unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn read(mut reader: Box<dyn Read + Send>) -> Vec<u8> {
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn encrypted_files_are_recognized() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst.age");
+        encrypt_to(&filepath, x25519::Identity::generate().to_public(), b"");
+
+        assert!(is_encrypted(&filepath).unwrap());
+    }
+
+    #[test]
+    fn plain_and_short_files_are_not_encrypted() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst");
+
+        std::fs::write(&filepath, zstd::encode_all(&b"content"[..], 0).unwrap()).unwrap();
+        assert!(!is_encrypted(&filepath).unwrap());
+
+        std::fs::write(&filepath, b"age").unwrap();
+        assert!(!is_encrypted(&filepath).unwrap());
+    }
+
+    #[test]
+    fn decrypts_with_the_identity() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst.age");
+        let identity = x25519::Identity::generate();
+        encrypt_to(&filepath, identity.to_public(), b"content");
+
+        let reader = open_with(&filepath, || Ok(vec![Box::new(identity)])).unwrap();
+
+        assert_eq!(read(reader), b"content");
+    }
+
+    #[test]
+    fn decrypts_with_the_identity_file() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst.age");
+        encrypt_to(&filepath, recipient(), b"content");
+
+        let identity_filepath = identity_filepath(dir.path());
+        let reader = open(&filepath, Some(&identity_filepath)).unwrap();
+
+        assert_eq!(read(reader), b"content");
+    }
+
+    #[test]
+    fn plain_files_need_no_identity() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst");
+        std::fs::write(&filepath, b"content").unwrap();
+
+        let reader = open_with(&filepath, || panic!("no identity is needed")).unwrap();
+
+        assert_eq!(read(reader), b"content");
+    }
+
+    #[test]
+    fn other_identities_cannot_decrypt() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst.age");
+        encrypt_to(
+            &filepath,
+            x25519::Identity::generate().to_public(),
+            b"content",
+        );
+
+        let other = x25519::Identity::generate();
+        let result = open_with(&filepath, || Ok(vec![Box::new(other)]));
+
+        let Err(Error::Encryption(message)) = result else {
+            panic!("expected an encryption error");
+        };
+        assert!(message.starts_with("cannot decrypt the backup"));
+    }
+
+    #[test]
+    fn missing_identities_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let filepath = dir.path().join("backup.tar.zst.age");
+        encrypt_to(
+            &filepath,
+            x25519::Identity::generate().to_public(),
+            b"content",
+        );
+
+        let identity_filepath = dir.path().join("missing-identity.txt");
+        let result = open_with(&filepath, || identities(Some(&identity_filepath)));
+
+        let Err(Error::Encryption(message)) = result else {
+            panic!("expected an encryption error");
+        };
+        assert!(message.starts_with("cannot read the identity file `"));
+    }
+}
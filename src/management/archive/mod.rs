@@ -1,3 +1,410 @@
-//! Define the archive formats (only one currently) and related functions.
+//! Define the archive formats and related functions.
+//!
+//! - `v1` archives store the panes content inside the archive.
+//! - `v2` archives, the current format, reference the panes content in the content store shared
+//!   by the backups of a catalog.
+//!
+//! Archives of the current format may also be encrypted, see [`encryption`].
 
+pub mod digest;
+pub mod encryption;
+pub mod reader;
+pub mod store;
 pub mod v1;
+pub mod v2;
+
+/// Current archive format, to which the metadata of older formats is upgraded when read.
+pub use v2::{FORMAT_VERSION, HistoryLines, Metadata, PaneMetadata};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Local;
+use serde::Serialize;
+
+/// Name of the file storing the version of the archive format.
+pub const VERSION_FILENAME: &str = "version";
+
+/// Filename of the rolling autosave archive.
+pub const AUTOSAVE_FILENAME: &str = "autosave.tar.zst";
+
+/// Name of the directory storing the panes content in the backup.
+///
+/// This name is also used in the temporary directory when retrieving the panes content from Tmux.
+pub const PANES_DIR_NAME: &str = "panes-content";
+
+/// Name of the file storing the metadata in the backup.
+///
+/// This name is also used in the temporary directory when storing the catalog.
+pub const METADATA_FILENAME: &str = "metadata.json";
+
+/// Overview of the archive's content: number of sessions, windows and panes in the archive.
+///
+/// These counts are displayed after the commands such as `save`, `restore`, or `catalog list
+/// --details`.
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    /// Format version of the archive.
+    pub version: String,
+
+    /// Number of sessions in the archive.
+    pub num_sessions: u16,
+
+    /// Number of windows in the archive.
+    pub num_windows: u16,
+
+    /// Number of panes in the archive.
+    pub num_panes: u16,
+
+    /// Whether the panes content includes escape sequences of colours and attributes.
+    pub escapes: bool,
+}
+
+impl fmt::Display for Overview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "{} sessions {} windows {} panes",
+            self.num_sessions, self.num_windows, self.num_panes,
+        ))
+    }
+}
+
+/// Label naming a backup, such as `before-k8s-rebuild`.
+///
+/// A label is made of ASCII letters, digits, `-` and `_`, so that it can be part of the backup
+/// file name. It is also stored in the metadata of the backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label(String);
+
+impl Label {
+    /// Return the label as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if s.is_empty() || !s.chars().all(is_valid) {
+            return Err(format!(
+                "invalid label `{s}`, expected ASCII letters, digits, `-` and `_`"
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Return the pattern for searching the backup files.
+///
+/// This is called by the catalog command to list the available backups. The first group captures
+/// the timestamp, and the second one the label, if any.
+///
+/// # Note
+///
+/// This pattern must match the filename generated by `new_backup_filepath()`.
+pub fn backup_filepath_pattern() -> &'static str {
+    r".*backup-(\d{8}T\d{6}\.\d{6})(?:-([A-Za-z0-9_-]+))?\.tar\.zst(?:\.age)?$"
+}
+
+/// Return the filepath for a new backup, with the `.age` extension if it is `encrypted`, and the
+/// `label` after the timestamp if any, such as `backup-20220531T123456.123456-my-label.tar.zst`.
+///
+/// This is used when the function `actions::save` needs a new filepath. The filepath is based on
+/// the current timestamp and is read by the catalog using the function `backup_filepath_pattern()`.
+pub fn new_backup_filepath<P>(dirpath: P, encrypted: bool, label: Option<&Label>) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let timestamp_frag = Local::now().format("%Y%m%dT%H%M%S%.6f").to_string();
+    let label_frag = label.map(|label| format!("-{label}")).unwrap_or_default();
+    let mut backup_filename = format!("backup-{timestamp_frag}{label_frag}.tar.zst");
+    if encrypted {
+        backup_filename = format!("{backup_filename}.{}", encryption::ENCRYPTED_EXTENSION);
+    }
+    dirpath.as_ref().join(backup_filename)
+}
+
+/// Return the fixed filepath of the rolling autosave archive.
+pub fn autosave_filepath<P>(dirpath: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    dirpath.as_ref().join(AUTOSAVE_FILENAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    mod backup_filepath_pattern {
+        use super::*;
+
+        fn matches(path: &str) -> bool {
+            let pattern = backup_filepath_pattern();
+            Regex::new(pattern).unwrap().is_match(path)
+        }
+
+        fn extract_timestamp(path: &str) -> Option<String> {
+            let pattern = backup_filepath_pattern();
+            let re = Regex::new(pattern).unwrap();
+            re.captures(path).map(|c| c[1].to_string())
+        }
+
+        #[test]
+        fn matches_standard_backup_filename() {
+            assert!(matches("backup-20220910T172024.141993.tar.zst"));
+        }
+
+        #[test]
+        fn matches_with_absolute_path() {
+            assert!(matches(
+                "/home/user/.local/state/tmux-backup/backup-20220910T172024.141993.tar.zst"
+            ));
+        }
+
+        #[test]
+        fn matches_with_relative_path() {
+            assert!(matches("./backups/backup-20220910T172024.141993.tar.zst"));
+        }
+
+        #[test]
+        fn matches_encrypted_backup_filename() {
+            assert!(matches("backup-20220910T172024.141993.tar.zst.age"));
+            assert_eq!(
+                extract_timestamp("backup-20220910T172024.141993.tar.zst.age"),
+                Some("20220910T172024.141993".to_string())
+            );
+        }
+
+        #[test]
+        fn extracts_timestamp_with_microseconds() {
+            let ts = extract_timestamp("backup-20220910T172024.141993.tar.zst");
+            assert_eq!(ts, Some("20220910T172024.141993".to_string()));
+        }
+
+        #[test]
+        fn rejects_missing_extension() {
+            assert!(!matches("backup-20220910T172024.141993.tar"));
+            assert!(!matches("backup-20220910T172024.141993"));
+        }
+
+        #[test]
+        fn rejects_wrong_prefix() {
+            assert!(!matches("snapshot-20220910T172024.141993.tar.zst"));
+            assert!(!matches("20220910T172024.141993.tar.zst"));
+        }
+
+        #[test]
+        fn rejects_malformed_timestamp() {
+            // Missing T separator
+            assert!(!matches("backup-20220910172024.141993.tar.zst"));
+            // Wrong date format
+            assert!(!matches("backup-2022-09-10T17:20:24.141993.tar.zst"));
+            // Too short
+            assert!(!matches("backup-20220910T1720.141993.tar.zst"));
+        }
+
+        #[test]
+        fn matches_labelled_backup_filename() {
+            let path = "backup-20220910T172024.141993-before-k8s_2.tar.zst.age";
+            let re = Regex::new(backup_filepath_pattern()).unwrap();
+            let captures = re.captures(path).unwrap();
+            assert_eq!(&captures[1], "20220910T172024.141993");
+            assert_eq!(&captures[2], "before-k8s_2");
+            assert!(!matches("backup-20220910T172024.141993-.tar.zst"));
+            assert!(!matches("backup-20220910T172024.141993-my.label.tar.zst"));
+        }
+
+        #[test]
+        fn rejects_missing_microseconds() {
+            assert!(!matches("backup-20220910T172024.tar.zst"));
+        }
+
+        #[test]
+        fn accepts_various_valid_timestamps() {
+            // Midnight
+            assert!(matches("backup-20240101T000000.000000.tar.zst"));
+            // End of day
+            assert!(matches("backup-20241231T235959.999999.tar.zst"));
+            // Leap year date
+            assert!(matches("backup-20240229T120000.123456.tar.zst"));
+        }
+    }
+
+    mod new_backup_filepath {
+        use super::*;
+
+        #[test]
+        fn generates_path_in_given_directory() {
+            let path = new_backup_filepath("/my/backup/dir", false, None);
+            assert!(path.starts_with("/my/backup/dir"));
+        }
+
+        #[test]
+        fn generated_filename_has_correct_extension() {
+            let path = new_backup_filepath("/tmp", false, None);
+            let filename = path.file_name().unwrap().to_string_lossy();
+            assert!(filename.ends_with(".tar.zst"));
+        }
+
+        #[test]
+        fn encrypted_filename_has_the_age_extension() {
+            let path = new_backup_filepath("/tmp", true, None);
+            let filename = path.file_name().unwrap().to_string_lossy();
+            assert!(filename.ends_with(".tar.zst.age"));
+            assert!(
+                Regex::new(backup_filepath_pattern())
+                    .unwrap()
+                    .is_match(&filename)
+            );
+        }
+
+        #[test]
+        fn generated_filename_starts_with_backup() {
+            let path = new_backup_filepath("/tmp", false, None);
+            let filename = path.file_name().unwrap().to_string_lossy();
+            assert!(filename.starts_with("backup-"));
+        }
+
+        #[test]
+        fn generated_path_matches_pattern() {
+            let path = new_backup_filepath("/tmp", false, None);
+            let pattern = backup_filepath_pattern();
+            let re = Regex::new(pattern).unwrap();
+            assert!(re.is_match(&path.to_string_lossy()));
+        }
+
+        #[test]
+        fn labelled_path_matches_pattern() {
+            let label = "before-k8s".parse().unwrap();
+            let path = new_backup_filepath("/tmp", false, Some(&label));
+            let filename = path.file_name().unwrap().to_string_lossy();
+            assert!(filename.ends_with("-before-k8s.tar.zst"));
+            let re = Regex::new(backup_filepath_pattern()).unwrap();
+            assert_eq!(&re.captures(&filename).unwrap()[2], "before-k8s");
+        }
+
+        #[test]
+        fn autosave_path_is_in_given_directory() {
+            assert_eq!(
+                autosave_filepath("/my/backup/dir"),
+                PathBuf::from("/my/backup/dir/autosave.tar.zst")
+            );
+        }
+
+        #[test]
+        fn accepts_path_with_trailing_slash() {
+            let path = new_backup_filepath("/tmp/", false, None);
+            assert!(path.starts_with("/tmp"));
+        }
+
+        #[test]
+        fn works_with_pathbuf() {
+            let dir = PathBuf::from("/var/backups");
+            let path = new_backup_filepath(dir, false, None);
+            assert!(path.starts_with("/var/backups"));
+        }
+    }
+
+    mod label {
+        use super::*;
+
+        #[test]
+        fn accepts_letters_digits_dashes_and_underscores() {
+            let label: Label = "Before_k8s-2".parse().unwrap();
+            assert_eq!(label.as_str(), "Before_k8s-2");
+            assert_eq!(label.to_string(), "Before_k8s-2");
+        }
+
+        #[test]
+        fn rejects_other_characters() {
+            for s in ["", "my label", "my.label", "a/b", "été"] {
+                assert_eq!(
+                    s.parse::<Label>(),
+                    Err(format!(
+                        "invalid label `{s}`, expected ASCII letters, digits, `-` and `_`"
+                    ))
+                );
+            }
+        }
+    }
+
+    mod overview_display {
+        use super::*;
+
+        #[test]
+        fn formats_counts_correctly() {
+            let overview = Overview {
+                version: "1.0".to_string(),
+                num_sessions: 3,
+                num_windows: 12,
+                num_panes: 47,
+                escapes: true,
+            };
+
+            let output = format!("{overview}");
+            assert_eq!(output, "3 sessions 12 windows 47 panes");
+        }
+
+        #[test]
+        fn handles_singular_counts() {
+            let overview = Overview {
+                version: "1.0".to_string(),
+                num_sessions: 1,
+                num_windows: 1,
+                num_panes: 1,
+                escapes: true,
+            };
+
+            // Note: The current implementation doesn't pluralize
+            let output = format!("{overview}");
+            assert_eq!(output, "1 sessions 1 windows 1 panes");
+        }
+
+        #[test]
+        fn handles_zero_counts() {
+            let overview = Overview {
+                version: "1.0".to_string(),
+                num_sessions: 0,
+                num_windows: 0,
+                num_panes: 0,
+                escapes: true,
+            };
+
+            let output = format!("{overview}");
+            assert_eq!(output, "0 sessions 0 windows 0 panes");
+        }
+    }
+
+    mod constants {
+        use super::*;
+
+        #[test]
+        fn panes_dir_name_is_reasonable() {
+            assert_eq!(PANES_DIR_NAME, "panes-content");
+            assert!(!PANES_DIR_NAME.contains('/'));
+            assert!(!PANES_DIR_NAME.contains('\\'));
+        }
+
+        #[test]
+        fn metadata_filename_is_json() {
+            assert!(METADATA_FILENAME.ends_with(".json"));
+        }
+
+        #[test]
+        fn autosave_filename_is_fixed_archive_name() {
+            assert_eq!(AUTOSAVE_FILENAME, "autosave.tar.zst");
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Read archives of every supported format version.
+//!
+//! The `version` file of an archive selects the [`MetadataDecoder`] of its format, which upgrades
+//! the archived metadata to the current [`Metadata`]. Encrypted archives are decrypted first, see
+//! [`encryption`].
+
+use std::io::Read;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::{
+    Result,
+    error::Error,
+    management::archive::{METADATA_FILENAME, Metadata, VERSION_FILENAME, encryption, v1, v2},
+};
+
+/// Decode the metadata file of one archive format version.
+pub trait MetadataDecoder: Sync {
+    /// Format version of the archives read by this decoder.
+    fn version(&self) -> &'static str;
+
+    /// Decode the content of the metadata file, and upgrade it to the current metadata.
+    ///
+    /// The `version` of the returned metadata is still the one of the archive.
+    fn decode(&self, bytes: &[u8]) -> Result<Metadata>;
+}
+
+/// Decoders of all supported format versions, from the oldest to the current one.
+const DECODERS: &[&dyn MetadataDecoder] = &[&v1::Decoder, &v2::Decoder];
+
+/// Return the decoder of the archive format `version`.
+pub fn decoder(version: &str) -> Result<&'static dyn MetadataDecoder> {
+    DECODERS
+        .iter()
+        .find(|decoder| decoder.version() == version)
+        .copied()
+        .ok_or_else(|| {
+            let supported = DECODERS.iter().map(|d| d.version()).join(", ");
+            Error::ArchiveVersion(format!(
+                "Unsupported format version: `{version}`, expected one of {supported}"
+            ))
+        })
+}
+
+/// Open the archive file at `backup_filepath` and return its format version.
+///
+/// An encrypted archive is decrypted with the identity file at `identity_filepath`, see
+/// [`encryption::open`].
+pub fn read_version<P: AsRef<Path>>(
+    backup_filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<String> {
+    let (version, _) = read_entries(backup_filepath.as_ref(), identity_filepath, false)?;
+    Ok(version)
+}
+
+/// Open the archive file at `backup_filepath`, and read its metadata with the decoder of its
+/// format version.
+pub async fn read_metadata<P: AsRef<Path>>(
+    backup_filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<Metadata> {
+    let (version, bytes) = read_entries(backup_filepath.as_ref(), identity_filepath, true)?;
+    decoder(&version)?.decode(&bytes)
+}
+
+/// Unpack all the files of the archive at `backup_filepath` into `dest_dirpath`.
+pub fn unpack(
+    backup_filepath: &Path,
+    dest_dirpath: &Path,
+    identity_filepath: Option<&Path>,
+) -> Result<()> {
+    open_archive(backup_filepath, identity_filepath)?.unpack(dest_dirpath)?;
+    Ok(())
+}
+
+/// Open the tar archive of the backup file at `backup_filepath`, decrypting it if needed.
+pub(crate) fn open_archive(
+    backup_filepath: &Path,
+    identity_filepath: Option<&Path>,
+) -> Result<tar::Archive<impl Read>> {
+    let archive = encryption::open(backup_filepath, identity_filepath)?;
+    let dec = zstd::stream::read::Decoder::new(archive)?;
+    Ok(tar::Archive::new(dec))
+}
+
+/// Read the version file and, if `with_metadata` is `true`, the metadata file of the archive.
+fn read_entries(
+    backup_filepath: &Path,
+    identity_filepath: Option<&Path>,
+    with_metadata: bool,
+) -> Result<(String, Vec<u8>)> {
+    let mut tar = open_archive(backup_filepath, identity_filepath)?;
+
+    let mut version = String::new();
+    let mut bytes = Vec::with_capacity(8 * 1024);
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() == VERSION_FILENAME {
+            entry.read_to_string(&mut version)?;
+            if version.is_empty() {
+                return Err(Error::ArchiveVersion(
+                    "could not read the format version".to_string(),
+                ));
+            }
+            if !with_metadata {
+                return Ok((version, bytes));
+            }
+        } else if with_metadata && entry.path()?.to_string_lossy() == METADATA_FILENAME {
+            entry.read_to_end(&mut bytes)?;
+        }
+        // The panes content of older formats follows, and is not needed.
+        if !version.is_empty() && !bytes.is_empty() {
+            break;
+        }
+    }
+
+    if version.is_empty() {
+        return Err(Error::ArchiveVersion(format!(
+            "missing format version in `{}`",
+            backup_filepath.to_string_lossy()
+        )));
+    }
+    if bytes.is_empty() {
+        return Err(Error::MissingMetadata(format!(
+            "missing metadata in `{}`",
+            backup_filepath.to_string_lossy()
+        )));
+    }
+
+    Ok((version, bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_the_decoder_of_each_version() {
+        assert_eq!(decoder("1.0").unwrap().version(), v1::FORMAT_VERSION);
+        assert_eq!(decoder("2.0").unwrap().version(), v2::FORMAT_VERSION);
+    }
+
+    #[test]
+    fn current_version_is_supported() {
+        assert!(decoder(crate::management::archive::FORMAT_VERSION).is_ok());
+    }
+
+    #[test]
+    fn unknown_version_lists_the_supported_ones() {
+        let Err(Error::ArchiveVersion(message)) = decoder("0.9") else {
+            panic!("expected an archive version error");
+        };
+
+        assert!(message.contains("`0.9`"));
+        assert!(message.ends_with("1.0, 2.0"));
+    }
+
+    #[test]
+    fn v1_metadata_is_upgraded() {
+        let json = r#"{
+            "version": "1.0",
+            "client": {"session_name": "work", "last_session_name": ""},
+            "sessions": [],
+            "windows": [],
+            "panes": []
+        }"#;
+
+        let metadata = decoder("1.0").unwrap().decode(json.as_bytes()).unwrap();
+
+        assert_eq!(metadata.version, "1.0");
+        assert_eq!(metadata.client.session_name, "work");
+        assert!(metadata.pane_metadata.is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+//! Content-addressed store of the panes content, shared by all backups of a catalog.
+//!
+//! Each pane buffer is stored once, zstd-compressed, in the `blobs` directory of the catalog, under
+//! the SHA-256 digest of its uncompressed content. Archives only reference these digests, so
+//! unchanged panes are not duplicated across backups.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use async_fs as fs;
+use futures::stream::StreamExt;
+use tempfile::NamedTempFile;
+
+use crate::{Result, error::Error, management::archive::digest};
+
+/// Name of the directory storing the blobs in the catalog directory.
+pub const BLOBS_DIR_NAME: &str = "blobs";
+
+/// Extension of the blob files.
+const BLOB_EXTENSION: &str = "zst";
+
+/// Content store located in a catalog directory.
+#[derive(Debug, Clone)]
+pub struct Store {
+    /// Directory of the blobs.
+    dirpath: PathBuf,
+}
+
+impl Store {
+    /// Return the store of the catalog at `catalog_dirpath`.
+    ///
+    /// The blobs directory is only created when the first blob is written.
+    pub fn new<P: AsRef<Path>>(catalog_dirpath: P) -> Self {
+        Self {
+            dirpath: catalog_dirpath.as_ref().join(BLOBS_DIR_NAME),
+        }
+    }
+
+    /// Return the store shared by the backup at `backup_filepath` and its siblings.
+    pub fn for_backup<P: AsRef<Path>>(backup_filepath: P) -> Self {
+        Self::new(backup_filepath.as_ref().parent().unwrap_or(Path::new("")))
+    }
+
+    /// Location of the blobs directory.
+    pub fn dirpath(&self) -> &Path {
+        &self.dirpath
+    }
+
+    /// Path of the blob storing the content with `digest`.
+    pub fn blob_filepath(&self, digest: &str) -> PathBuf {
+        self.dirpath.join(blob_filename(digest))
+    }
+
+    /// Path of the blob storing the content with `digest`, relative to the catalog directory.
+    ///
+    /// Encrypted archives hold their blobs under this path.
+    pub fn relative_blob_filepath(digest: &str) -> PathBuf {
+        Path::new(BLOBS_DIR_NAME).join(blob_filename(digest))
+    }
+
+    /// Store `content` if not already present, and return its digest.
+    ///
+    /// New blobs are written to a temporary file, then atomically renamed. An existing blob has
+    /// its modification time refreshed, so a concurrent garbage collection leaves it alone.
+    pub async fn write(&self, content: &[u8]) -> Result<String> {
+        let digest = digest::sha256_hex(content);
+        let blob_filepath = self.blob_filepath(&digest);
+
+        if let Ok(file) = File::options().append(true).open(&blob_filepath) {
+            file.set_modified(SystemTime::now())?;
+            return Ok(digest);
+        }
+
+        fs::create_dir_all(&self.dirpath).await?;
+        let compressed = zstd::encode_all(content, 0)?;
+        let temp_blob = NamedTempFile::new_in(&self.dirpath)?;
+        fs::write(temp_blob.path(), compressed).await?;
+        temp_blob
+            .persist(&blob_filepath)
+            .map_err(|error| Error::from(error.error))?;
+
+        Ok(digest)
+    }
+
+    /// Return the content with `digest`, after checking it against the digest.
+    pub async fn read(&self, digest: &str) -> Result<Vec<u8>> {
+        let blob_filepath = self.blob_filepath(digest);
+        let compressed = fs::read(&blob_filepath).await.map_err(|error| {
+            Error::MissingContent(format!(
+                "cannot read blob `{}`: {error}",
+                blob_filepath.to_string_lossy()
+            ))
+        })?;
+        let content = zstd::decode_all(compressed.as_slice())?;
+
+        if digest::sha256_hex(&content) != digest {
+            return Err(Error::MissingContent(format!(
+                "blob `{}` does not match its digest",
+                blob_filepath.to_string_lossy()
+            )));
+        }
+
+        Ok(content)
+    }
+
+    /// Delete the blobs which are not `referenced` and were last modified before `older_than`.
+    ///
+    /// Recent blobs may belong to a backup being written, and are always kept. Return the number
+    /// of deleted blobs.
+    pub async fn remove_unreferenced(
+        &self,
+        referenced: &HashSet<String>,
+        older_than: SystemTime,
+    ) -> Result<usize> {
+        let mut entries = match fs::read_dir(&self.dirpath).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut num_deleted = 0;
+        while let Some(entry) = entries.next().await {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != BLOB_EXTENSION) {
+                continue;
+            }
+            let Some(digest) = path.file_stem().map(|stem| stem.to_string_lossy()) else {
+                continue;
+            };
+            if referenced.contains(digest.as_ref()) {
+                continue;
+            }
+            if fs::metadata(&path).await?.modified()? >= older_than {
+                continue;
+            }
+
+            fs::remove_file(&path).await?;
+            num_deleted += 1;
+        }
+
+        Ok(num_deleted)
+    }
+}
+
+/// Name of the blob file storing the content with `digest`.
+fn blob_filename(digest: &str) -> String {
+    format!("{digest}.{BLOB_EXTENSION}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        File::options()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn identical_content_is_stored_once() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+
+        let first = smol::block_on(store.write(b"$ ls\nfile.txt\n")).unwrap();
+        let second = smol::block_on(store.write(b"$ ls\nfile.txt\n")).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(std::fs::read_dir(store.dirpath()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn reads_back_the_content() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+
+        let digest = smol::block_on(store.write(b"hello")).unwrap();
+
+        assert_eq!(smol::block_on(store.read(&digest)).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn missing_blob_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+
+        let result = smol::block_on(store.read(&digest::sha256_hex(b"absent")));
+
+        assert!(matches!(result, Err(Error::MissingContent(_))));
+    }
+
+    #[test]
+    fn corrupted_blob_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+        let digest = smol::block_on(store.write(b"hello")).unwrap();
+        std::fs::write(
+            store.blob_filepath(&digest),
+            zstd::encode_all(&b"tampered"[..], 0).unwrap(),
+        )
+        .unwrap();
+
+        let result = smol::block_on(store.read(&digest));
+
+        assert!(matches!(result, Err(Error::MissingContent(_))));
+    }
+
+    #[test]
+    fn backup_store_is_next_to_the_backup() {
+        let store = Store::for_backup("/backups/backup-20220910T172024.141993.tar.zst");
+
+        assert_eq!(store.dirpath(), Path::new("/backups/blobs"));
+    }
+
+    #[test]
+    fn removes_only_old_unreferenced_blobs() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+        let kept = smol::block_on(store.write(b"kept")).unwrap();
+        let old = smol::block_on(store.write(b"old")).unwrap();
+        let recent = smol::block_on(store.write(b"recent")).unwrap();
+
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let a_day_ago = SystemTime::now() - Duration::from_secs(86400);
+        set_modified(&store.blob_filepath(&kept), a_day_ago);
+        set_modified(&store.blob_filepath(&old), a_day_ago);
+
+        let referenced = HashSet::from([kept.clone()]);
+        let num_deleted =
+            smol::block_on(store.remove_unreferenced(&referenced, an_hour_ago)).unwrap();
+
+        assert_eq!(num_deleted, 1);
+        assert!(store.blob_filepath(&kept).exists());
+        assert!(!store.blob_filepath(&old).exists());
+        assert!(store.blob_filepath(&recent).exists());
+    }
+
+    #[test]
+    fn rewriting_a_blob_refreshes_it() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+        let digest = smol::block_on(store.write(b"content")).unwrap();
+        let a_day_ago = SystemTime::now() - Duration::from_secs(86400);
+        set_modified(&store.blob_filepath(&digest), a_day_ago);
+
+        smol::block_on(store.write(b"content")).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let num_deleted =
+            smol::block_on(store.remove_unreferenced(&HashSet::new(), an_hour_ago)).unwrap();
+
+        assert_eq!(num_deleted, 0);
+    }
+
+    #[test]
+    fn missing_store_has_nothing_to_remove() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::new(dir.path());
+
+        let num_deleted =
+            smol::block_on(store.remove_unreferenced(&HashSet::new(), SystemTime::now())).unwrap();
+
+        assert_eq!(num_deleted, 0);
+    }
+}
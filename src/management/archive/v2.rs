@@ -0,0 +1,1010 @@
+//! Version 2 of the archive format, the current one.
+//!
+//! Archives only hold the version and metadata files. The content of each pane is stored in the
+//! content store of the catalog (see [`Store`]), and referenced by its digest in the pane metadata.
+//! Encrypted archives also hold the blobs of their panes content, under the same paths as in the
+//! catalog. Archives in format v1 are read and upgraded to this metadata.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use async_fs as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Result,
+    management::archive::{
+        METADATA_FILENAME, Overview, PANES_DIR_NAME, VERSION_FILENAME,
+        encryption::{self, Encryption},
+        reader::{self, MetadataDecoder},
+        store::Store,
+        v1,
+    },
+    pattern::NamePattern,
+    tmux,
+};
+
+/// Version of the archive format.
+pub const FORMAT_VERSION: &str = "2.0";
+
+/// Metadata of a pane which is not provided by Tmux.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneMetadata {
+    /// Id of the pane.
+    pub pane_id: tmux::pane_id::PaneId,
+
+    /// SHA-256 digest of the pane content, which addresses it in the content store.
+    pub digest: String,
+
+    /// Size of the pane content in bytes.
+    pub size: u64,
+
+    /// Command line of the foreground process of the pane, such as `["nvim", "src/lib.rs"]`.
+    ///
+    /// This is empty if it could not be read, see [`process`](crate::process).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub argv: Vec<String>,
+
+    /// Depth of the history captured above the visible lines of the pane.
+    ///
+    /// Older archives do not record it, and always captured the entire history.
+    #[serde(default)]
+    pub history_lines: HistoryLines,
+}
+
+/// Depth of the history captured in a pane, written `all`, `visible` or a number of lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum HistoryLines {
+    /// The entire history.
+    #[default]
+    All,
+
+    /// Only the visible lines, without history.
+    Visible,
+
+    /// At most this number of lines of history above the visible lines.
+    Lines(u32),
+}
+
+impl HistoryLines {
+    /// Return the start line of `capture-pane` capturing this history.
+    pub fn start_line(&self) -> String {
+        match self {
+            Self::All => "-".to_string(),
+            Self::Visible => "0".to_string(),
+            Self::Lines(n) => format!("-{n}"),
+        }
+    }
+}
+
+impl FromStr for HistoryLines {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "visible" => Ok(Self::Visible),
+            _ => s.parse().map(Self::Lines).map_err(|_| {
+                format!("invalid history lines `{s}`, expected `all`, `visible` or a number")
+            }),
+        }
+    }
+}
+
+impl TryFrom<String> for HistoryLines {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HistoryLines> for String {
+    fn from(history_lines: HistoryLines) -> Self {
+        history_lines.to_string()
+    }
+}
+
+impl fmt::Display for HistoryLines {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => f.write_str("all"),
+            Self::Visible => f.write_str("visible"),
+            Self::Lines(n) => write!(f, "{n}"),
+        }
+    }
+}
+
+/// Describes the Tmux sessions, windows & panes stored in a backup.
+///
+/// This is enough information to recreate all sessions, windows & panes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Version of the archive's format.
+    pub version: String,
+
+    /// Tmux client metadata.
+    pub client: tmux::client::Client,
+
+    /// Tmux sessions metadata.
+    pub sessions: Vec<tmux::session::Session>,
+
+    /// Tmux windows metadata.
+    pub windows: Vec<tmux::window::Window>,
+
+    /// Tmux panes metadata.
+    pub panes: Vec<tmux::pane::Pane>,
+
+    /// Content and other metadata of each pane.
+    ///
+    /// This is empty for archives upgraded from format v1, which store the panes content inside
+    /// the archive.
+    #[serde(default)]
+    pub pane_metadata: Vec<PaneMetadata>,
+
+    /// Whether the panes content includes the escape sequences of colours and attributes.
+    ///
+    /// Older archives do not record it, and were always captured with escape sequences.
+    #[serde(default = "default_escapes")]
+    pub escapes: bool,
+
+    /// Label of the backup, which is also part of its file name, see [`Label`](super::Label).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Free text describing the backup, such as why it was saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Panes content is captured with escape sequences unless stated otherwise.
+fn default_escapes() -> bool {
+    true
+}
+
+impl Metadata {
+    /// Query Tmux and return a new `Metadata` for the current client.
+    pub async fn new() -> Result<Self> {
+        let client = tmux::client::current().await?;
+        Self::new_with_client(client).await
+    }
+
+    /// Query Tmux and return new metadata for `client`.
+    ///
+    /// This is used by autosaves started outside a Tmux client, where the caller has selected a
+    /// client explicitly.
+    pub async fn new_with_client(client: tmux::client::Client) -> Result<Self> {
+        let version = FORMAT_VERSION.to_string();
+        let sessions = tmux::session::available_sessions().await?;
+        let windows = tmux::window::available_windows().await?;
+        let panes = tmux::pane::available_panes().await?;
+
+        Ok(Self {
+            version,
+            client,
+            sessions,
+            windows,
+            panes,
+            pane_metadata: vec![],
+            escapes: true,
+            label: None,
+            note: None,
+        })
+    }
+
+    /// Open the archive file at `backup_filepath` and read the version string and tmux metadata.
+    ///
+    /// Archives in older formats are upgraded to the current metadata, see [`reader`]. An
+    /// encrypted archive is decrypted with the identity file at `identity_filepath`.
+    pub async fn read_file<P: AsRef<Path>>(
+        backup_filepath: P,
+        identity_filepath: Option<&Path>,
+    ) -> Result<Self> {
+        reader::read_metadata(backup_filepath, identity_filepath).await
+    }
+
+    /// Return an overview of the metadata.
+    pub fn overview(&self) -> Overview {
+        Overview {
+            version: self.version.clone(),
+            num_sessions: self.sessions.len() as u16,
+            num_windows: self.windows.len() as u16,
+            num_panes: self.panes.len() as u16,
+            escapes: self.escapes,
+        }
+    }
+
+    /// Return the digests of the panes content referenced by this archive.
+    pub fn referenced_digests(&self) -> impl Iterator<Item = &str> {
+        self.pane_metadata.iter().map(|p| p.digest.as_str())
+    }
+
+    /// Return the command line of the foreground process of the pane `pane_id`, which is empty
+    /// if it is unknown.
+    pub fn pane_argv(&self, pane_id: &tmux::pane_id::PaneId) -> &[String] {
+        self.pane_metadata
+            .iter()
+            .find(|p| &p.pane_id == pane_id)
+            .map_or(&[], |p| p.argv.as_slice())
+    }
+
+    /// Return the depth of the history captured in the pane `pane_id`.
+    pub fn pane_history_lines(&self, pane_id: &tmux::pane_id::PaneId) -> HistoryLines {
+        self.pane_metadata
+            .iter()
+            .find(|p| &p.pane_id == pane_id)
+            .map_or_else(HistoryLines::default, |p| p.history_lines)
+    }
+
+    /// Return the list of windows in the provided session.
+    pub fn windows_related_to(
+        &self,
+        session: &tmux::session::Session,
+    ) -> Vec<tmux::window::Window> {
+        self.windows
+            .iter()
+            .filter(|&w| w.sessions.contains(&session.name))
+            .cloned()
+            .collect()
+    }
+
+    /// Return the list of panes in the provided window.
+    pub fn panes_related_to(&self, window: &tmux::window::Window) -> Vec<&tmux::pane::Pane> {
+        let pane_ids: HashSet<tmux::pane_id::PaneId> = window.pane_ids().iter().cloned().collect();
+        self.panes
+            .iter()
+            .filter(|&p| pane_ids.contains(&p.id))
+            .collect()
+    }
+
+    /// Keep only the sessions whose name matches one of the `patterns`, along with their windows
+    /// and panes.
+    ///
+    /// A window linked to several sessions is kept if one of these sessions is kept.
+    pub fn retain_sessions(&mut self, patterns: &[NamePattern]) {
+        self.retain(
+            |session| NamePattern::any_match(patterns, &session.name),
+            |_| true,
+            |_| true,
+        );
+    }
+
+    /// Keep only the sessions, windows and panes selected by `keep_session`, `keep_window` and
+    /// `keep_pane`.
+    ///
+    /// The windows of the sessions left out are left out too, unless they are linked to a kept
+    /// session, and so are the panes of the windows left out. Windows left without panes, and
+    /// then sessions left without windows, are also left out: they cannot be restored.
+    pub fn retain<S, W, P>(&mut self, keep_session: S, keep_window: W, keep_pane: P)
+    where
+        S: Fn(&tmux::session::Session) -> bool,
+        W: Fn(&tmux::window::Window) -> bool,
+        P: Fn(&tmux::pane::Pane) -> bool,
+    {
+        self.sessions.retain(keep_session);
+
+        let session_names: HashSet<&str> = self.sessions.iter().map(|s| s.name.as_str()).collect();
+        self.windows.retain(|window| {
+            keep_window(window)
+                && window
+                    .sessions
+                    .iter()
+                    .any(|name| session_names.contains(name.as_str()))
+        });
+
+        let pane_ids: HashSet<tmux::pane_id::PaneId> =
+            self.windows.iter().flat_map(|w| w.pane_ids()).collect();
+        self.panes
+            .retain(|pane| pane_ids.contains(&pane.id) && keep_pane(pane));
+
+        let pane_ids: HashSet<&tmux::pane_id::PaneId> = self.panes.iter().map(|p| &p.id).collect();
+        self.windows
+            .retain(|window| window.pane_ids().iter().any(|id| pane_ids.contains(id)));
+
+        let session_names: HashSet<&str> = self
+            .windows
+            .iter()
+            .flat_map(|w| w.sessions.iter().map(String::as_str))
+            .collect();
+        self.sessions
+            .retain(|session| session_names.contains(session.name.as_str()));
+
+        self.pane_metadata
+            .retain(|pane_metadata| pane_ids.contains(&pane_metadata.pane_id));
+    }
+
+    /// Return a tree view of the sessions, windows and panes, along with the client sessions.
+    ///
+    /// Windows and panes are listed by index. Active windows and panes are marked with `*`. Panes
+    /// show the full command line of their program when it is known, and the depth of their
+    /// captured history unless it is entire.
+    pub fn description(&self) -> String {
+        let mut text = String::new();
+
+        // Writing into a `String` cannot fail.
+        let _ = writeln!(text, "Version: {}", self.version);
+        let _ = writeln!(text, "Content: {}", self.overview());
+        let _ = writeln!(
+            text,
+            "Escape sequences: {}",
+            if self.escapes { "yes" } else { "no" }
+        );
+        let _ = writeln!(text, "Current session: {}", self.client.session_name);
+        let _ = writeln!(text, "Last session: {}", self.client.last_session_name);
+        if let Some(label) = &self.label {
+            let _ = writeln!(text, "Label: {label}");
+        }
+        if let Some(note) = &self.note {
+            let _ = writeln!(text, "Note: {note}");
+        }
+
+        for session in &self.sessions {
+            let _ = writeln!(
+                text,
+                "\n{} ({})",
+                session.name,
+                session.dirpath.to_string_lossy()
+            );
+
+            let mut windows = self.windows_related_to(session);
+            windows.sort_unstable_by_key(|w| w.index);
+
+            for (window_pos, window) in windows.iter().enumerate() {
+                let is_last_window = window_pos + 1 == windows.len();
+                let (branch, indent) = if is_last_window {
+                    ("└──", "    ")
+                } else {
+                    ("├──", "│   ")
+                };
+                let marker = if window.is_active { "*" } else { " " };
+                let _ = writeln!(
+                    text,
+                    "{branch} {}:{marker} {}  layout: {}",
+                    window.index, window.name, window.layout
+                );
+
+                let mut panes = self.panes_related_to(window);
+                panes.sort_unstable_by_key(|p| p.index);
+
+                for (pane_pos, pane) in panes.iter().enumerate() {
+                    let branch = if pane_pos + 1 == panes.len() {
+                        "└──"
+                    } else {
+                        "├──"
+                    };
+                    let marker = if pane.is_active { "*" } else { " " };
+                    let argv = self.pane_argv(&pane.id);
+                    let command = if argv.is_empty() {
+                        pane.command.clone()
+                    } else {
+                        argv.join(" ")
+                    };
+                    let history = match self.pane_history_lines(&pane.id) {
+                        HistoryLines::All => String::new(),
+                        history_lines => format!("  history: {history_lines}"),
+                    };
+                    let _ = writeln!(
+                        text,
+                        "{indent}{branch} {}:{marker} {command}  {}  \"{}\"{history}",
+                        pane.index,
+                        pane.dirpath.to_string_lossy(),
+                        pane.title
+                    );
+                }
+            }
+        }
+
+        text
+    }
+}
+
+/// Print a full description of the archive, with session and window names.
+pub async fn print_description<P>(
+    backup_filepath: P,
+    identity_filepath: Option<&Path>,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let metadata = Metadata::read_file(backup_filepath, identity_filepath).await?;
+    print!("{}", metadata.description());
+
+    Ok(())
+}
+
+/// Decoder of the metadata of v2 archives.
+pub struct Decoder;
+
+impl MetadataDecoder for Decoder {
+    fn version(&self) -> &'static str {
+        FORMAT_VERSION
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Metadata> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Create a backup archive with the `metadata` in an already-open file.
+///
+/// The panes content is expected to be in the content store already.
+pub fn create_from_file(archive: std::fs::File, metadata: &Metadata) -> Result<()> {
+    write_archive(archive, metadata, None)?;
+    Ok(())
+}
+
+/// Create an encrypted backup archive with the `metadata`, and the blobs of its panes content
+/// read from `store`, in an already-open file.
+pub fn create_encrypted_from_file(
+    archive: std::fs::File,
+    metadata: &Metadata,
+    store: &Store,
+    encryption: &Encryption,
+) -> Result<()> {
+    let output = encryption.wrap_output(archive)?;
+    write_archive(output, metadata, Some(store))?.finish()?;
+    Ok(())
+}
+
+/// Write the tar+zstd archive with the `metadata`, and the blobs of `store` it references if any,
+/// into `output`, and return it.
+fn write_archive<W: std::io::Write>(
+    output: W,
+    metadata: &Metadata,
+    store: Option<&Store>,
+) -> Result<W> {
+    let mut enc = zstd::stream::write::Encoder::new(output, 0)?;
+    // Let `verify` detect a corrupt archive by decoding it.
+    enc.include_checksum(true)?;
+    let mut tar = tar::Builder::new(enc);
+
+    append_bytes(&mut tar, VERSION_FILENAME, FORMAT_VERSION.as_bytes())?;
+    append_bytes(&mut tar, METADATA_FILENAME, &serde_json::to_vec(metadata)?)?;
+    if let Some(store) = store {
+        let digests: BTreeSet<&str> = metadata.referenced_digests().collect();
+        for digest in digests {
+            let blob = std::fs::read(store.blob_filepath(digest))?;
+            let name = Store::relative_blob_filepath(digest);
+            append_bytes(&mut tar, &name.to_string_lossy(), &blob)?;
+        }
+    }
+
+    Ok(tar.into_inner()?.finish()?)
+}
+
+/// Append a regular file named `name` with `bytes` to the archive.
+fn append_bytes<W: std::io::Write>(
+    tar: &mut tar::Builder<W>,
+    name: &str,
+    bytes: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    tar.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+/// Write the content of each pane of the backup at `backup_filepath` into `dest_dirpath`.
+///
+/// Return the directory with one `pane-<id>.txt` file per pane. The content is read from the
+/// content store next to the backup, or from the archive itself if it is encrypted or in format
+/// v1. An encrypted archive is decrypted with the identity file at `identity_filepath`.
+pub async fn extract_panes_content(
+    backup_filepath: &Path,
+    metadata: &Metadata,
+    dest_dirpath: &Path,
+    identity_filepath: Option<&Path>,
+) -> Result<PathBuf> {
+    let panes_content_dir = dest_dirpath.join(PANES_DIR_NAME);
+
+    if metadata.version == v1::FORMAT_VERSION {
+        v1::unpack(backup_filepath, dest_dirpath).await?;
+        return Ok(panes_content_dir);
+    }
+
+    // The content of a backup which cannot be read is looked up in the store.
+    let store = if matches!(encryption::is_encrypted(backup_filepath), Ok(true)) {
+        reader::unpack(backup_filepath, dest_dirpath, identity_filepath)?;
+        Store::new(dest_dirpath)
+    } else {
+        Store::for_backup(backup_filepath)
+    };
+
+    fs::create_dir_all(&panes_content_dir).await?;
+    for pane_metadata in &metadata.pane_metadata {
+        let content = store.read(&pane_metadata.digest).await?;
+        let filepath = panes_content_dir.join(format!("pane-{}.txt", pane_metadata.pane_id));
+        fs::write(filepath, content).await?;
+    }
+
+    Ok(panes_content_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+    use crate::management::archive::{digest, store::BLOBS_DIR_NAME};
+
+    fn metadata() -> Metadata {
+        let sessions = ["$1:'work':/home/user/work", "$2:'misc':/tmp"]
+            .into_iter()
+            .map(|line| tmux::session::Session::from_str(line).unwrap())
+            .collect();
+        let windows = [
+            "@1:1:false:64f0,334x85,0,0,3:'shell':'work'",
+            "@2:0:true:035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}:'editor':'work'",
+            "@3:0:true:64f1,334x85,0,0,4:'scratch':'misc'",
+        ]
+        .into_iter()
+        .map(|line| tmux::window::Window::from_str(line).unwrap())
+        .collect();
+        let panes: Vec<tmux::pane::Pane> = [
+            "%2:1:false:'host':'zsh':/home/user/work",
+            "%1:0:true:'host':'nvim':/home/user/work/src",
+            "%3:0:true:'host':'zsh':/home/user",
+            "%4:0:true:'':'htop':/tmp",
+        ]
+        .into_iter()
+        .map(|line| tmux::pane::Pane::from_str(line).unwrap())
+        .collect();
+        let pane_metadata = panes
+            .iter()
+            .map(|pane| {
+                let content = format!("content of {}", pane.id);
+                let argv = match pane.command.as_str() {
+                    "nvim" => vec!["nvim".to_string(), "src/lib.rs".to_string()],
+                    _ => vec![],
+                };
+                let history_lines = match pane.command.as_str() {
+                    "htop" => HistoryLines::Visible,
+                    _ => HistoryLines::All,
+                };
+                PaneMetadata {
+                    pane_id: pane.id.clone(),
+                    digest: digest::sha256_hex(content.as_bytes()),
+                    size: content.len() as u64,
+                    argv,
+                    history_lines,
+                }
+            })
+            .collect();
+
+        Metadata {
+            version: FORMAT_VERSION.to_string(),
+            client: tmux::client::Client::from_str("'work':'misc'").unwrap(),
+            sessions,
+            windows,
+            panes,
+            pane_metadata,
+            escapes: true,
+            label: None,
+            note: None,
+        }
+    }
+
+    mod retain {
+        use super::*;
+
+        #[test]
+        fn retain_sessions_keeps_related_windows_and_panes() {
+            let mut metadata = metadata();
+            metadata.retain_sessions(&["mi*".parse().unwrap()]);
+
+            let names: Vec<_> = metadata.sessions.iter().map(|s| s.name.as_str()).collect();
+            assert_eq!(names, ["misc"]);
+            assert_eq!(metadata.windows.len(), 1);
+            assert_eq!(metadata.windows[0].name, "scratch");
+            assert_eq!(metadata.panes.len(), 1);
+            assert_eq!(metadata.panes[0].id.as_str(), "%4");
+            assert_eq!(metadata.pane_metadata.len(), 1);
+            assert_eq!(metadata.pane_metadata[0].pane_id.as_str(), "%4");
+        }
+
+        #[test]
+        fn retain_sessions_without_match_empties_everything() {
+            let mut metadata = metadata();
+            metadata.retain_sessions(&["nope".parse().unwrap()]);
+
+            assert!(metadata.sessions.is_empty());
+            assert!(metadata.windows.is_empty());
+            assert!(metadata.panes.is_empty());
+            assert!(metadata.pane_metadata.is_empty());
+            assert!(metadata.escapes);
+        }
+
+        #[test]
+        fn retain_leaves_out_windows_and_panes() {
+            let mut metadata = metadata();
+            metadata.retain(|_| true, |w| w.name != "shell", |p| p.command != "nvim");
+
+            let names: Vec<_> = metadata.windows.iter().map(|w| w.name.as_str()).collect();
+            assert_eq!(names, ["editor", "scratch"]);
+            let pane_ids: Vec<_> = metadata.panes.iter().map(|p| p.id.as_str()).collect();
+            assert_eq!(pane_ids, ["%2", "%4"]);
+            assert_eq!(metadata.pane_metadata.len(), 2);
+        }
+
+        #[test]
+        fn retain_leaves_out_sessions_without_panes() {
+            let mut metadata = metadata();
+            metadata.retain(|_| true, |_| true, |p| p.command != "htop");
+
+            let names: Vec<_> = metadata.sessions.iter().map(|s| s.name.as_str()).collect();
+            assert_eq!(names, ["work"]);
+            assert_eq!(metadata.windows.len(), 2);
+            assert_eq!(metadata.panes.len(), 3);
+        }
+    }
+
+    mod description {
+        use super::*;
+
+        #[test]
+        fn lists_client_sessions_and_overview() {
+            let text = metadata().description();
+
+            assert!(text.starts_with(
+                "Version: 2.0\nContent: 2 sessions 3 windows 4 panes\nEscape sequences: yes\n"
+            ));
+            assert!(text.contains("Current session: work\n"));
+            assert!(text.contains("Last session: misc\n"));
+        }
+
+        #[test]
+        fn renders_a_tree_sorted_by_index() {
+            let text = metadata().description();
+
+            let expected = "\
+work (/home/user/work)
+├── 0:* editor  layout: 035d,334x85,0,0{167x85,0,0,1,166x85,168,0,2}
+│   ├── 0:* nvim src/lib.rs  /home/user/work/src  \"host\"
+│   └── 1:  zsh  /home/user/work  \"host\"
+└── 1:  shell  layout: 64f0,334x85,0,0,3
+    └── 0:* zsh  /home/user  \"host\"
+
+misc (/tmp)
+└── 0:* scratch  layout: 64f1,334x85,0,0,4
+    └── 0:* htop  /tmp  \"\"  history: visible
+";
+            assert!(text.ends_with(expected), "unexpected description:\n{text}");
+        }
+    }
+
+    mod escapes {
+        use super::*;
+
+        #[test]
+        fn older_metadata_was_captured_with_escapes() {
+            let mut json = serde_json::to_value(Metadata {
+                escapes: false,
+                ..metadata()
+            })
+            .unwrap();
+            json.as_object_mut().unwrap().remove("escapes");
+
+            let metadata: Metadata = serde_json::from_value(json).unwrap();
+
+            assert!(metadata.escapes);
+        }
+
+        #[test]
+        fn description_reports_plain_text_captures() {
+            let metadata = Metadata {
+                escapes: false,
+                ..metadata()
+            };
+
+            assert!(metadata.description().contains("\nEscape sequences: no\n"));
+        }
+    }
+
+    mod label_and_note {
+        use super::*;
+
+        #[test]
+        fn are_left_out_of_unlabelled_metadata() {
+            let json = serde_json::to_value(metadata()).unwrap();
+
+            assert!(json.get("label").is_none());
+            assert!(json.get("note").is_none());
+            assert!(!metadata().description().contains("Label:"));
+        }
+
+        #[test]
+        fn are_described_and_read_back() {
+            let metadata = Metadata {
+                label: Some("before-k8s".to_string()),
+                note: Some("before rebuilding the k8s sessions".to_string()),
+                ..metadata()
+            };
+            let text = metadata.description();
+            assert!(text.contains(
+                "Last session: misc\nLabel: before-k8s\nNote: before rebuilding the k8s sessions\n"
+            ));
+
+            let json = serde_json::to_vec(&metadata).unwrap();
+            let read: Metadata = serde_json::from_slice(&json).unwrap();
+            assert_eq!(read.label.as_deref(), Some("before-k8s"));
+            assert_eq!(read.note, metadata.note);
+        }
+    }
+
+    mod history_lines {
+        use super::*;
+
+        #[test]
+        fn parses_all_visible_and_numbers() {
+            assert_eq!("all".parse(), Ok(HistoryLines::All));
+            assert_eq!("visible".parse(), Ok(HistoryLines::Visible));
+            assert_eq!("5000".parse(), Ok(HistoryLines::Lines(5000)));
+            assert_eq!(
+                "-1".parse::<HistoryLines>(),
+                Err("invalid history lines `-1`, expected `all`, `visible` or a number".into())
+            );
+        }
+
+        #[test]
+        fn start_line_of_capture() {
+            assert_eq!(HistoryLines::All.start_line(), "-");
+            assert_eq!(HistoryLines::Visible.start_line(), "0");
+            assert_eq!(HistoryLines::Lines(200).start_line(), "-200");
+        }
+
+        #[test]
+        fn is_written_as_a_string_in_the_metadata_file() {
+            let json = r#"{"pane_id": "%1", "digest": "abc", "size": 3, "history_lines": "200"}"#;
+
+            let pane_metadata: PaneMetadata = serde_json::from_str(json).unwrap();
+
+            assert_eq!(pane_metadata.history_lines, HistoryLines::Lines(200));
+            let json = serde_json::to_value(&pane_metadata).unwrap();
+            assert_eq!(json["history_lines"], "200");
+        }
+
+        #[test]
+        fn older_metadata_captured_the_entire_history() {
+            let json = r#"{"pane_id": "%1", "digest": "abc", "size": 3}"#;
+
+            let pane_metadata: PaneMetadata = serde_json::from_str(json).unwrap();
+
+            assert_eq!(pane_metadata.history_lines, HistoryLines::All);
+        }
+    }
+
+    mod pane_argv {
+        use super::*;
+
+        #[test]
+        fn returns_the_command_line_of_the_pane() {
+            let metadata = metadata();
+            let pane_id = tmux::pane_id::PaneId::from_str("%1").unwrap();
+
+            assert_eq!(metadata.pane_argv(&pane_id), ["nvim", "src/lib.rs"]);
+        }
+
+        #[test]
+        fn is_empty_for_unknown_panes() {
+            let metadata = metadata();
+            let pane_id = tmux::pane_id::PaneId::from_str("%9").unwrap();
+
+            assert!(metadata.pane_argv(&pane_id).is_empty());
+        }
+
+        #[test]
+        fn is_optional_in_the_metadata_file() {
+            let json = r#"{"pane_id": "%1", "digest": "abc", "size": 3}"#;
+
+            let pane_metadata: PaneMetadata = serde_json::from_str(json).unwrap();
+
+            assert!(pane_metadata.argv.is_empty());
+        }
+    }
+
+    mod archive_file {
+        use super::*;
+        use tempfile::TempDir;
+
+        /// Write an archive in format v1, with the content of each pane inside.
+        fn write_v1_archive(filepath: &Path) {
+            let metadata = metadata();
+            let v1_metadata = v1::Metadata {
+                version: v1::FORMAT_VERSION.to_string(),
+                client: metadata.client,
+                sessions: metadata.sessions,
+                windows: metadata.windows,
+                panes: metadata.panes,
+            };
+
+            let archive = std::fs::File::create(filepath).unwrap();
+            let enc = zstd::stream::write::Encoder::new(archive, 0)
+                .unwrap()
+                .auto_finish();
+            let mut tar = tar::Builder::new(enc);
+            append_bytes(&mut tar, VERSION_FILENAME, v1::FORMAT_VERSION.as_bytes()).unwrap();
+            let json = serde_json::to_vec(&v1_metadata).unwrap();
+            append_bytes(&mut tar, METADATA_FILENAME, &json).unwrap();
+            append_bytes(&mut tar, "panes-content/pane-%1.txt", b"v1 content").unwrap();
+            tar.finish().unwrap();
+        }
+
+        #[test]
+        fn metadata_round_trips_through_the_archive() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+            let metadata = metadata();
+
+            create_from_file(std::fs::File::create(&filepath).unwrap(), &metadata).unwrap();
+            let read = smol::block_on(Metadata::read_file(&filepath, None)).unwrap();
+
+            assert_eq!(read.version, FORMAT_VERSION);
+            assert_eq!(read.panes.len(), 4);
+            assert_eq!(read.pane_metadata, metadata.pane_metadata);
+            assert!(read.escapes);
+        }
+
+        #[test]
+        fn plain_text_captures_are_recorded() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+            let metadata = Metadata {
+                escapes: false,
+                ..metadata()
+            };
+
+            create_from_file(std::fs::File::create(&filepath).unwrap(), &metadata).unwrap();
+            let read = smol::block_on(Metadata::read_file(&filepath, None)).unwrap();
+
+            assert!(!read.escapes);
+            assert!(!read.overview().escapes);
+        }
+
+        #[test]
+        fn archive_does_not_contain_the_panes_content() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+
+            create_from_file(std::fs::File::create(&filepath).unwrap(), &metadata()).unwrap();
+            smol::block_on(v1::unpack(filepath.as_path(), dir.path())).unwrap();
+
+            assert!(dir.path().join(METADATA_FILENAME).exists());
+            assert!(!dir.path().join(PANES_DIR_NAME).exists());
+        }
+
+        #[test]
+        fn v1_archives_are_upgraded() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+            write_v1_archive(&filepath);
+
+            let metadata = smol::block_on(Metadata::read_file(&filepath, None)).unwrap();
+
+            assert_eq!(metadata.version, v1::FORMAT_VERSION);
+            assert_eq!(metadata.sessions.len(), 2);
+            assert!(metadata.pane_metadata.is_empty());
+        }
+
+        #[test]
+        fn unknown_versions_are_rejected() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+            let archive = std::fs::File::create(&filepath).unwrap();
+            let enc = zstd::stream::write::Encoder::new(archive, 0)
+                .unwrap()
+                .auto_finish();
+            let mut tar = tar::Builder::new(enc);
+            append_bytes(&mut tar, VERSION_FILENAME, b"9.0").unwrap();
+            append_bytes(&mut tar, METADATA_FILENAME, b"{}").unwrap();
+            tar.finish().unwrap();
+            drop(tar);
+
+            let result = smol::block_on(Metadata::read_file(&filepath, None));
+
+            assert!(matches!(result, Err(Error::ArchiveVersion(_))));
+        }
+
+        #[test]
+        fn extracts_panes_content_from_the_store() {
+            let catalog_dir = TempDir::new().unwrap();
+            let dest_dir = TempDir::new().unwrap();
+            let store = Store::new(catalog_dir.path());
+            let mut metadata = metadata();
+            metadata.retain_sessions(&["misc".parse().unwrap()]);
+            let digest = smol::block_on(store.write(b"content of %4")).unwrap();
+            assert_eq!(metadata.pane_metadata[0].digest, digest);
+
+            let backup_filepath = catalog_dir.path().join("backup.tar.zst");
+            let panes_content_dir = smol::block_on(extract_panes_content(
+                &backup_filepath,
+                &metadata,
+                dest_dir.path(),
+                None,
+            ))
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read(panes_content_dir.join("pane-%4.txt")).unwrap(),
+                b"content of %4"
+            );
+        }
+
+        #[test]
+        fn missing_blob_fails_the_extraction() {
+            let catalog_dir = TempDir::new().unwrap();
+            let dest_dir = TempDir::new().unwrap();
+
+            let backup_filepath = catalog_dir.path().join("backup.tar.zst");
+            let result = smol::block_on(extract_panes_content(
+                &backup_filepath,
+                &metadata(),
+                dest_dir.path(),
+                None,
+            ));
+
+            assert!(matches!(result, Err(Error::MissingContent(_))));
+        }
+
+        #[test]
+        fn encrypted_archives_hold_their_panes_content() {
+            let capture_dir = TempDir::new().unwrap();
+            let catalog_dir = TempDir::new().unwrap();
+            let dest_dir = TempDir::new().unwrap();
+            let store = Store::new(capture_dir.path());
+            let mut metadata = metadata();
+            metadata.retain_sessions(&["misc".parse().unwrap()]);
+            smol::block_on(store.write(b"content of %4")).unwrap();
+
+            let filepath = catalog_dir.path().join("backup.tar.zst.age");
+            let encryption = Encryption::Recipients(vec![encryption::tests::recipient()]);
+            let archive = std::fs::File::create(&filepath).unwrap();
+            create_encrypted_from_file(archive, &metadata, &store, &encryption).unwrap();
+
+            assert!(encryption::is_encrypted(&filepath).unwrap());
+            let identity_filepath = encryption::tests::identity_filepath(catalog_dir.path());
+            let identity_filepath = Some(identity_filepath.as_path());
+            let read = smol::block_on(Metadata::read_file(&filepath, identity_filepath)).unwrap();
+            assert_eq!(read.pane_metadata, metadata.pane_metadata);
+
+            let panes_content_dir = smol::block_on(extract_panes_content(
+                &filepath,
+                &read,
+                dest_dir.path(),
+                identity_filepath,
+            ))
+            .unwrap();
+            assert_eq!(
+                std::fs::read(panes_content_dir.join("pane-%4.txt")).unwrap(),
+                b"content of %4"
+            );
+            assert!(!catalog_dir.path().join(BLOBS_DIR_NAME).exists());
+        }
+
+        #[test]
+        fn extracts_panes_content_from_v1_archives() {
+            let dir = TempDir::new().unwrap();
+            let dest_dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup.tar.zst");
+            write_v1_archive(&filepath);
+            let metadata = smol::block_on(Metadata::read_file(&filepath, None)).unwrap();
+
+            let panes_content_dir = smol::block_on(extract_panes_content(
+                &filepath,
+                &metadata,
+                dest_dir.path(),
+                None,
+            ))
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read(panes_content_dir.join("pane-%1.txt")).unwrap(),
+                b"v1 content"
+            );
+        }
+    }
+}
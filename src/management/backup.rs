@@ -5,11 +5,12 @@ use std::path::PathBuf;
 
 use chrono::{Duration, NaiveDateTime};
 use clap::ValueEnum;
+use serde::Serialize;
 
 /// Quick access, high-level representation of a backup.
 ///
-/// `Backup` provides only information which can be derived from the file name, avoiding to open
-/// the file, deal with the format, parse the metadata, etc.
+/// `Backup` provides only information which can be derived from the file name and the folder
+/// entry, avoiding to open the file, deal with the format, parse the metadata, etc.
 ///
 /// This is sufficient for the [`Catalog`](crate::management::catalog::Catalog) to list backups
 /// and decide whether or not a backup should be deleted or kept.
@@ -20,6 +21,17 @@ pub struct Backup {
 
     /// Backup date.
     pub creation_date: NaiveDateTime,
+
+    /// Size of the backup file in bytes.
+    pub filesize: u64,
+
+    /// Whether the backup is pinned, so that it is always retained.
+    ///
+    /// Labelled backups are pinned as well if the catalog keeps them.
+    pub pinned: bool,
+
+    /// Label of the backup, from its file name.
+    pub label: Option<String>,
 }
 
 impl Backup {
@@ -101,13 +113,20 @@ fn format_age(creation_date: NaiveDateTime, now: NaiveDateTime) -> String {
 }
 
 /// Which subset of backups to print.
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BackupStatus {
     /// Retainable backups only.
     Retainable,
 
     /// Purgeable backups only.
     Purgeable,
+
+    /// Backups which cannot be read, such as corrupt archives.
+    Broken,
+
+    /// Pinned backups, which are always retained.
+    Pinned,
 }
 
 impl fmt::Display for BackupStatus {
@@ -115,6 +134,8 @@ impl fmt::Display for BackupStatus {
         match self {
             BackupStatus::Retainable => write!(f, "{:12}", "retainable"),
             BackupStatus::Purgeable => write!(f, "{:12}", "purgeable"),
+            BackupStatus::Broken => write!(f, "{:12}", "broken"),
+            BackupStatus::Pinned => write!(f, "{:12}", "pinned"),
         }
     }
 }
@@ -132,6 +153,9 @@ mod tests {
                 .unwrap()
                 .and_hms_opt(hour, min, sec)
                 .unwrap(),
+            filesize: 0,
+            pinned: false,
+            label: None,
         }
     }
 
@@ -342,10 +366,16 @@ mod tests {
             let a = Backup {
                 filepath: PathBuf::from("/tmp/a.tar.zst"),
                 creation_date: datetime(2024, 6, 15, 10, 30, 0),
+                filesize: 0,
+                pinned: false,
+                label: None,
             };
             let b = Backup {
                 filepath: PathBuf::from("/tmp/b.tar.zst"),
                 creation_date: datetime(2024, 6, 15, 10, 30, 0),
+                filesize: 0,
+                pinned: false,
+                label: None,
             };
 
             assert_ne!(a, b);
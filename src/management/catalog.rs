@@ -1,9 +1,11 @@
 //! Catalog of all backups.
 
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
 use std::{env, iter};
 
 use async_fs as fs;
@@ -11,18 +13,27 @@ use chrono::{DateTime, Local, NaiveDateTime};
 use futures::future::join_all;
 use futures::stream::StreamExt;
 use regex::Regex;
+use serde::Serialize;
 use si_scale::helpers::bytes2;
 use smol;
 
 use crate::{
     Result,
+    error::Error,
     management::{
-        archive::v1,
+        archive::{self, Label, encryption, reader, store::Store},
         backup::{Autosave, Backup, BackupStatus},
-        compaction::{Plan, Strategy},
+        compaction::{Limits, Plan, PurgeReason, Strategy},
     },
+    report::{self, BackupRecord, Format},
 };
 
+/// Age below which unreferenced blobs are not deleted by the compaction.
+const BLOB_GRACE_PERIOD: Duration = Duration::from_secs(3600);
+
+/// Extension appended to the name of a backup file to name the empty file pinning it.
+const PIN_EXTENSION: &str = ".pin";
+
 /// Catalog of all backups.
 pub struct Catalog {
     /// Location of the catalog.
@@ -31,6 +42,15 @@ pub struct Catalog {
     /// Compaction strategy.
     pub strategy: Strategy,
 
+    /// Limits on the backups kept by the strategy.
+    pub limits: Limits,
+
+    /// Whether the labelled backups are pinned, so that they are always retained.
+    pub keep_labelled: bool,
+
+    /// Identity file decrypting the encrypted backups, to read their details.
+    pub identity_filepath: Option<PathBuf>,
+
     /// Sorted list of all backups (oldest to newest).
     pub backups: Vec<Backup>,
 
@@ -38,6 +58,42 @@ pub struct Catalog {
     pub autosave: Option<Autosave>,
 }
 
+/// Outcome of a compaction.
+#[derive(Debug, Default)]
+pub struct Compaction {
+    /// Number of deleted backups.
+    pub num_deleted: usize,
+
+    /// Number of deleted blobs of the content store.
+    pub num_blobs_deleted: usize,
+
+    /// Reason why the unreferenced blobs were kept, if they could not be collected.
+    pub blobs_error: Option<String>,
+}
+
+/// Content of a backup file, as read by `catalog list --details` and `--only broken`.
+enum Details {
+    /// Metadata of a readable backup.
+    Readable(archive::Metadata),
+
+    /// Encrypted backup which cannot be decrypted, for instance without its identity.
+    Locked,
+
+    /// Backup which cannot be read, and the reason why.
+    Broken(String),
+}
+
+/// Catalog content, as printed in the json format.
+#[derive(Serialize)]
+struct Listing<'a> {
+    strategy: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<String>,
+    dirpath: &'a Path,
+    autosave: Option<BackupRecord>,
+    backups: Vec<BackupRecord>,
+}
+
 // Public API
 
 impl Catalog {
@@ -47,17 +103,35 @@ impl Catalog {
     ///
     /// - The folder is created if missing.
     /// - The catalog only manages backup files such as `backup-20220804T221153.tar.zst`, other
-    ///   files are simply ignored (and in principle, should not be present).
-    pub async fn new<P: AsRef<Path>>(dirpath: P, strategy: Strategy) -> Result<Catalog> {
+    ///   files are simply ignored (and in principle, should not be present), except the empty
+    ///   files such as `backup-20220804T221153.tar.zst.pin` which pin a backup.
+    /// - The label of a backup follows its timestamp, as in
+    ///   `backup-20220804T221153-my-label.tar.zst`. If `keep_labelled` is `true`, the labelled
+    ///   backups are pinned.
+    /// - The panes content of the backups is stored in the `blobs` folder, see
+    ///   [`Store`].
+    /// - The `limits` apply on top of the `strategy`.
+    /// - The encrypted backups are decrypted with the identity file at `identity_filepath`, if
+    ///   any, to list their details.
+    pub async fn new<P: AsRef<Path>>(
+        dirpath: P,
+        strategy: Strategy,
+        limits: Limits,
+        keep_labelled: bool,
+        identity_filepath: Option<PathBuf>,
+    ) -> Result<Catalog> {
         let dirpath = dirpath.as_ref();
         fs::create_dir_all(dirpath).await?;
 
-        let backup_files = Self::parse_backup_filenames(dirpath).await?;
+        let backup_files = Self::parse_backup_filenames(dirpath, keep_labelled).await?;
         let autosave = Self::parse_autosave(dirpath).await?;
 
         let catalog = Catalog {
             dirpath: dirpath.to_path_buf(),
             strategy,
+            limits,
+            keep_labelled,
+            identity_filepath,
             backups: backup_files,
             autosave,
         };
@@ -69,11 +143,15 @@ impl Catalog {
     ///
     /// This returns a new catalog with the updated content.
     pub async fn refresh(self) -> Result<Catalog> {
-        let backups = Self::parse_backup_filenames(self.dirpath.as_path()).await?;
+        let backups =
+            Self::parse_backup_filenames(self.dirpath.as_path(), self.keep_labelled).await?;
         let autosave = Self::parse_autosave(self.dirpath.as_path()).await?;
         Ok(Catalog {
             dirpath: self.dirpath,
             strategy: self.strategy,
+            limits: self.limits,
+            keep_labelled: self.keep_labelled,
+            identity_filepath: self.identity_filepath,
             backups,
             autosave,
         })
@@ -81,7 +159,8 @@ impl Catalog {
 
     /// Update the catalog's list of backups with the current content of `dirpath`.
     pub async fn refresh_mut(&mut self) -> Result<()> {
-        self.backups = Self::parse_backup_filenames(self.dirpath.as_path()).await?;
+        self.backups =
+            Self::parse_backup_filenames(self.dirpath.as_path(), self.keep_labelled).await?;
         self.autosave = Self::parse_autosave(self.dirpath.as_path()).await?;
         Ok(())
     }
@@ -103,6 +182,13 @@ impl Catalog {
         self.backups.last()
     }
 
+    /// Most recent backup having the `label`.
+    pub fn latest_labelled(&self, label: &Label) -> Option<&Backup> {
+        self.backups
+            .iter()
+            .rfind(|backup| backup.label.as_deref() == Some(label.as_str()))
+    }
+
     /// Filepath of the newest archive that can be restored.
     ///
     /// This compares the latest ordinary backup with the rolling autosave archive. An autosave
@@ -119,28 +205,74 @@ impl Catalog {
     }
 
     /// Simulate the compaction strategy: list the backup files to delete, and the ones to keep.
+    ///
+    /// The backups kept by the strategy but beyond the limits are deleted as well.
     pub fn plan(&self) -> Plan<'_> {
-        self.strategy.plan(&self.backups)
+        let now = Local::now().naive_local();
+        self.limits
+            .restrict(self.strategy.plan_at(&self.backups, now), now)
+    }
+
+    /// Pin the backup at `filepath`, or named so in the catalog, and return its filepath.
+    ///
+    /// A pinned backup is always retained, whatever the strategy and the limits. The pin is an
+    /// empty file next to the backup file, with the same name followed by `.pin`.
+    pub async fn pin(&self, filepath: &Path) -> Result<PathBuf> {
+        let backup = self.find(filepath)?;
+        fs::write(pin_filepath(&backup.filepath), b"").await?;
+        Ok(backup.filepath.clone())
+    }
+
+    /// Unpin the backup at `filepath`, or named so in the catalog, and return its filepath.
+    ///
+    /// Unpinning a backup which is not pinned does nothing. A labelled backup cannot be unpinned
+    /// while `keep_labelled` is `true`: its pin file, if any, is left in place and an error is
+    /// returned.
+    pub async fn unpin(&self, filepath: &Path) -> Result<PathBuf> {
+        let backup = self.find(filepath)?;
+        if self.keep_labelled && backup.label.is_some() {
+            return Err(Error::LabelledBackup(
+                backup.filepath.to_string_lossy().to_string(),
+            ));
+        }
+        match fs::remove_file(pin_filepath(&backup.filepath)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(backup.filepath.clone())
     }
 
     /// Apply the compaction strategy.
     ///
+    /// After deleting the purgeable backups, the blobs of the content store which are referenced
+    /// by neither the retained backups nor the autosave archive are deleted as well. If they
+    /// cannot be collected, the compaction still succeeds, and tells why.
+    ///
     /// # Important
     ///
     /// This will probably delete files in the `dirpath` folder.
-    pub async fn compact(&self) -> Result<usize> {
+    pub async fn compact(&self) -> Result<Compaction> {
         let Plan {
             purgeable,
-            retainable: _retainable,
+            retainable,
             ..
         } = self.plan();
 
-        let n = purgeable.len();
+        let num_deleted = purgeable.len();
         for backup in purgeable {
             fs::remove_file(&backup.filepath).await?;
         }
 
-        Ok(n)
+        let (num_blobs_deleted, blobs_error) = match self.collect_garbage(&retainable).await {
+            Ok(num_blobs_deleted) => (num_blobs_deleted, None),
+            Err(e) => (0, Some(e.to_string())),
+        };
+
+        Ok(Compaction {
+            num_deleted,
+            num_blobs_deleted,
+            blobs_error,
+        })
     }
 
     /// Apply the compaction strategy and update the catalog.
@@ -173,14 +305,21 @@ impl Catalog {
     /// scripting scenarios.
     ///
     /// If `only_status` is a `Some(..)`, this lists only the corresponding backup filepaths,
-    /// acting as if `filepaths_flag` is `true`.
+    /// acting as if `filepaths_flag` is `true`. Listing the broken backups requires to read each
+    /// backup file.
+    ///
+    /// In the json and ndjson formats, this prints one record per backup instead, and
+    /// `filepaths_flag` is ignored.
     pub async fn list(
         &self,
         details_flag: bool,
         only_status: Option<BackupStatus>,
         filepaths_flag: bool,
+        format: Format,
     ) {
-        if filepaths_flag || only_status.is_some() {
+        if format != Format::Text {
+            self.print_records(details_flag, only_status, format).await;
+        } else if filepaths_flag || only_status.is_some() {
             match only_status {
                 Some(BackupStatus::Purgeable) => {
                     let Plan { purgeable, .. } = self.plan();
@@ -194,6 +333,19 @@ impl Catalog {
                         println!("{}", backup.filepath.to_string_lossy());
                     }
                 }
+                Some(BackupStatus::Pinned) => {
+                    for backup in self.backups.iter().filter(|backup| backup.pinned) {
+                        println!("{}", backup.filepath.to_string_lossy());
+                    }
+                }
+                Some(BackupStatus::Broken) => {
+                    let details = self.read_all_details(self.backups.iter()).await;
+                    for (backup, (_, details)) in iter::zip(&self.backups, details) {
+                        if matches!(details, Details::Broken(_)) {
+                            println!("{}", backup.filepath.to_string_lossy());
+                        }
+                    }
+                }
                 None => {
                     for backup in self.backups.iter() {
                         println!("{}", backup.filepath.to_string_lossy());
@@ -209,30 +361,67 @@ impl Catalog {
 // Private functions
 
 impl Catalog {
-    /// Return the list of `Backup` in `dirpath`.
-    async fn parse_backup_filenames<P: AsRef<Path>>(dirpath: P) -> Result<Vec<Backup>> {
+    /// Return the backup at `filepath`, or named `filepath` in the catalog.
+    fn find(&self, filepath: &Path) -> Result<&Backup> {
+        let is_name = filepath.parent() == Some(Path::new(""));
+        let canonical = std::fs::canonicalize(filepath).ok();
+        self.backups
+            .iter()
+            .find(|backup| {
+                if is_name {
+                    backup.filepath.file_name() == Some(filepath.as_os_str())
+                } else {
+                    backup.filepath == filepath
+                        || canonical.is_some()
+                            && std::fs::canonicalize(&backup.filepath).ok() == canonical
+                }
+            })
+            .ok_or_else(|| Error::UnknownBackup(filepath.to_string_lossy().to_string()))
+    }
+
+    /// Return the list of `Backup` in `dirpath`, pinning the labelled ones if `keep_labelled` is
+    /// `true`.
+    async fn parse_backup_filenames<P: AsRef<Path>>(
+        dirpath: P,
+        keep_labelled: bool,
+    ) -> Result<Vec<Backup>> {
         let mut backups: Vec<Backup> = vec![];
+        let mut pins = HashSet::new();
 
         static BACKUP_RE: LazyLock<Regex> =
-            LazyLock::new(|| Regex::new(v1::backup_filepath_pattern()).unwrap());
+            LazyLock::new(|| Regex::new(archive::backup_filepath_pattern()).unwrap());
 
         let mut entries = fs::read_dir(dirpath.as_ref()).await?;
         while let Some(entry) = entries.next().await {
             let entry = entry?;
             let path = entry.path();
+            if path.to_string_lossy().ends_with(PIN_EXTENSION) {
+                pins.insert(path);
+                continue;
+            }
             if let Some(captures) = BACKUP_RE.captures(&path.to_string_lossy()) {
                 let date_str = &captures[1];
+                let label = captures.get(2).map(|label| label.as_str().to_string());
+                // The backup may have been deleted meanwhile.
                 if let Ok(creation_date) =
                     NaiveDateTime::parse_from_str(date_str, "%Y%m%dT%H%M%S%.f")
+                    && let Ok(metadata) = entry.metadata().await
                 {
                     backups.push(Backup {
                         filepath: path,
                         creation_date,
+                        filesize: metadata.len(),
+                        pinned: false,
+                        label,
                     });
                 }
             }
         }
 
+        for backup in backups.iter_mut() {
+            backup.pinned = pins.contains(&pin_filepath(&backup.filepath))
+                || keep_labelled && backup.label.is_some();
+        }
         backups.sort_unstable_by_key(|b| b.creation_date);
 
         Ok(backups)
@@ -240,7 +429,7 @@ impl Catalog {
 
     /// Return the rolling autosave archive, if present.
     async fn parse_autosave<P: AsRef<Path>>(dirpath: P) -> Result<Option<Autosave>> {
-        let filepath = v1::autosave_filepath(dirpath);
+        let filepath = archive::autosave_filepath(dirpath);
         let metadata = match fs::metadata(&filepath).await {
             Ok(metadata) => metadata,
             Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
@@ -258,8 +447,219 @@ impl Catalog {
         }))
     }
 
+    /// Delete the blobs of the content store referenced by neither the `backups` nor the autosave
+    /// archive, and return their number.
+    ///
+    /// If one of these archives cannot be read, the blobs it references are unknown and nothing is
+    /// deleted. Encrypted archives hold their own blobs, and are not read. Blobs written recently
+    /// are kept, as they may belong to a backup being saved.
+    async fn collect_garbage(&self, backups: &[&Backup]) -> Result<usize> {
+        let filepaths = backups
+            .iter()
+            .map(|backup| backup.filepath.as_path())
+            .chain(
+                self.autosave
+                    .iter()
+                    .map(|autosave| autosave.filepath.as_path()),
+            );
+
+        let mut referenced = HashSet::new();
+        for filepath in filepaths {
+            if encryption::is_encrypted(filepath)? {
+                continue;
+            }
+            let metadata = archive::Metadata::read_file(filepath, None)
+                .await
+                .map_err(|e| {
+                    Error::UnreadableBackup(filepath.to_string_lossy().to_string(), e.to_string())
+                })?;
+            referenced.extend(metadata.referenced_digests().map(str::to_string));
+        }
+
+        let older_than = SystemTime::now() - BLOB_GRACE_PERIOD;
+        Store::new(&self.dirpath)
+            .remove_unreferenced(&referenced, older_than)
+            .await
+    }
+
+    /// Print the backups as json or ndjson records.
+    async fn print_records(
+        &self,
+        details_flag: bool,
+        only_status: Option<BackupStatus>,
+        format: Format,
+    ) {
+        let plan = self.plan();
+
+        // Broken backups are only known after reading them.
+        let details_flag = details_flag || only_status == Some(BackupStatus::Broken);
+        let is_listed = |record: &BackupRecord| match &only_status {
+            None => true,
+            Some(BackupStatus::Broken) => record.error.is_some(),
+            Some(BackupStatus::Pinned) => record.pinned,
+            Some(only) => record.status.as_ref() == Some(only),
+        };
+
+        let mut backups = vec![];
+        for &(backup, status) in &plan.statuses {
+            // Skip the backups known not to be listed before reading them.
+            let is_skipped = match only_status {
+                Some(BackupStatus::Retainable | BackupStatus::Purgeable) => {
+                    only_status != Some(status)
+                }
+                Some(BackupStatus::Pinned) => !backup.pinned,
+                Some(BackupStatus::Broken) | None => false,
+            };
+            if is_skipped {
+                continue;
+            }
+            let mut record = self
+                .record(
+                    "backup",
+                    &backup.filepath,
+                    backup.creation_date,
+                    Some(status),
+                    plan.reason(backup),
+                    details_flag,
+                )
+                .await;
+            record.pinned = backup.pinned;
+            record.label = backup.label.clone();
+            if is_listed(&record) {
+                backups.push(record);
+            }
+        }
+
+        let autosave = match &self.autosave {
+            Some(autosave) => Some(
+                self.record(
+                    "autosave",
+                    &autosave.filepath,
+                    autosave.modified_at,
+                    None,
+                    None,
+                    details_flag,
+                )
+                .await,
+            )
+            .filter(is_listed),
+            None => None,
+        };
+
+        if format == Format::Ndjson {
+            for record in autosave.iter().chain(backups.iter()) {
+                if let Some(line) = report::to_string(record, format) {
+                    println!("{line}");
+                }
+            }
+        } else {
+            let listing = Listing {
+                strategy: self.strategy.to_string(),
+                limits: (!self.limits.is_empty()).then(|| self.limits.to_string()),
+                dirpath: &self.dirpath,
+                autosave,
+                backups,
+            };
+            if let Some(document) = report::to_string(&listing, format) {
+                println!("{document}");
+            }
+        }
+    }
+
+    /// Return the record of a backup file, reading its metadata if `details_flag` is `true`.
+    ///
+    /// A backup which cannot be read, or was deleted meanwhile, is recorded with the reason why.
+    async fn record(
+        &self,
+        kind: &'static str,
+        filepath: &Path,
+        creation_date: NaiveDateTime,
+        status: Option<BackupStatus>,
+        reason: Option<PurgeReason>,
+        details_flag: bool,
+    ) -> BackupRecord {
+        let filesize = fs::metadata(filepath)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let (encrypted, overview, error) = if details_flag {
+            match Self::read_details(filepath.to_path_buf(), self.identity_filepath.clone()).await {
+                (encrypted, Details::Readable(metadata)) => {
+                    (encrypted, Some(metadata.overview()), None)
+                }
+                (encrypted, Details::Locked) => (encrypted, None, None),
+                (encrypted, Details::Broken(reason)) => (encrypted, None, Some(reason)),
+            }
+        } else {
+            match encryption::is_encrypted(filepath) {
+                Ok(encrypted) => (encrypted, None, None),
+                Err(e) => (false, None, Some(broken_reason(&e))),
+            }
+        };
+
+        BackupRecord {
+            kind,
+            filepath: filepath.to_path_buf(),
+            creation_date: creation_date.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+            status,
+            reason,
+            pinned: false,
+            label: None,
+            filesize,
+            encrypted,
+            overview,
+            error,
+        }
+    }
+
+    /// Return whether the backup file at `filepath` is encrypted, and its details, decrypting it
+    /// with the identity file at `identity_filepath`.
+    async fn read_details(
+        filepath: PathBuf,
+        identity_filepath: Option<PathBuf>,
+    ) -> (bool, Details) {
+        let encrypted = match encryption::is_encrypted(&filepath) {
+            Ok(encrypted) => encrypted,
+            Err(e) => return (false, Details::Broken(broken_reason(&e))),
+        };
+        let version = match reader::read_version(&filepath, identity_filepath.as_deref()) {
+            Ok(version) => version,
+            Err(Error::Encryption(_)) if encrypted => return (encrypted, Details::Locked),
+            Err(e) => return (encrypted, Details::Broken(broken_reason(&e))),
+        };
+        if reader::decoder(&version).is_err() {
+            let reason = format!("unsupported version {version}");
+            return (encrypted, Details::Broken(reason));
+        }
+
+        match archive::Metadata::read_file(&filepath, identity_filepath.as_deref()).await {
+            Ok(metadata) => (encrypted, Details::Readable(metadata)),
+            Err(e) => (encrypted, Details::Broken(broken_reason(&e))),
+        }
+    }
+
+    /// Read the details of all `backups` concurrently.
+    async fn read_all_details<'a>(
+        &self,
+        backups: impl Iterator<Item = &'a Backup>,
+    ) -> Vec<(bool, Details)> {
+        let tasks: Vec<_> = backups
+            .map(|backup| {
+                let identity_filepath = self.identity_filepath.clone();
+                smol::spawn(Self::read_details(
+                    backup.filepath.clone(),
+                    identity_filepath,
+                ))
+            })
+            .collect();
+        join_all(tasks).await
+    }
+
     async fn print_table(&self, details_flag: bool) {
         println!("Strategy: {}", self.strategy);
+        if !self.limits.is_empty() {
+            println!("Limits: {}", self.limits);
+        }
 
         // Try to strip the HOME prefix from self.dirpath, otherwise return self.dirpath.
         let location: Cow<Path> = {
@@ -280,94 +680,196 @@ impl Catalog {
         }
         println!();
 
+        let plan = self.plan();
         let Plan {
             purgeable,
             retainable,
             statuses,
-        } = self.plan();
+            ..
+        } = &plan;
 
         let reset = "\u{001b}[0m";
+        let red = "\u{001b}[31m";
         let green = "\u{001b}[32m";
         let yellow = "\u{001b}[33m";
+        let blue = "\u{001b}[34m";
 
         // 45, 44, ..., 1
         let indices = RangeInclusive::new(1, statuses.len()).rev();
 
+        // Name and reason of the backups which cannot be read, only known with details.
+        let mut broken = vec![];
+
+        // Labelled backups have longer names.
+        let name_width = statuses
+            .iter()
+            .map(|(backup, _)| backup.filepath.file_name().unwrap().len())
+            .fold(41, usize::max);
+        let label_width = statuses
+            .iter()
+            .filter_map(|(backup, _)| backup.label.as_ref().map(String::len))
+            .fold(5, usize::max);
+
         if details_flag {
             // Table header
             println!(
-                "{:4} {:37} {:11} {:12} {:11} {:8} {:8}",
-                "", "NAME", "AGE", "STATUS", "FILESIZE", "VERSION", "CONTENT"
+                "{:4} {:name_width$} {:11} {:12} {:11} {:label_width$} {:11} {:10} {:8} {:8} {:8}",
+                "",
+                "NAME",
+                "AGE",
+                "STATUS",
+                "REASON",
+                "LABEL",
+                "FILESIZE",
+                "ENCRYPTED",
+                "VERSION",
+                "ESCAPES",
+                "CONTENT"
             );
 
-            // Read all metadata concurrently
-            let tasks: Vec<_> = statuses
-                .iter()
-                .map(|&(backup, _)| {
-                    let backup_filepath = backup.filepath.clone();
-                    smol::spawn(async move { v1::Metadata::read_file(backup_filepath).await })
-                })
-                .collect();
-            let metadatas: Result<Vec<_>> = join_all(tasks).await.into_iter().collect();
-            let metadatas = metadatas.expect("Cannot read metadata files");
+            let details = self
+                .read_all_details(statuses.iter().map(|&(backup, _)| backup))
+                .await;
 
             // Build & print table rows
-            for (index, ((backup, status), metadata)) in
-                iter::zip(indices, iter::zip(statuses, metadatas))
+            for (index, (&(backup, status), (encrypted, details))) in
+                iter::zip(indices, iter::zip(statuses, details))
             {
                 let filename = backup.filepath.file_name().unwrap().to_string_lossy();
-                let filesize = fs::metadata(backup.filepath.as_path()).await.unwrap().len();
-                let filesize = bytes2(filesize as f64);
+                // Pinned backups are retainable, but shown apart.
+                let status = if backup.pinned {
+                    BackupStatus::Pinned
+                } else {
+                    status
+                };
+                // The backup may have been deleted meanwhile.
+                let filesize = match fs::metadata(backup.filepath.as_path()).await {
+                    Ok(metadata) => bytes2(metadata.len() as f64),
+                    Err(_) => "-".to_string(),
+                };
 
                 let color = match status {
                     BackupStatus::Purgeable => yellow,
                     BackupStatus::Retainable => green,
+                    BackupStatus::Broken => red,
+                    BackupStatus::Pinned => blue,
                 };
                 let age = backup.age(now);
-
-                let overview = metadata.overview();
-                let version = &metadata.version;
+                let reason = plan
+                    .reason(backup)
+                    .map(|r| r.to_string())
+                    .unwrap_or_default();
+                let label = backup.label.as_deref().unwrap_or_default();
+
+                let encrypted = if encrypted { "yes" } else { "no" };
+                // Without its identity, the content of an encrypted backup is unknown.
+                let (version, escapes, overview) = match &details {
+                    Details::Readable(metadata) => (
+                        metadata.version.as_str(),
+                        if metadata.escapes { "yes" } else { "no" },
+                        metadata.overview().to_string(),
+                    ),
+                    Details::Locked => ("-", "-", "-".to_string()),
+                    Details::Broken(reason) => {
+                        broken.push((filename.to_string(), reason.clone()));
+                        ("-", "-", format!("{red}{reason}{reset}"))
+                    }
+                };
 
                 println!(
-                    "{index:3}. {color}{filename:32}{reset} {age:11} {color}{status:12}{reset} {filesize:11} {version:8} {overview:8}"
+                    "{index:3}. {color}{filename:name_width$}{reset} {age:11} {color}{status:12}{reset} {reason:11} {label:label_width$} {filesize:11} {encrypted:10} {version:8} {escapes:8} {overview:8}"
                 );
             }
         } else {
             // Table header
-            println!("{:4} {:37} {:11} {:11}", "", "NAME", "AGE", "STATUS");
+            println!(
+                "{:4} {:name_width$} {:11} {:12} {:11} {:label_width$}",
+                "", "NAME", "AGE", "STATUS", "REASON", "LABEL"
+            );
 
             // Build & print table rows
-            for (index, (backup, status)) in iter::zip(indices, statuses) {
+            for (index, &(backup, status)) in iter::zip(indices, statuses) {
                 let filename = backup.filepath.file_name().unwrap().to_string_lossy();
+                // Pinned backups are retainable, but shown apart.
+                let status = if backup.pinned {
+                    BackupStatus::Pinned
+                } else {
+                    status
+                };
                 let color = match status {
                     BackupStatus::Purgeable => yellow,
                     BackupStatus::Retainable => green,
+                    BackupStatus::Broken => red,
+                    BackupStatus::Pinned => blue,
                 };
                 let age = backup.age(now);
+                let reason = plan
+                    .reason(backup)
+                    .map(|r| r.to_string())
+                    .unwrap_or_default();
+                let label = backup.label.as_deref().unwrap_or_default();
 
                 println!(
-                    "{index:3}. {color}{filename:32}{reset} {age:11} {color}{status:6}{reset}"
+                    "{index:3}. {color}{filename:name_width$}{reset} {age:11} {color}{status:12}{reset} {reason:11} {label}"
                 );
             }
         }
 
+        let pinned = match self.backups.iter().filter(|b| b.pinned).count() {
+            0 => String::new(),
+            n => format!(" ({n} pinned)"),
+        };
         println!(
-            "\n{} backups: {} retainable, {} purgeable",
+            "\n{} backups: {} retainable{pinned}, {} purgeable",
             self.len(),
             retainable.len(),
             purgeable.len(),
         );
+        if !broken.is_empty() {
+            println!("{} broken backups:", broken.len());
+            for (filename, reason) in broken {
+                println!("  - {filename}: {reason}");
+            }
+        }
+    }
+}
+
+/// Return the filepath of the empty file pinning the backup at `backup_filepath`.
+fn pin_filepath(backup_filepath: &Path) -> PathBuf {
+    let mut filepath = backup_filepath.as_os_str().to_owned();
+    filepath.push(PIN_EXTENSION);
+    PathBuf::from(filepath)
+}
+
+/// Return why a backup cannot be read, from the `error` reading it.
+fn broken_reason(error: &Error) -> String {
+    match error {
+        Error::Io { source } if source.kind() == std::io::ErrorKind::NotFound => {
+            "deleted".to_string()
+        }
+        Error::Io { source } => format!("corrupt: {source}"),
+        Error::MissingMetadata(_) => "corrupt: missing metadata".to_string(),
+        Error::ArchiveVersion(_) => "corrupt: missing version".to_string(),
+        Error::Serde { .. } => "corrupt: invalid metadata".to_string(),
+        error => format!("corrupt: {error}"),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Timelike;
+    use chrono::{Datelike, Timelike};
     use tempfile::TempDir;
 
     fn catalog(dir: &TempDir) -> Catalog {
-        smol::block_on(Catalog::new(dir.path(), Strategy::most_recent(1))).unwrap()
+        smol::block_on(Catalog::new(
+            dir.path(),
+            Strategy::most_recent(1),
+            Limits::default(),
+            false,
+            None,
+        ))
+        .unwrap()
     }
 
     #[test]
@@ -378,7 +880,7 @@ mod tests {
             "backup",
         )
         .unwrap();
-        std::fs::write(dir.path().join(v1::AUTOSAVE_FILENAME), "autosave").unwrap();
+        std::fs::write(dir.path().join(archive::AUTOSAVE_FILENAME), "autosave").unwrap();
 
         let catalog = catalog(&dir);
 
@@ -397,7 +899,7 @@ mod tests {
             "backup",
         )
         .unwrap();
-        let autosave_filepath = v1::autosave_filepath(dir.path());
+        let autosave_filepath = archive::autosave_filepath(dir.path());
         std::fs::write(&autosave_filepath, "autosave").unwrap();
 
         let catalog = catalog(&dir);
@@ -413,7 +915,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         let backup_filepath = dir.path().join("backup-20990101T120000.123456.tar.zst");
         std::fs::write(&backup_filepath, "backup").unwrap();
-        std::fs::write(dir.path().join(v1::AUTOSAVE_FILENAME), "autosave").unwrap();
+        std::fs::write(dir.path().join(archive::AUTOSAVE_FILENAME), "autosave").unwrap();
 
         let catalog = catalog(&dir);
 
@@ -423,6 +925,208 @@ mod tests {
         );
     }
 
+    #[test]
+    fn total_size_is_counted_with_the_file_sizes() {
+        let dir = TempDir::new().unwrap();
+        for (name, content) in [
+            ("backup-20240101T120000.000000.tar.zst", "backup 1"),
+            ("backup-20240102T120000.000000.tar.zst", "backup 2"),
+            ("backup-20240103T120000.000000.tar.zst", "backup 3"),
+        ] {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        let limits = Limits {
+            max_total_size: Some("20B".parse().unwrap()),
+            ..Default::default()
+        };
+        let catalog = smol::block_on(Catalog::new(
+            dir.path(),
+            Strategy::most_recent(10),
+            limits,
+            false,
+            None,
+        ))
+        .unwrap();
+
+        let plan = catalog.plan();
+
+        assert_eq!(catalog.backups[0].filesize, 8);
+        assert_eq!(plan.purgeable, [&catalog.backups[0]]);
+        assert_eq!(
+            plan.reason(&catalog.backups[0]),
+            Some(PurgeReason::OverBudget)
+        );
+        assert_eq!(plan.retainable.len(), 2);
+    }
+
+    mod pins {
+        use super::*;
+
+        const NAME: &str = "backup-20240101T120000.000000.tar.zst";
+
+        fn catalog_with_backup(dir: &TempDir) -> Catalog {
+            std::fs::write(dir.path().join(NAME), "backup").unwrap();
+            catalog(dir)
+        }
+
+        #[test]
+        fn pin_marks_the_backup_as_pinned() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_backup(&dir);
+            assert!(!catalog.backups[0].pinned);
+
+            let filepath = smol::block_on(catalog.pin(Path::new(NAME))).unwrap();
+
+            assert_eq!(filepath, dir.path().join(NAME));
+            assert!(dir.path().join(format!("{NAME}.pin")).exists());
+            let catalog = smol::block_on(catalog.refresh()).unwrap();
+            assert_eq!(catalog.len(), 1);
+            assert!(catalog.backups[0].pinned);
+        }
+
+        #[test]
+        fn unpin_removes_the_pin() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_backup(&dir);
+            let filepath = dir.path().join(NAME);
+            smol::block_on(catalog.pin(&filepath)).unwrap();
+
+            smol::block_on(catalog.unpin(&filepath)).unwrap();
+            smol::block_on(catalog.unpin(&filepath)).unwrap();
+
+            assert!(!dir.path().join(format!("{NAME}.pin")).exists());
+            let catalog = smol::block_on(catalog.refresh()).unwrap();
+            assert!(!catalog.backups[0].pinned);
+        }
+
+        #[test]
+        fn unknown_backups_cannot_be_pinned() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_backup(&dir);
+
+            let result = smol::block_on(catalog.pin(Path::new("backup-missing.tar.zst")));
+
+            assert!(matches!(result, Err(Error::UnknownBackup(_))));
+        }
+
+        #[test]
+        fn compaction_keeps_pinned_backups() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_backup(&dir);
+            smol::block_on(catalog.pin(Path::new(NAME))).unwrap();
+            std::fs::write(
+                dir.path().join("backup-20240102T120000.000000.tar.zst"),
+                "backup",
+            )
+            .unwrap();
+            let catalog = smol::block_on(catalog.refresh()).unwrap();
+
+            let compaction = smol::block_on(catalog.compact()).unwrap();
+
+            assert_eq!(compaction.num_deleted, 0);
+            assert!(dir.path().join(NAME).exists());
+        }
+    }
+
+    mod labels {
+        use super::*;
+
+        fn catalog_with_labels(dir: &TempDir, keep_labelled: bool) -> Catalog {
+            for name in [
+                "backup-20240101T120000.000000-before-k8s.tar.zst",
+                "backup-20240102T120000.000000-before-k8s.tar.zst",
+                "backup-20240103T120000.000000.tar.zst",
+                "backup-20240104T120000.000000-other.tar.zst.age",
+                "backup-20240105T120000.000000.tar.zst",
+            ] {
+                std::fs::write(dir.path().join(name), "backup").unwrap();
+            }
+            smol::block_on(Catalog::new(
+                dir.path(),
+                Strategy::most_recent(1),
+                Limits::default(),
+                keep_labelled,
+                None,
+            ))
+            .unwrap()
+        }
+
+        #[test]
+        fn labels_are_read_from_the_file_names() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_labels(&dir, false);
+
+            let labels: Vec<_> = catalog
+                .backups
+                .iter()
+                .map(|backup| backup.label.as_deref())
+                .collect();
+            assert_eq!(
+                labels,
+                [
+                    Some("before-k8s"),
+                    Some("before-k8s"),
+                    None,
+                    Some("other"),
+                    None
+                ]
+            );
+            assert_eq!(catalog.backups[1].creation_date.day(), 2);
+        }
+
+        #[test]
+        fn latest_labelled_backup_is_found() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_labels(&dir, false);
+
+            let backup = catalog
+                .latest_labelled(&"before-k8s".parse().unwrap())
+                .unwrap();
+
+            assert_eq!(
+                backup.filepath,
+                dir.path()
+                    .join("backup-20240102T120000.000000-before-k8s.tar.zst")
+            );
+            assert!(catalog.latest_labelled(&"nope".parse().unwrap()).is_none());
+        }
+
+        #[test]
+        fn labelled_backups_are_compacted_by_default() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_labels(&dir, false);
+
+            assert_eq!(catalog.plan().purgeable.len(), 4);
+        }
+
+        #[test]
+        fn labelled_backups_are_pinned_if_kept() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_labels(&dir, true);
+
+            let pinned: Vec<_> = catalog.backups.iter().map(|b| b.pinned).collect();
+            assert_eq!(pinned, [true, true, false, true, false]);
+            let plan = catalog.plan();
+            assert_eq!(plan.purgeable, [&catalog.backups[2]]);
+
+            let catalog = smol::block_on(catalog.refresh()).unwrap();
+            assert!(catalog.backups[0].pinned);
+        }
+
+        #[test]
+        fn labelled_backups_cannot_be_unpinned_if_kept() {
+            let dir = TempDir::new().unwrap();
+            let catalog = catalog_with_labels(&dir, true);
+            let filepath = catalog.backups[0].filepath.clone();
+
+            let result = smol::block_on(catalog.unpin(&filepath));
+            assert!(matches!(result, Err(Error::LabelledBackup(_))));
+
+            let catalog = catalog_with_labels(&dir, false);
+            assert!(smol::block_on(catalog.unpin(&filepath)).is_ok());
+        }
+    }
+
     #[test]
     fn backup_timestamp_retains_microseconds() {
         let dir = TempDir::new().unwrap();
@@ -452,4 +1156,183 @@ mod tests {
 
         assert!(catalog(&dir).is_empty());
     }
+
+    mod garbage_collection {
+        use super::*;
+        use std::str::FromStr;
+
+        use crate::{management::archive::v2, tmux};
+
+        /// Write a v2 backup referencing `content`, stored long enough ago to be collected.
+        fn write_backup(dir: &TempDir, filename: &str, content: &[u8]) -> String {
+            let store = Store::new(dir.path());
+            let digest = smol::block_on(store.write(content)).unwrap();
+            let a_day_ago = SystemTime::now() - Duration::from_secs(86400);
+            std::fs::File::options()
+                .append(true)
+                .open(store.blob_filepath(&digest))
+                .unwrap()
+                .set_modified(a_day_ago)
+                .unwrap();
+
+            let metadata = archive::Metadata {
+                version: archive::FORMAT_VERSION.to_string(),
+                client: tmux::client::Client::from_str("'work':''").unwrap(),
+                sessions: vec![],
+                windows: vec![],
+                panes: vec![],
+                pane_metadata: vec![archive::PaneMetadata {
+                    pane_id: tmux::pane_id::PaneId::from_str("%1").unwrap(),
+                    digest: digest.clone(),
+                    size: content.len() as u64,
+                    argv: vec![],
+                    history_lines: archive::HistoryLines::All,
+                }],
+                escapes: true,
+                label: None,
+                note: None,
+            };
+            let archive = std::fs::File::create(dir.path().join(filename)).unwrap();
+            v2::create_from_file(archive, &metadata).unwrap();
+
+            digest
+        }
+
+        #[test]
+        fn compaction_deletes_unreferenced_blobs() {
+            let dir = TempDir::new().unwrap();
+            let old = write_backup(&dir, "backup-20240101T120000.000000.tar.zst", b"old");
+            let shared = write_backup(&dir, "backup-20240102T120000.000000.tar.zst", b"shared");
+            let new = write_backup(&dir, "backup-20240103T120000.000000.tar.zst", b"new");
+            write_backup(&dir, archive::AUTOSAVE_FILENAME, b"shared");
+
+            let compaction = smol::block_on(catalog(&dir).compact()).unwrap();
+
+            let store = Store::new(dir.path());
+            assert_eq!(compaction.num_deleted, 2);
+            assert_eq!(compaction.num_blobs_deleted, 1);
+            assert!(compaction.blobs_error.is_none());
+            assert!(!store.blob_filepath(&old).exists());
+            assert!(store.blob_filepath(&shared).exists());
+            assert!(store.blob_filepath(&new).exists());
+        }
+
+        #[test]
+        fn unreadable_archive_keeps_all_blobs() {
+            let dir = TempDir::new().unwrap();
+            let old = write_backup(&dir, "backup-20240101T120000.000000.tar.zst", b"old");
+            std::fs::write(
+                dir.path().join("backup-20240103T120000.000000.tar.zst"),
+                "not an archive",
+            )
+            .unwrap();
+
+            let compaction = smol::block_on(catalog(&dir).compact()).unwrap();
+
+            assert_eq!(compaction.num_deleted, 1);
+            assert_eq!(compaction.num_blobs_deleted, 0);
+            assert!(
+                compaction
+                    .blobs_error
+                    .unwrap()
+                    .starts_with("cannot read backup `")
+            );
+            assert!(Store::new(dir.path()).blob_filepath(&old).exists());
+        }
+    }
+
+    mod broken_backups {
+        use super::*;
+
+        /// Write a tar+zstd archive holding the `entries`, as named files with their content.
+        fn write_archive(filepath: &Path, entries: &[(&str, &[u8])]) {
+            let file = std::fs::File::create(filepath).unwrap();
+            let enc = zstd::stream::write::Encoder::new(file, 0)
+                .unwrap()
+                .auto_finish();
+            let mut tar = tar::Builder::new(enc);
+            for (name, content) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                tar.append_data(&mut header, name, *content).unwrap();
+            }
+            tar.finish().unwrap();
+        }
+
+        fn broken_reason_of(filepath: &Path) -> Option<String> {
+            match smol::block_on(Catalog::read_details(filepath.to_path_buf(), None)) {
+                (_, Details::Broken(reason)) => Some(reason),
+                _ => None,
+            }
+        }
+
+        #[test]
+        fn archives_without_metadata_are_corrupt() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup-20240101T120000.000000.tar.zst");
+            write_archive(&filepath, &[(archive::VERSION_FILENAME, b"2.0")]);
+
+            assert_eq!(
+                broken_reason_of(&filepath).as_deref(),
+                Some("corrupt: missing metadata")
+            );
+        }
+
+        #[test]
+        fn unknown_versions_are_unsupported() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup-20240101T120000.000000.tar.zst");
+            write_archive(
+                &filepath,
+                &[
+                    (archive::VERSION_FILENAME, b"9.0"),
+                    (archive::METADATA_FILENAME, b"{}"),
+                ],
+            );
+
+            assert_eq!(
+                broken_reason_of(&filepath).as_deref(),
+                Some("unsupported version 9.0")
+            );
+        }
+
+        #[test]
+        fn invalid_and_deleted_files_are_broken() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup-20240101T120000.000000.tar.zst");
+            std::fs::write(&filepath, "not an archive").unwrap();
+
+            assert!(
+                broken_reason_of(&filepath)
+                    .unwrap()
+                    .starts_with("corrupt: ")
+            );
+
+            std::fs::remove_file(&filepath).unwrap();
+            assert_eq!(broken_reason_of(&filepath).as_deref(), Some("deleted"));
+        }
+
+        #[test]
+        fn records_of_deleted_backups_have_the_reason() {
+            let dir = TempDir::new().unwrap();
+            let filepath = dir.path().join("backup-20240101T120000.000000.tar.zst");
+            let creation_date = NaiveDateTime::default();
+
+            let catalog = catalog(&dir);
+            for details_flag in [false, true] {
+                let record = smol::block_on(catalog.record(
+                    "backup",
+                    &filepath,
+                    creation_date,
+                    None,
+                    None,
+                    details_flag,
+                ));
+
+                assert_eq!(record.filesize, 0);
+                assert_eq!(record.error.as_deref(), Some("deleted"));
+            }
+        }
+    }
 }
@@ -1,10 +1,13 @@
 //! Allows to keep the number of backup files under control.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::str::FromStr;
 
-use chrono::{Datelike, Timelike};
+use chrono::{Datelike, NaiveDateTime, Timelike};
 use chrono::{Duration, Local};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
 use super::backup::{Backup, BackupStatus};
 
@@ -22,8 +25,16 @@ pub enum Strategy {
     /// Classic backup strategy.
     ///
     /// This is only useful if you save _very_ often, probably in an automated manner. See
-    /// the method [`Strategy::plan`] for details.
+    /// the method [`Strategy::plan_at`] for details.
     Classic,
+
+    /// Keep the latest backup of the most recent periods of each tier.
+    ///
+    /// See the method [`Strategy::plan_at`] for details.
+    Tiered {
+        /// Periods and number of periods to keep.
+        tiers: Tiers,
+    },
 }
 
 impl Strategy {
@@ -32,9 +43,18 @@ impl Strategy {
         Self::KeepMostRecent { k }
     }
 
-    /// Determine which backup files should be kept.
+    /// Determine which backup files should be kept, as of now.
+    ///
+    /// The `backup_files` are assumed to be sorted from oldest to newest. See
+    /// [`Strategy::plan_at`] for details.
+    pub fn plan<'a>(&self, backups: &'a [Backup]) -> Plan<'a> {
+        self.plan_at(backups, Local::now().naive_local())
+    }
+
+    /// Determine which backup files should be kept at the local time `now`.
     ///
-    /// The `backup_files` are assumed to be sorted from oldest to newest.
+    /// The `backup_files` are assumed to be sorted from oldest to newest. Pinned backups are
+    /// always retained, in addition to the backups kept by the strategy.
     ///
     /// # KeepMostRecent strategy
     ///
@@ -52,115 +72,79 @@ impl Strategy {
     /// - the lastest backup per month of this year (max 11 backups - exclude the past month).
     ///
     /// The time windows above are a partition; they do not overlap. Within each partition,
-    /// only the most recent backup is kept.
+    /// only the most recent backup of each hour, day, ISO week or month is kept. These periods
+    /// are calendar periods of the local time, such as the 10 o'clock hour of a given day, so
+    /// that backups of the same hour on two different days are kept in separate periods.
     ///
-    pub fn plan<'a>(&self, backups: &'a [Backup]) -> Plan<'a> {
+    /// # Tiered strategy
+    ///
+    /// Each tier, such as `daily=14`, keeps the latest backup of each of the 14 most recent days
+    /// which have a backup, in the manner of `restic forget`. Days without backups are skipped
+    /// rather than counted, so that a gap in the backups does not purge older ones.
+    ///
+    /// The tiers overlap: a backup is kept if any tier keeps it. As each tier keeps the latest
+    /// backup of the most recent period, the most recent backup is always kept.
+    ///
+    pub fn plan_at<'a>(&self, backups: &'a [Backup], now: NaiveDateTime) -> Plan<'a> {
         match self {
             Strategy::KeepMostRecent { k } => {
                 let k = std::cmp::min(backups.len(), *k);
                 let index = std::cmp::max(0, backups.len() - k);
-                let (outdated_backups, recent_backups) = backups.split_at(index);
-
-                let mut statuses = vec![];
-                statuses.extend(
-                    outdated_backups
-                        .iter()
-                        .map(|backup| (backup, BackupStatus::Purgeable)),
-                );
-                statuses.extend(
-                    recent_backups
-                        .iter()
-                        .map(|backup| (backup, BackupStatus::Retainable)),
-                );
+                let retain_set: HashSet<&Backup> = backups[index..].iter().collect();
 
-                Plan {
-                    purgeable: outdated_backups.iter().collect(),
-                    retainable: recent_backups.iter().collect(),
-                    statuses,
-                }
+                Plan::new(backups, &retain_set)
             }
 
             Strategy::Classic => {
-                let now = Local::now().naive_local();
                 let _24h_ago = now - Duration::days(1);
                 let _7d_ago = now - Duration::days(7);
                 let _4w_ago = now - Duration::weeks(4);
                 let _year_ago = now - Duration::days(365);
 
-                // Last 24 h, grouped by hour
-                let last_24h_per_hour: Vec<_> = backups
-                    .iter()
-                    .filter(|&b| b.creation_date > _24h_ago)
-                    .chunk_by(|&b| b.creation_date.hour())
-                    .into_iter()
-                    .map(|(_key, group)| group.collect::<Vec<_>>())
-                    .filter_map(|group| group.last().cloned())
-                    .collect();
-
-                // Last 7 days excluding the last 24 h, grouped by day
-                let last_7d_per_day: Vec<_> = backups
-                    .iter()
-                    .filter(|&b| _24h_ago > b.creation_date && b.creation_date >= _7d_ago)
-                    .chunk_by(|&b| b.creation_date.day())
-                    .into_iter()
-                    .map(|(_key, group)| group.collect::<Vec<_>>())
-                    .filter_map(|group| group.last().cloned())
-                    .collect();
-
-                // Last 4 weeks excluding the last 7 days, grouped by week number
-                let last_4w_per_isoweek: Vec<_> = backups
-                    .iter()
-                    .filter(|&b| _7d_ago > b.creation_date && b.creation_date >= _4w_ago)
-                    .chunk_by(|&b| b.creation_date.iso_week())
-                    .into_iter()
-                    .map(|(_key, group)| group.collect::<Vec<_>>())
-                    .filter_map(|group| group.last().cloned())
-                    .collect();
-
-                // Last year (365 days) excluding the last 4 weeks, grouped by month
-                let last_year_per_month: Vec<_> = backups
-                    .iter()
-                    .filter(|&b| _4w_ago > b.creation_date && b.creation_date >= _year_ago)
-                    .chunk_by(|&b| b.creation_date.month())
-                    .into_iter()
-                    .map(|(_key, group)| group.collect::<Vec<_>>())
-                    .filter_map(|group| group.last().cloned())
-                    .collect();
+                // Latest backup of each period between `from` (included) and `to` (excluded).
+                let latest_per = |period: Period, from: NaiveDateTime, to: NaiveDateTime| {
+                    backups
+                        .iter()
+                        .filter(|&b| from <= b.creation_date && b.creation_date < to)
+                        .chunk_by(|&b| period.start(&b.creation_date))
+                        .into_iter()
+                        .filter_map(|(_key, group)| group.last())
+                        .collect::<Vec<_>>()
+                };
 
                 let retainable: Vec<_> = vec![
-                    last_year_per_month,
-                    last_4w_per_isoweek,
-                    last_7d_per_day,
-                    last_24h_per_hour,
+                    latest_per(Period::Monthly, _year_ago, _4w_ago),
+                    latest_per(Period::Weekly, _4w_ago, _7d_ago),
+                    latest_per(Period::Daily, _7d_ago, _24h_ago),
+                    latest_per(Period::Hourly, _24h_ago, NaiveDateTime::MAX),
                 ]
                 .into_iter()
                 .flatten()
                 .collect();
 
-                let retain_set: std::collections::HashSet<&Backup> =
-                    retainable.iter().copied().collect();
+                let retain_set: HashSet<&Backup> = retainable.into_iter().collect();
 
-                let purgeable: Vec<_> = backups
-                    .iter()
-                    .filter(|&b| !retain_set.contains(b))
-                    .collect();
+                Plan::new(backups, &retain_set)
+            }
 
-                let statuses: Vec<_> = backups
+            Strategy::Tiered { tiers } => {
+                let retain_set: HashSet<&Backup> = tiers
+                    .0
                     .iter()
-                    .map(|b| {
-                        if retain_set.contains(b) {
-                            (b, BackupStatus::Retainable)
-                        } else {
-                            (b, BackupStatus::Purgeable)
-                        }
+                    .flat_map(|tier| {
+                        // Newest first, so the first backup of each period is its latest.
+                        backups
+                            .iter()
+                            .rev()
+                            .chunk_by(|&b| tier.period.start(&b.creation_date))
+                            .into_iter()
+                            .take(tier.count)
+                            .filter_map(|(_key, mut group)| group.next())
+                            .collect::<Vec<_>>()
                     })
                     .collect();
 
-                Plan {
-                    purgeable,
-                    retainable,
-                    statuses,
-                }
+                Plan::new(backups, &retain_set)
             }
         }
     }
@@ -173,6 +157,384 @@ impl fmt::Display for Strategy {
                 write!(f, "KeepMostRecent: {k}")
             }
             Strategy::Classic => write!(f, "Classic"),
+            Strategy::Tiered { tiers } => write!(f, "Tiered: {tiers}"),
+        }
+    }
+}
+
+/// Length of the periods of a tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Hours.
+    Hourly,
+    /// Days, starting at midnight.
+    Daily,
+    /// ISO weeks, starting on Monday.
+    Weekly,
+    /// Calendar months.
+    Monthly,
+    /// Calendar years.
+    Yearly,
+}
+
+impl Period {
+    const ALL: [Period; 5] = [
+        Period::Hourly,
+        Period::Daily,
+        Period::Weekly,
+        Period::Monthly,
+        Period::Yearly,
+    ];
+
+    /// Return the start of the period containing `datetime`.
+    fn start(&self, datetime: &NaiveDateTime) -> NaiveDateTime {
+        let date = datetime.date();
+        let midnight = |date: chrono::NaiveDate| date.and_time(chrono::NaiveTime::MIN);
+        match self {
+            Period::Hourly => midnight(date) + Duration::hours(datetime.hour().into()),
+            Period::Daily => midnight(date),
+            Period::Weekly => {
+                midnight(date) - Duration::days(date.weekday().num_days_from_monday().into())
+            }
+            Period::Monthly => midnight(date.with_day(1).unwrap()),
+            Period::Yearly => midnight(date.with_ordinal(1).unwrap()),
+        }
+    }
+
+    /// Return the name of the period in the tiers, such as `daily`.
+    fn name(&self) -> &'static str {
+        match self {
+            Period::Hourly => "hourly",
+            Period::Daily => "daily",
+            Period::Weekly => "weekly",
+            Period::Monthly => "monthly",
+            Period::Yearly => "yearly",
+        }
+    }
+}
+
+/// A tier of the tiered strategy, keeping the latest backup of `count` periods.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tier {
+    /// Length of the periods.
+    pub period: Period,
+
+    /// Number of the most recent periods with a backup to keep.
+    pub count: usize,
+}
+
+/// The tiers of the tiered strategy.
+///
+/// They are written `PERIOD=COUNT,...`, such as `hourly=48,daily=14,weekly=8`, where the period is
+/// `hourly`, `daily`, `weekly`, `monthly` or `yearly`. Each period appears at most once.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tiers(pub Vec<Tier>);
+
+impl Default for Tiers {
+    /// Tiers close to the classic strategy.
+    fn default() -> Self {
+        Self(vec![
+            Tier {
+                period: Period::Hourly,
+                count: 24,
+            },
+            Tier {
+                period: Period::Daily,
+                count: 7,
+            },
+            Tier {
+                period: Period::Weekly,
+                count: 4,
+            },
+            Tier {
+                period: Period::Monthly,
+                count: 12,
+            },
+        ])
+    }
+}
+
+impl FromStr for Tiers {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let mut tiers: Vec<Tier> = vec![];
+        for tier in source.split(',') {
+            let Some((period, count)) = tier.trim().split_once('=') else {
+                return Err(format!("expected `PERIOD=COUNT`, not `{tier}`"));
+            };
+            let Some(period) = Period::ALL.into_iter().find(|p| p.name() == period) else {
+                let expected: Vec<String> = Period::ALL
+                    .iter()
+                    .map(|p| format!("`{}`", p.name()))
+                    .collect();
+                return Err(format!(
+                    "invalid period `{period}`, expected one of {}",
+                    expected.join(", ")
+                ));
+            };
+            if tiers.iter().any(|t| t.period == period) {
+                return Err(format!("period `{}` given twice", period.name()));
+            }
+            let count = match count.parse() {
+                Ok(count) if count > 0 => count,
+                _ => return Err(format!("invalid count `{count}` for `{}`", period.name())),
+            };
+            tiers.push(Tier { period, count });
+        }
+        Ok(Self(tiers))
+    }
+}
+
+impl TryFrom<String> for Tiers {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Tiers> for String {
+    fn from(tiers: Tiers) -> Self {
+        tiers.to_string()
+    }
+}
+
+impl fmt::Display for Tiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tiers: Vec<String> = self
+            .0
+            .iter()
+            .map(|tier| format!("{}={}", tier.period.name(), tier.count))
+            .collect();
+        write!(f, "{}", tiers.join(","))
+    }
+}
+
+/// Limits on the backups kept by a strategy.
+///
+/// Backups beyond these limits are purged, even if the strategy would keep them. The most recent
+/// backup is always kept, so that a catalog is never emptied by its limits, and so are the pinned
+/// backups.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Age beyond which backups are purged.
+    pub max_age: Option<Age>,
+
+    /// Total size of the backup files beyond which the oldest backups are purged.
+    pub max_total_size: Option<Size>,
+}
+
+impl Limits {
+    /// Return `true` if there is no limit.
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.max_total_size.is_none()
+    }
+
+    /// Purge the backups of the `plan` beyond these limits, at the local time `now`.
+    ///
+    /// The retained backups are scanned from the most recent: those older than the maximum age
+    /// are purged, then the others are purged once the sum of their file sizes exceeds the
+    /// maximum total size. Backups already purged by the strategy are not counted, but the pinned
+    /// ones are.
+    pub fn restrict<'a>(&self, plan: Plan<'a>, now: NaiveDateTime) -> Plan<'a> {
+        let Plan {
+            retainable,
+            statuses,
+            mut reasons,
+            ..
+        } = plan;
+
+        let mut total_size = 0;
+        for (index, &backup) in retainable.iter().rev().enumerate() {
+            let is_kept = index == 0 || backup.pinned;
+            if !is_kept
+                && let Some(Age(max_age)) = self.max_age
+                && now - backup.creation_date > max_age
+            {
+                reasons.insert(backup, PurgeReason::TooOld);
+                continue;
+            }
+            total_size += backup.filesize;
+            if !is_kept
+                && let Some(Size(max_total_size)) = self.max_total_size
+                && total_size > max_total_size
+            {
+                reasons.insert(backup, PurgeReason::OverBudget);
+            }
+        }
+
+        Plan::with_reasons(statuses.into_iter().map(|(backup, _)| backup), reasons)
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut limits = vec![];
+        if let Some(max_age) = &self.max_age {
+            limits.push(format!("max age {max_age}"));
+        }
+        if let Some(max_total_size) = &self.max_total_size {
+            limits.push(format!("max total size {max_total_size}"));
+        }
+        write!(f, "{}", limits.join(", "))
+    }
+}
+
+/// An age, written with a unit, such as `36h`, `30d`, `8w` or `1y` (365 days).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Age(pub Duration);
+
+const AGE_UNITS: [(&str, i64); 4] = [("y", 365 * 24), ("w", 7 * 24), ("d", 24), ("h", 1)];
+
+impl FromStr for Age {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "expected a number of hours, days, weeks or years such as `30d`, not `{source}`"
+            )
+        };
+        let index = source
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(error)?;
+        let (count, unit) = source.split_at(index);
+        let count: i64 = match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(error()),
+        };
+        let (_, hours) = AGE_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(error)?;
+        count
+            .checked_mul(*hours)
+            .and_then(Duration::try_hours)
+            .map(Self)
+            .ok_or_else(|| format!("age `{source}` is too large"))
+    }
+}
+
+impl TryFrom<String> for Age {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Age> for String {
+    fn from(age: Age) -> Self {
+        age.to_string()
+    }
+}
+
+impl fmt::Display for Age {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hours = self.0.num_hours();
+        let (name, hours_per_unit) = AGE_UNITS
+            .iter()
+            .find(|(_, n)| hours % n == 0)
+            .unwrap_or(&("h", 1));
+        write!(f, "{}{name}", hours / hours_per_unit)
+    }
+}
+
+/// A size in bytes, written with an optional unit, such as `500MB`, `1.5GB` or `2GiB`.
+///
+/// The units are `B`, `kB`, `MB`, `GB` and `TB` in powers of 1000, and `KiB`, `MiB`, `GiB` and
+/// `TiB` in powers of 1024, regardless of case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Size(pub u64);
+
+const SIZE_UNITS: [(&str, u64); 9] = [
+    ("TiB", 1 << 40),
+    ("TB", 1_000_000_000_000),
+    ("GiB", 1 << 30),
+    ("GB", 1_000_000_000),
+    ("MiB", 1 << 20),
+    ("MB", 1_000_000),
+    ("KiB", 1 << 10),
+    ("kB", 1_000),
+    ("B", 1),
+];
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(source: &str) -> std::result::Result<Self, Self::Err> {
+        let error = || format!("expected a size such as `500MB`, not `{source}`");
+        let index = source
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(source.len());
+        let (number, unit) = source.split_at(index);
+        let number: f64 = number.parse().map_err(|_| error())?;
+        let bytes_per_unit = match unit {
+            "" => 1,
+            unit => {
+                let (_, n) = SIZE_UNITS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+                    .ok_or_else(error)?;
+                *n
+            }
+        };
+        let bytes = number * bytes_per_unit as f64;
+        if bytes >= u64::MAX as f64 {
+            return Err(format!("size `{source}` is too large"));
+        }
+        Ok(Self(bytes.round() as u64))
+    }
+}
+
+impl TryFrom<String> for Size {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Size> for String {
+    fn from(size: Size) -> Self {
+        size.to_string()
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, bytes_per_unit) = SIZE_UNITS
+            .iter()
+            .find(|(_, n)| self.0.is_multiple_of(*n) && self.0 >= *n)
+            .unwrap_or(&("B", 1));
+        write!(f, "{}{name}", self.0 / bytes_per_unit)
+    }
+}
+
+/// Why a backup is purgeable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PurgeReason {
+    /// The strategy keeps a more recent backup instead, in its place or its period.
+    Superseded,
+
+    /// The backup is older than the maximum age.
+    TooOld,
+
+    /// The backup does not fit in the maximum total size.
+    OverBudget,
+}
+
+impl fmt::Display for PurgeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PurgeReason::Superseded => write!(f, "superseded"),
+            PurgeReason::TooOld => write!(f, "too old"),
+            PurgeReason::OverBudget => write!(f, "over budget"),
         }
     }
 }
@@ -187,6 +549,54 @@ pub struct Plan<'a> {
 
     /// Sorted list of backup files along with their status (purgeable/retainable).
     pub statuses: Vec<(&'a Backup, BackupStatus)>,
+
+    /// Reason why each purgeable backup file should be purged.
+    pub reasons: HashMap<&'a Backup, PurgeReason>,
+}
+
+impl<'a> Plan<'a> {
+    /// Return the plan keeping the `backups` of the `retain_set` and the pinned ones, the others
+    /// being superseded.
+    fn new(backups: &'a [Backup], retain_set: &HashSet<&Backup>) -> Self {
+        let reasons = backups
+            .iter()
+            .filter(|&b| !b.pinned && !retain_set.contains(b))
+            .map(|b| (b, PurgeReason::Superseded))
+            .collect();
+        Self::with_reasons(backups.iter(), reasons)
+    }
+
+    /// Return the plan purging the `backups` which have a reason to be.
+    fn with_reasons(
+        backups: impl Iterator<Item = &'a Backup>,
+        reasons: HashMap<&'a Backup, PurgeReason>,
+    ) -> Self {
+        let statuses: Vec<_> = backups
+            .map(|b| {
+                if reasons.contains_key(b) {
+                    (b, BackupStatus::Purgeable)
+                } else {
+                    (b, BackupStatus::Retainable)
+                }
+            })
+            .collect();
+        let (purgeable, retainable) = statuses
+            .iter()
+            .map(|&(b, _)| b)
+            .partition(|b| reasons.contains_key(b));
+
+        Plan {
+            purgeable,
+            retainable,
+            statuses,
+            reasons,
+        }
+    }
+
+    /// Return why the `backup` should be purged, if it should.
+    pub fn reason(&self, backup: &Backup) -> Option<PurgeReason> {
+        self.reasons.get(backup).copied()
+    }
 }
 
 #[cfg(test)]
@@ -207,6 +617,9 @@ mod tests {
                 dt.format("%Y%m%dT%H%M%S")
             )),
             creation_date: dt,
+            filesize: 0,
+            pinned: false,
+            label: None,
         }
     }
 
@@ -352,6 +765,457 @@ mod tests {
         }
     }
 
+    mod tiered_strategy {
+        use super::*;
+
+        fn tiered(tiers: &str) -> Strategy {
+            Strategy::Tiered {
+                tiers: tiers.parse().unwrap(),
+            }
+        }
+
+        fn retained_dates(plan: &Plan) -> Vec<String> {
+            plan.retainable
+                .iter()
+                .map(|b| b.creation_date.format("%Y-%m-%d %H:%M").to_string())
+                .collect()
+        }
+
+        #[test]
+        fn empty_catalog_produces_empty_plan() {
+            let backups: Vec<Backup> = vec![];
+
+            let plan = tiered("daily=7").plan(&backups);
+
+            assert!(plan.purgeable.is_empty());
+            assert!(plan.retainable.is_empty());
+            assert!(plan.statuses.is_empty());
+        }
+
+        #[test]
+        fn keeps_the_latest_backup_per_hour() {
+            let backups = vec![
+                backup_at(2024, 6, 15, 8, 10, 0),
+                backup_at(2024, 6, 15, 8, 50, 0),
+                backup_at(2024, 6, 15, 9, 5, 0),
+                backup_at(2024, 6, 15, 9, 30, 0),
+                backup_at(2024, 6, 15, 10, 0, 0),
+            ];
+
+            let plan = tiered("hourly=48").plan(&backups);
+
+            assert_eq!(
+                retained_dates(&plan),
+                ["2024-06-15 08:50", "2024-06-15 09:30", "2024-06-15 10:00"]
+            );
+            assert_eq!(plan.purgeable.len(), 2);
+        }
+
+        #[test]
+        fn keeps_only_the_most_recent_periods() {
+            let backups = generate_hourly_backups(24 * 5);
+
+            let plan = tiered("daily=3").plan(&backups);
+
+            assert_eq!(
+                retained_dates(&plan),
+                ["2024-06-03 23:00", "2024-06-04 23:00", "2024-06-05 23:00"]
+            );
+            assert_eq!(plan.purgeable.len(), 24 * 5 - 3);
+        }
+
+        #[test]
+        fn periods_without_backups_are_not_counted() {
+            let backups = vec![
+                backup_at(2024, 1, 10, 12, 0, 0),
+                backup_at(2024, 3, 10, 12, 0, 0),
+                backup_at(2024, 6, 10, 12, 0, 0),
+            ];
+
+            let plan = tiered("daily=3").plan(&backups);
+
+            assert_eq!(plan.retainable.len(), 3);
+            assert!(plan.purgeable.is_empty());
+        }
+
+        #[test]
+        fn weeks_start_on_monday() {
+            // 2024-06-09 is a Sunday, 2024-06-10 a Monday.
+            let backups = vec![
+                backup_at(2024, 6, 3, 12, 0, 0),
+                backup_at(2024, 6, 9, 23, 0, 0),
+                backup_at(2024, 6, 10, 1, 0, 0),
+                backup_at(2024, 6, 12, 12, 0, 0),
+            ];
+
+            let plan = tiered("weekly=2").plan(&backups);
+
+            assert_eq!(
+                retained_dates(&plan),
+                ["2024-06-09 23:00", "2024-06-12 12:00"]
+            );
+        }
+
+        #[test]
+        fn weeks_span_the_turn_of_the_year() {
+            // 2024-12-30 is a Monday, in the same ISO week as 2025-01-02.
+            let backups = vec![
+                backup_at(2024, 12, 30, 12, 0, 0),
+                backup_at(2025, 1, 2, 12, 0, 0),
+            ];
+
+            let plan = tiered("weekly=1").plan(&backups);
+
+            assert_eq!(retained_dates(&plan), ["2025-01-02 12:00"]);
+        }
+
+        #[test]
+        fn keeps_the_latest_backup_per_month_and_year() {
+            let backups = vec![
+                backup_at(2022, 5, 1, 12, 0, 0),
+                backup_at(2022, 11, 1, 12, 0, 0),
+                backup_at(2023, 2, 1, 12, 0, 0),
+                backup_at(2023, 12, 31, 23, 0, 0),
+                backup_at(2024, 1, 1, 0, 0, 0),
+                backup_at(2024, 1, 20, 12, 0, 0),
+            ];
+
+            let monthly = tiered("monthly=2").plan(&backups);
+            let yearly = tiered("yearly=2").plan(&backups);
+
+            assert_eq!(
+                retained_dates(&monthly),
+                ["2023-12-31 23:00", "2024-01-20 12:00"]
+            );
+            assert_eq!(
+                retained_dates(&yearly),
+                ["2023-12-31 23:00", "2024-01-20 12:00"]
+            );
+        }
+
+        #[test]
+        fn a_backup_is_kept_if_any_tier_keeps_it() {
+            let backups = generate_hourly_backups(24 * 3);
+
+            let plan = tiered("hourly=2,daily=3").plan(&backups);
+
+            assert_eq!(
+                retained_dates(&plan),
+                [
+                    "2024-06-01 23:00",
+                    "2024-06-02 23:00",
+                    "2024-06-03 22:00",
+                    "2024-06-03 23:00"
+                ]
+            );
+        }
+
+        #[test]
+        fn most_recent_backup_is_always_kept() {
+            let backups = generate_hourly_backups(100);
+
+            for tiers in ["hourly=1", "daily=1", "weekly=1", "monthly=1", "yearly=1"] {
+                let plan = tiered(tiers).plan(&backups);
+
+                assert_eq!(plan.retainable, [backups.last().unwrap()], "{tiers}");
+            }
+        }
+
+        #[test]
+        fn statuses_preserve_original_order() {
+            let backups = vec![
+                backup_at(2024, 6, 15, 8, 0, 0),
+                backup_at(2024, 6, 15, 8, 30, 0),
+                backup_at(2024, 6, 15, 9, 0, 0),
+            ];
+
+            let plan = tiered("hourly=5").plan(&backups);
+
+            assert!(matches!(plan.statuses[0].1, BackupStatus::Purgeable));
+            assert!(matches!(plan.statuses[1].1, BackupStatus::Retainable));
+            assert!(matches!(plan.statuses[2].1, BackupStatus::Retainable));
+        }
+    }
+
+    mod tiers_parsing {
+        use super::*;
+
+        #[test]
+        fn round_trips() {
+            let tiers: Tiers = "hourly=48,daily=14,weekly=8,monthly=24,yearly=5"
+                .parse()
+                .unwrap();
+
+            assert_eq!(tiers.0.len(), 5);
+            assert_eq!(
+                tiers.0[1],
+                Tier {
+                    period: Period::Daily,
+                    count: 14
+                }
+            );
+            assert_eq!(
+                tiers.to_string(),
+                "hourly=48,daily=14,weekly=8,monthly=24,yearly=5"
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_tiers() {
+            let error = |s: &str| s.parse::<Tiers>().unwrap_err();
+
+            assert_eq!(error("daily"), "expected `PERIOD=COUNT`, not `daily`");
+            assert!(error("minutely=5").starts_with("invalid period `minutely`"));
+            assert_eq!(error("daily=0"), "invalid count `0` for `daily`");
+            assert_eq!(error("daily=x"), "invalid count `x` for `daily`");
+            assert_eq!(error("daily=2,daily=3"), "period `daily` given twice");
+        }
+    }
+
+    mod limits {
+        use super::*;
+
+        fn sized(mut backup: Backup, filesize: u64) -> Backup {
+            backup.filesize = filesize;
+            backup
+        }
+
+        fn now() -> NaiveDateTime {
+            backup_at(2024, 6, 30, 12, 0, 0).creation_date
+        }
+
+        fn limits(max_age: Option<&str>, max_total_size: Option<&str>) -> Limits {
+            Limits {
+                max_age: max_age.map(|age| age.parse().unwrap()),
+                max_total_size: max_total_size.map(|size| size.parse().unwrap()),
+            }
+        }
+
+        fn reasons(plan: &Plan) -> Vec<Option<PurgeReason>> {
+            plan.statuses.iter().map(|(b, _)| plan.reason(b)).collect()
+        }
+
+        #[test]
+        fn no_limits_keep_the_plan() {
+            let backups = generate_hourly_backups(5);
+            let plan = Strategy::most_recent(3).plan_at(&backups, now());
+
+            let plan = Limits::default().restrict(plan, now());
+
+            assert_eq!(plan.retainable.len(), 3);
+            assert_eq!(
+                reasons(&plan),
+                [
+                    Some(PurgeReason::Superseded),
+                    Some(PurgeReason::Superseded),
+                    None,
+                    None,
+                    None
+                ]
+            );
+        }
+
+        #[test]
+        fn purges_the_backups_older_than_the_max_age() {
+            let backups = vec![
+                backup_at(2024, 5, 1, 12, 0, 0),
+                backup_at(2024, 5, 31, 11, 0, 0),
+                backup_at(2024, 5, 31, 13, 0, 0),
+                backup_at(2024, 6, 29, 12, 0, 0),
+            ];
+            let plan = Strategy::most_recent(3).plan_at(&backups, now());
+
+            let plan = limits(Some("30d"), None).restrict(plan, now());
+
+            assert_eq!(
+                reasons(&plan),
+                [
+                    Some(PurgeReason::Superseded),
+                    Some(PurgeReason::TooOld),
+                    None,
+                    None
+                ]
+            );
+            assert_eq!(plan.purgeable.len(), 2);
+            assert!(matches!(plan.statuses[1].1, BackupStatus::Purgeable));
+        }
+
+        #[test]
+        fn purges_the_oldest_backups_over_the_max_total_size() {
+            let backups: Vec<_> = generate_hourly_backups(5)
+                .into_iter()
+                .map(|b| sized(b, 400))
+                .collect();
+            let plan = Strategy::most_recent(10).plan_at(&backups, now());
+
+            let plan = limits(None, Some("1kB")).restrict(plan, now());
+
+            assert_eq!(
+                reasons(&plan),
+                [
+                    Some(PurgeReason::OverBudget),
+                    Some(PurgeReason::OverBudget),
+                    Some(PurgeReason::OverBudget),
+                    None,
+                    None
+                ]
+            );
+        }
+
+        #[test]
+        fn purged_backups_do_not_count_in_the_total_size() {
+            let backups = vec![
+                sized(backup_at(2024, 4, 1, 12, 0, 0), 100),
+                sized(backup_at(2024, 6, 1, 12, 0, 0), 100),
+                sized(backup_at(2024, 6, 29, 11, 0, 0), 900),
+                sized(backup_at(2024, 6, 29, 12, 0, 0), 100),
+            ];
+            let plan = Strategy::most_recent(3).plan_at(&backups, now());
+
+            let plan = limits(Some("4w"), Some("1kB")).restrict(plan, now());
+
+            assert_eq!(
+                reasons(&plan),
+                [
+                    Some(PurgeReason::Superseded),
+                    Some(PurgeReason::TooOld),
+                    None,
+                    None
+                ]
+            );
+        }
+
+        #[test]
+        fn most_recent_backup_is_always_kept() {
+            let backups = vec![
+                sized(backup_at(2024, 1, 1, 12, 0, 0), 5000),
+                sized(backup_at(2024, 2, 1, 12, 0, 0), 5000),
+            ];
+            let plan = Strategy::Classic.plan_at(&backups, now());
+
+            let plan = limits(Some("1d"), Some("1kB")).restrict(plan, now());
+
+            assert_eq!(plan.retainable, [&backups[1]]);
+            assert_eq!(plan.reason(&backups[0]), Some(PurgeReason::TooOld));
+        }
+    }
+
+    mod pinned_backups {
+        use super::*;
+
+        fn pinned(mut backup: Backup) -> Backup {
+            backup.pinned = true;
+            backup
+        }
+
+        #[test]
+        fn pinned_backups_are_always_retained() {
+            let mut backups = generate_hourly_backups(24 * 3);
+            backups[0] = pinned(backups[0].clone());
+            backups[30] = pinned(backups[30].clone());
+            let now = backups.last().unwrap().creation_date;
+
+            for strategy in [
+                Strategy::most_recent(1),
+                Strategy::Classic,
+                Strategy::Tiered {
+                    tiers: "daily=1".parse().unwrap(),
+                },
+            ] {
+                let plan = strategy.plan_at(&backups, now);
+
+                assert!(plan.retainable.contains(&&backups[0]), "{strategy}");
+                assert!(plan.retainable.contains(&&backups[30]), "{strategy}");
+                assert!(plan.retainable.contains(&backups.last().unwrap()));
+                assert_eq!(plan.reason(&backups[0]), None);
+                assert!(matches!(plan.statuses[30].1, BackupStatus::Retainable));
+            }
+        }
+
+        #[test]
+        fn pinned_backups_are_kept_beyond_the_limits() {
+            let backups = vec![
+                pinned(backup_at(2024, 1, 1, 12, 0, 0)),
+                backup_at(2024, 5, 1, 12, 0, 0),
+                backup_at(2024, 6, 29, 12, 0, 0),
+            ];
+            let backups: Vec<_> = backups
+                .into_iter()
+                .map(|mut b| {
+                    b.filesize = 600;
+                    b
+                })
+                .collect();
+            let now = backup_at(2024, 6, 30, 12, 0, 0).creation_date;
+            let limits = Limits {
+                max_age: Some("30d".parse().unwrap()),
+                max_total_size: Some("1kB".parse().unwrap()),
+            };
+
+            let plan = limits.restrict(Strategy::most_recent(10).plan_at(&backups, now), now);
+
+            assert_eq!(plan.retainable, [&backups[0], &backups[2]]);
+            assert_eq!(plan.reason(&backups[1]), Some(PurgeReason::TooOld));
+        }
+    }
+
+    mod limits_parsing {
+        use super::*;
+
+        #[test]
+        fn ages_round_trip() {
+            for (source, hours) in [("36h", 36), ("30d", 720), ("8w", 1344), ("1y", 8760)] {
+                let age: Age = source.parse().unwrap();
+
+                assert_eq!(age.0, Duration::hours(hours));
+                assert_eq!(age.to_string(), source);
+            }
+            assert_eq!("48h".parse::<Age>().unwrap().to_string(), "2d");
+        }
+
+        #[test]
+        fn rejects_invalid_ages() {
+            for source in ["", "30", "d", "30 d", "30m", "-1d", "0d"] {
+                assert!(source.parse::<Age>().is_err(), "{source}");
+            }
+        }
+
+        #[test]
+        fn sizes_round_trip() {
+            for (source, bytes) in [
+                ("500MB", 500_000_000),
+                ("2GiB", 2 << 30),
+                ("1536B", 1536),
+                ("12kB", 12_000),
+            ] {
+                let size: Size = source.parse().unwrap();
+
+                assert_eq!(size.0, bytes);
+                assert_eq!(size.to_string(), source);
+            }
+            assert_eq!("1.5GB".parse::<Size>().unwrap().0, 1_500_000_000);
+            assert_eq!("1024".parse::<Size>().unwrap().to_string(), "1KiB");
+            assert_eq!("500mb".parse::<Size>().unwrap().0, 500_000_000);
+        }
+
+        #[test]
+        fn rejects_invalid_sizes() {
+            for source in ["", "MB", "500XB", "1.2.3GB", "-5MB"] {
+                assert!(source.parse::<Size>().is_err(), "{source}");
+            }
+        }
+
+        #[test]
+        fn limits_show_their_values() {
+            let limits = Limits {
+                max_age: Some("30d".parse().unwrap()),
+                max_total_size: Some("500MB".parse().unwrap()),
+            };
+
+            assert_eq!(limits.to_string(), "max age 30d, max total size 500MB");
+        }
+    }
+
     mod strategy_display {
         use super::*;
 
@@ -366,6 +1230,14 @@ mod tests {
             let strategy = Strategy::Classic;
             assert_eq!(format!("{strategy}"), "Classic");
         }
+
+        #[test]
+        fn tiered_shows_tiers() {
+            let strategy = Strategy::Tiered {
+                tiers: "daily=7,monthly=6".parse().unwrap(),
+            };
+            assert_eq!(format!("{strategy}"), "Tiered: daily=7,monthly=6");
+        }
     }
 
     mod strategy_constructors {
@@ -381,16 +1253,257 @@ mod tests {
         }
     }
 
-    // Note: The Classic strategy uses `Local::now()` internally, making it
-    // non-deterministic and difficult to unit test reliably. To properly test
-    // Classic, consider refactoring `plan()` to accept a `now` parameter,
-    // or create an integration test with a controlled time environment.
-    //
-    // The Classic strategy logic groups backups by:
-    // - Hour (last 24h)
-    // - Day (last 7 days, excluding last 24h)
-    // - Week (last 4 weeks, excluding last 7 days)
-    // - Month (last year, excluding last 4 weeks)
-    //
-    // Each group keeps only the most recent backup within that time window.
+    mod classic_strategy {
+        use super::*;
+        use std::collections::HashMap;
+
+        fn at(year: i32, month: u32, day: u32, hour: u32, min: u32) -> NaiveDateTime {
+            NaiveDate::from_ymd_opt(year, month, day)
+                .unwrap()
+                .and_hms_opt(hour, min, 0)
+                .unwrap()
+        }
+
+        fn backup(creation_date: NaiveDateTime) -> Backup {
+            Backup {
+                filepath: PathBuf::from(format!(
+                    "/backups/backup-{}.tar.zst",
+                    creation_date.format("%Y%m%dT%H%M%S")
+                )),
+                creation_date,
+                filesize: 0,
+                pinned: false,
+                label: None,
+            }
+        }
+
+        /// Backups every `step` from `from` to `to`, in local time without DST changes.
+        fn every(from: NaiveDateTime, to: NaiveDateTime, step: Duration) -> Vec<Backup> {
+            std::iter::successors(Some(from), |&date| Some(date + step))
+                .take_while(|&date| date <= to)
+                .map(backup)
+                .collect()
+        }
+
+        /// Backups every `step` from `from` to `to` in UTC, named after the local time of Paris
+        /// in 2024: the clocks go from 02:00 to 03:00 on March 31, then from 03:00 back to 02:00
+        /// on October 27. The backups are sorted by name, as in the catalog.
+        fn every_in_paris(from: NaiveDateTime, to: NaiveDateTime, step: Duration) -> Vec<Backup> {
+            let summer = at(2024, 3, 31, 1, 0)..at(2024, 10, 27, 1, 0);
+            let mut backups: Vec<Backup> =
+                std::iter::successors(Some(from), |&utc| Some(utc + step))
+                    .take_while(|&utc| utc <= to)
+                    .map(|utc| {
+                        let offset = if summer.contains(&utc) { 2 } else { 1 };
+                        backup(utc + Duration::hours(offset))
+                    })
+                    .collect();
+            backups.sort_by_key(|b| b.creation_date);
+            backups
+        }
+
+        /// Return the backups the classic strategy should keep at `now`, computed without
+        /// relying on the order of the backups: the latest of each period of each time window.
+        fn expected(backups: &[Backup], now: NaiveDateTime) -> HashSet<&Backup> {
+            let mut latest: HashMap<(usize, NaiveDateTime), &Backup> = HashMap::new();
+            for b in backups {
+                let age = now - b.creation_date;
+                let (window, period) = if age <= Duration::days(1) {
+                    (0, Period::Hourly)
+                } else if age <= Duration::days(7) {
+                    (1, Period::Daily)
+                } else if age <= Duration::weeks(4) {
+                    (2, Period::Weekly)
+                } else if age <= Duration::days(365) {
+                    (3, Period::Monthly)
+                } else {
+                    continue;
+                };
+                let key = (window, period.start(&b.creation_date));
+                if latest
+                    .get(&key)
+                    .is_none_or(|l| l.creation_date < b.creation_date)
+                {
+                    latest.insert(key, b);
+                }
+            }
+            latest.into_values().collect()
+        }
+
+        /// Check the plan at each of the `nows`, with the backups made until then.
+        fn check_plans(backups: &[Backup], nows: impl Iterator<Item = NaiveDateTime>) {
+            for now in nows {
+                let count = backups.partition_point(|b| b.creation_date <= now);
+                let backups = &backups[..count];
+
+                let plan = Strategy::Classic.plan_at(backups, now);
+
+                let retained: HashSet<&Backup> = plan.retainable.iter().copied().collect();
+                assert_eq!(retained.len(), plan.retainable.len(), "at {now}");
+                assert_eq!(retained, expected(backups, now), "at {now}");
+                assert_eq!(plan.retainable.len() + plan.purgeable.len(), backups.len());
+                assert!(plan.purgeable.iter().all(|b| !retained.contains(b)));
+                if let Some(latest) = backups.last() {
+                    assert!(retained.contains(latest), "latest backup purged at {now}");
+                }
+                for ((b, status), backup) in plan.statuses.iter().zip(backups) {
+                    assert_eq!(*b, backup);
+                    assert_eq!(
+                        matches!(status, BackupStatus::Retainable),
+                        retained.contains(b)
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn empty_catalog_produces_empty_plan() {
+            let plan = Strategy::Classic.plan_at(&[], at(2024, 6, 15, 12, 0));
+
+            assert!(plan.purgeable.is_empty());
+            assert!(plan.retainable.is_empty());
+            assert!(plan.statuses.is_empty());
+        }
+
+        #[test]
+        fn same_hour_on_two_days_are_separate_periods() {
+            let backups = vec![
+                backup(at(2024, 6, 14, 10, 40)),
+                backup(at(2024, 6, 15, 10, 10)),
+            ];
+
+            let plan = Strategy::Classic.plan_at(&backups, at(2024, 6, 15, 10, 30));
+
+            assert_eq!(plan.retainable.len(), 2);
+            assert!(plan.purgeable.is_empty());
+        }
+
+        #[test]
+        fn backup_made_exactly_a_day_ago_is_kept() {
+            let backups = vec![
+                backup(at(2024, 6, 14, 9, 0)),
+                backup(at(2024, 6, 14, 12, 0)),
+                backup(at(2024, 6, 15, 11, 0)),
+            ];
+
+            let plan = Strategy::Classic.plan_at(&backups, at(2024, 6, 15, 12, 0));
+
+            assert_eq!(plan.retainable.len(), 3);
+        }
+
+        #[test]
+        fn keeps_the_latest_backup_per_period() {
+            let backups = every(
+                at(2024, 5, 1, 0, 0),
+                at(2024, 6, 15, 12, 0),
+                Duration::minutes(20),
+            );
+
+            let plan = Strategy::Classic.plan_at(&backups, at(2024, 6, 15, 12, 0));
+
+            let dates: Vec<_> = plan
+                .retainable
+                .iter()
+                .map(|b| b.creation_date.format("%m-%d %H:%M").to_string())
+                .collect();
+            // Per month until 4 weeks ago, then per week until 7 days ago.
+            assert_eq!(
+                dates[..5],
+                [
+                    "05-18 11:40",
+                    "05-19 23:40",
+                    "05-26 23:40",
+                    "06-02 23:40",
+                    "06-08 11:40"
+                ]
+            );
+            // Per day until 24 hours ago.
+            assert_eq!(
+                dates[5..12],
+                [
+                    "06-08 23:40",
+                    "06-09 23:40",
+                    "06-10 23:40",
+                    "06-11 23:40",
+                    "06-12 23:40",
+                    "06-13 23:40",
+                    "06-14 11:40"
+                ]
+            );
+            // Per hour since then, 12:00 to 23:00 then 00:00 to 12:00.
+            assert_eq!(dates[12], "06-14 12:40");
+            assert_eq!(dates[35], "06-15 11:40");
+            assert_eq!(dates[36], "06-15 12:00");
+            assert_eq!(dates.len(), 37);
+        }
+
+        #[test]
+        fn plans_across_month_and_year_rollovers() {
+            let backups = every(
+                at(2023, 10, 1, 0, 0),
+                at(2025, 3, 15, 0, 0),
+                Duration::minutes(307),
+            );
+
+            let nows = [
+                at(2024, 1, 31, 23, 59),
+                at(2024, 2, 29, 0, 30),
+                at(2024, 3, 1, 0, 0),
+                at(2025, 1, 1, 0, 0),
+            ]
+            .into_iter()
+            .chain(
+                std::iter::successors(Some(at(2024, 12, 20, 3, 0)), |&now| {
+                    Some(now + Duration::minutes(733))
+                })
+                .take_while(|&now| now < at(2025, 1, 20, 0, 0)),
+            );
+            check_plans(&backups, nows);
+        }
+
+        #[test]
+        fn plans_across_dst_changes() {
+            // Frequent backups in the weeks around the changes, rare ones otherwise.
+            let step = Duration::minutes(25);
+            let backups: Vec<_> = [
+                every_in_paris(at(2024, 1, 1, 0, 0), at(2024, 3, 27, 0, 0), step * 12),
+                every_in_paris(at(2024, 3, 27, 0, 0), at(2024, 4, 10, 0, 0), step),
+                every_in_paris(at(2024, 4, 10, 0, 0), at(2024, 10, 23, 0, 0), step * 12),
+                every_in_paris(at(2024, 10, 23, 0, 0), at(2024, 11, 5, 0, 0), step),
+            ]
+            .concat();
+            assert!(backups.is_sorted_by_key(|b| b.creation_date));
+
+            let around = |utc: NaiveDateTime| {
+                std::iter::successors(Some(utc - Duration::days(2)), |&now| {
+                    Some(now + Duration::minutes(173))
+                })
+                .take_while(move |&now| now < utc + Duration::days(9))
+            };
+            check_plans(
+                &backups,
+                around(at(2024, 3, 31, 1, 0)).chain(around(at(2024, 10, 27, 1, 0))),
+            );
+        }
+
+        #[test]
+        fn repeated_hour_at_the_end_of_summer_time_is_one_period() {
+            // From 00:05 to 01:50 UTC, the local time goes through 02:xx twice.
+            let backups = every_in_paris(
+                at(2024, 10, 27, 0, 5),
+                at(2024, 10, 27, 1, 50),
+                Duration::minutes(25),
+            );
+
+            let plan = Strategy::Classic.plan_at(&backups, at(2024, 10, 27, 3, 0));
+
+            let dates: Vec<_> = backups
+                .iter()
+                .map(|b| b.creation_date.format("%H:%M").to_string())
+                .collect();
+            assert_eq!(dates, ["02:05", "02:20", "02:30", "02:45", "02:55"]);
+            // Backups are ordered by their local time, as their names.
+            assert_eq!(plan.retainable, [&backups[4]]);
+        }
+    }
 }
@@ -0,0 +1,255 @@
+//! Name patterns used to select sessions, windows or panes.
+
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Prefix marking a pattern as a regular expression instead of a glob.
+const REGEX_PREFIX: &str = "re:";
+
+/// A pattern matching a session, window or command name.
+///
+/// Patterns are globs by default and must match the whole name: `*` matches any sequence of
+/// characters, `?` matches a single character and `[...]` matches a character class (`[!...]`
+/// for a negated class). A name without any of these characters is therefore matched exactly.
+///
+/// A pattern prefixed with `re:`, such as `re:^proj-(a|b)$`, is a regular expression, which
+/// matches anywhere in the name unless anchored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NamePattern {
+    /// Pattern as provided by the user.
+    source: String,
+
+    /// Compiled pattern.
+    regex: Regex,
+}
+
+impl NamePattern {
+    /// Return `true` if `name` matches this pattern.
+    pub fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    /// Return `true` if `name` matches at least one of the `patterns`.
+    pub fn any_match(patterns: &[NamePattern], name: &str) -> bool {
+        patterns.iter().any(|pattern| pattern.is_match(name))
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = regex::Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let regex = match source.strip_prefix(REGEX_PREFIX) {
+            Some(expression) => Regex::new(expression)?,
+            None => Regex::new(&glob_to_regex(source))?,
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            regex,
+        })
+    }
+}
+
+/// Patterns are equal if they are written the same.
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse().map_err(|e| format!("invalid pattern `{s}`: {e}"))
+    }
+}
+
+impl From<NamePattern> for String {
+    fn from(name_pattern: NamePattern) -> Self {
+        name_pattern.to_string()
+    }
+}
+
+impl fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Include and exclude patterns selecting names.
+///
+/// A name is selected if it matches one of the `include` patterns, or if there are none, and if
+/// it matches none of the `exclude` patterns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NameFilter {
+    /// Patterns of the selected names, all names if empty.
+    pub include: Vec<NamePattern>,
+
+    /// Patterns of the names left out, even if included.
+    pub exclude: Vec<NamePattern>,
+}
+
+impl NameFilter {
+    /// Return `true` if `name` is selected by this filter.
+    pub fn is_match(&self, name: &str) -> bool {
+        (self.include.is_empty() || NamePattern::any_match(&self.include, name))
+            && !NamePattern::any_match(&self.exclude, name)
+    }
+
+    /// Return `true` if this filter selects all names.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// Translate a glob into an anchored regular expression.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut expression = String::from("^");
+
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '*' => expression.push_str(".*"),
+            '?' => expression.push('.'),
+            '[' => match chars[index + 1..].iter().position(|&c| c == ']') {
+                Some(len) => {
+                    let class: String = chars[index + 1..index + 1 + len].iter().collect();
+                    let class = match class.strip_prefix('!') {
+                        Some(negated) => format!("^{negated}"),
+                        None => class,
+                    };
+                    expression.push('[');
+                    expression.push_str(&class.replace('\\', r"\\").replace('[', r"\["));
+                    expression.push(']');
+                    index += len + 1;
+                }
+                // An unterminated class is matched literally.
+                None => expression.push_str(r"\["),
+            },
+            c => expression.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        index += 1;
+    }
+
+    expression.push('$');
+    expression
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        NamePattern::from_str(pattern).unwrap().is_match(name)
+    }
+
+    #[test]
+    fn plain_name_matches_exactly() {
+        assert!(matches("work", "work"));
+        assert!(!matches("work", "work-2"));
+        assert!(!matches("work", "homework"));
+    }
+
+    #[test]
+    fn star_matches_any_sequence() {
+        assert!(matches("proj-*", "proj-"));
+        assert!(matches("proj-*", "proj-backend"));
+        assert!(!matches("proj-*", "my-proj-backend"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("w?rk", "work"));
+        assert!(!matches("w?rk", "wrk"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches("env-[ab]", "env-a"));
+        assert!(!matches("env-[ab]", "env-c"));
+        assert!(matches("env-[!ab]", "env-c"));
+        assert!(!matches("env-[!ab]", "env-a"));
+    }
+
+    #[test]
+    fn unterminated_class_is_literal() {
+        assert!(matches("a[b", "a[b"));
+        assert!(!matches("a[b", "ab"));
+    }
+
+    #[test]
+    fn regex_metacharacters_are_escaped_in_globs() {
+        assert!(matches("a.b+(c)", "a.b+(c)"));
+        assert!(!matches("a.b", "axb"));
+    }
+
+    #[test]
+    fn regex_prefix_is_unanchored() {
+        assert!(matches("re:k8s", "infra-k8s-prod"));
+        assert!(matches("re:^(api|web)$", "web"));
+        assert!(!matches("re:^(api|web)$", "webapp"));
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(NamePattern::from_str("re:(unclosed").is_err());
+    }
+
+    #[test]
+    fn any_match_over_several_patterns() {
+        let patterns: Vec<NamePattern> = ["api", "web-*"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+
+        assert!(NamePattern::any_match(&patterns, "web-1"));
+        assert!(!NamePattern::any_match(&patterns, "db"));
+        assert!(!NamePattern::any_match(&[], "db"));
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> NameFilter {
+        let patterns = |sources: &[&str]| sources.iter().map(|p| p.parse().unwrap()).collect();
+        NameFilter {
+            include: patterns(include),
+            exclude: patterns(exclude),
+        }
+    }
+
+    #[test]
+    fn empty_filter_selects_every_name() {
+        let filter = NameFilter::default();
+
+        assert!(filter.is_empty());
+        assert!(filter.is_match("work"));
+    }
+
+    #[test]
+    fn filter_selects_included_names() {
+        let filter = filter(&["proj-*"], &[]);
+
+        assert!(filter.is_match("proj-api"));
+        assert!(!filter.is_match("scratch"));
+    }
+
+    #[test]
+    fn exclusion_takes_precedence_over_inclusion() {
+        let filter = filter(&["proj-*"], &["*-old"]);
+
+        assert!(filter.is_match("proj-api"));
+        assert!(!filter.is_match("proj-api-old"));
+        assert!(!filter.is_empty());
+    }
+
+    #[test]
+    fn displays_the_source_pattern() {
+        let pattern = NamePattern::from_str("re:^a").unwrap();
+        assert_eq!(pattern.to_string(), "re:^a");
+    }
+}
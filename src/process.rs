@@ -0,0 +1,151 @@
+//! Inspect the processes running in Tmux panes.
+//!
+//! Tmux only reports the name of the foreground program of a pane, such as `nvim`. The full
+//! command line is read from the process of the pane instead, through `/proc` on Linux.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use smol::process::Command;
+
+use crate::{Result, error::Error, tmux::pane_id::PaneId};
+
+/// Query Tmux and return the process id of the initial process of each pane, usually a shell.
+pub async fn pane_pids() -> Result<HashMap<PaneId, u32>> {
+    let output = Command::new("tmux")
+        .args(["list-panes", "-a", "-F", "#{pane_id}\t#{pane_pid}"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::TmuxCommand(format!(
+            "could not list the processes of panes: {}",
+            stderr.trim()
+        )));
+    }
+
+    Ok(parse_pane_pids(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the lines `<pane_id>\t<pane_pid>` printed by Tmux, ignoring malformed lines.
+fn parse_pane_pids(output: &str) -> HashMap<PaneId, u32> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (pane_id, pid) = line.split_once('\t')?;
+            Some((PaneId::from_str(pane_id).ok()?, pid.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Return the command line of the foreground process of the pane started with `pane_pid`.
+///
+/// The foreground process group of the pane terminal is read from the stat file of `pane_pid`,
+/// and the command line from the cmdline file of its leader. This is the pane process itself
+/// when no program is running in the shell.
+///
+/// Return `None` if the process exited meanwhile, or if this is not supported on this platform.
+#[cfg(target_os = "linux")]
+pub fn foreground_argv(pane_pid: u32) -> Option<Vec<String>> {
+    let stat = std::fs::read_to_string(format!("/proc/{pane_pid}/stat")).ok()?;
+    let pid = parse_tpgid(&stat).unwrap_or(pane_pid);
+
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let argv = parse_cmdline(&cmdline);
+    if argv.is_empty() { None } else { Some(argv) }
+}
+
+/// Return the command line of the foreground process of the pane started with `pane_pid`.
+///
+/// Return `None` if the process exited meanwhile, or if this is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn foreground_argv(_pane_pid: u32) -> Option<Vec<String>> {
+    None
+}
+
+/// Return the foreground process group of the terminal, the 8th field of a `/proc/<pid>/stat`
+/// file, or `None` if the process has no terminal.
+///
+/// The 2nd field is the program name in parentheses, which may contain spaces and parentheses,
+/// so fields are counted after the last closing parenthesis.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_tpgid(stat: &str) -> Option<u32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    let tpgid: i64 = fields.split_whitespace().nth(5)?.parse().ok()?;
+    u32::try_from(tpgid).ok().filter(|&pid| pid > 0)
+}
+
+/// Split the content of a `/proc/<pid>/cmdline` file into arguments.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cmdline(cmdline: &[u8]) -> Vec<String> {
+    cmdline
+        .strip_suffix(b"\0")
+        .unwrap_or(cmdline)
+        .split(|&byte| byte == 0)
+        .filter(|_| !cmdline.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pane_pids() {
+        let pids = parse_pane_pids("%0\t4242\n%12\t4250\nnot a pane\n%3\tnot a pid\n");
+
+        assert_eq!(pids.len(), 2);
+        assert_eq!(pids[&PaneId::from_str("%0").unwrap()], 4242);
+        assert_eq!(pids[&PaneId::from_str("%12").unwrap()], 4250);
+    }
+
+    #[test]
+    fn reads_the_foreground_process_group() {
+        let stat = "4242 (zsh) S 4200 4242 4242 34817 5120 4194304 1834 0 0 0";
+
+        assert_eq!(parse_tpgid(stat), Some(5120));
+    }
+
+    #[test]
+    fn program_names_may_contain_parentheses_and_spaces() {
+        let stat = "4242 (my (odd) prog) S 4200 4242 4242 34817 5120 4194304";
+
+        assert_eq!(parse_tpgid(stat), Some(5120));
+    }
+
+    #[test]
+    fn processes_without_terminal_have_no_foreground_group() {
+        let stat = "4242 (daemon) S 1 4242 4242 0 -1 4194368";
+
+        assert_eq!(parse_tpgid(stat), None);
+    }
+
+    #[test]
+    fn splits_the_command_line_into_arguments() {
+        let argv = parse_cmdline(b"nvim\0src/lib.rs\0+12\0");
+
+        assert_eq!(argv, vec!["nvim", "src/lib.rs", "+12"]);
+    }
+
+    #[test]
+    fn keeps_empty_arguments() {
+        let argv = parse_cmdline(b"grep\0\0file\0");
+
+        assert_eq!(argv, vec!["grep", "", "file"]);
+    }
+
+    #[test]
+    fn kernel_threads_have_no_command_line() {
+        assert!(parse_cmdline(b"").is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_the_command_line_of_a_running_process() {
+        let argv = foreground_argv(std::process::id()).unwrap();
+
+        assert!(!argv.is_empty());
+    }
+}